@@ -1,6 +1,37 @@
-use crate::lexer::Token;
+use crate::{error, lexer::Token, source};
 use thiserror::Error;
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    I32,
+    I64,
+    F32,
+    F64,
+    /// A Unicode scalar value
+    Char,
+    /// An unsigned byte
+    U8,
+    /// `()`, the type of functions without a return value
+    Unit,
+    Array(Box<Type>, u64),
+    Pointer(Box<Type>),
+    /// `(i64, f64)`, `()` is `Unit` rather than an empty tuple
+    Tuple(Vec<Type>),
+    /// A user defined struct or enum
+    Named(String),
+    /// `Pair<i64>`, a generic struct applied to type arguments. Monomorphization replaces it
+    /// with the `Named` instance
+    Generic(String, Box<[Type]>),
+    /// `dyn Shape`, some type implementing the trait, only usable behind a pointer
+    Dyn(String),
+    /// `fn(i64) -> u8`, a function or closure taking and returning those types
+    Function(Box<[Type]>, Box<Type>),
+    /// `[]i64`, a pointer to some number of elements along with that number
+    Slice(Box<Type>),
+    /// UTF-8 text, laid out like `[]u8`
+    Str,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
     Number {
@@ -8,6 +39,19 @@ pub enum Expression {
         here: usize,
         len: usize,
     },
+    Float {
+        value: f64,
+        here: usize,
+        len: usize,
+    },
+    Char {
+        value: char,
+        here: usize,
+    },
+    Str {
+        value: String,
+        here: usize,
+    },
     Variable {
         name: String,
         here: usize,
@@ -17,108 +61,1099 @@ pub enum Expression {
         op: Token,
         right: Box<Expression>,
     },
+    Cast {
+        value: Box<Expression>,
+        to: Type,
+        here: usize,
+    },
+    Array {
+        elements: Vec<Expression>,
+        here: usize,
+    },
+    Tuple {
+        elements: Vec<Expression>,
+        here: usize,
+    },
+    Index {
+        array: Box<Expression>,
+        index: Box<Expression>,
+        here: usize,
+    },
+    /// `value[start..end]`, a slice of an array, slice, string or pointer. Either bound may be
+    /// left out except for a pointer's end, `..=` includes `end`
+    Slice {
+        value: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        inclusive: bool,
+        here: usize,
+    },
+    StructLiteral {
+        name: String,
+        fields: Vec<FieldInit>,
+        here: usize,
+    },
+    /// `value.field`, tuple fields are named by their index
+    Field {
+        value: Box<Expression>,
+        field: String,
+        here: usize,
+    },
+    Variant {
+        ty: String,
+        variant: String,
+        args: Vec<Expression>,
+        here: usize,
+    },
+    Match {
+        value: Box<Expression>,
+        arms: Vec<MatchArm>,
+        here: usize,
+    },
+    Null {
+        here: usize,
+    },
+    AddressOf {
+        value: Box<Expression>,
+        here: usize,
+    },
+    Deref {
+        value: Box<Expression>,
+        here: usize,
+    },
+    /// `name(args)`, where `name` is a function or a variable holding a function value
+    Call {
+        name: String,
+        args: Vec<Expression>,
+        here: usize,
+    },
+    /// `|a, b: i64| a + b`, a function value holding copies of the variables it uses. A block
+    /// body `|x| { ... }` returns `()` unless a return type is given, `|x| -> i64 { ... }`
+    Closure {
+        params: Vec<ClosureParam>,
+        ret: Option<Type>,
+        body: Vec<Statement>,
+        here: usize,
+    },
+    /// `value.method(args)`, `here` is the method name
+    MethodCall {
+        value: Box<Expression>,
+        method: String,
+        args: Vec<Expression>,
+        here: usize,
+    },
+    /// `value?`, the payload of an `Option::Some` or `Result::Ok`. A `None` or an `Err` is
+    /// returned from the enclosing function instead, `here` is the `?`
+    Try {
+        value: Box<Expression>,
+        here: usize,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldInit {
+    pub name: String,
+    pub value: Expression,
+    pub here: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldDecl {
+    pub name: String,
+    pub ty: Type,
+    pub here: usize,
+}
+
+/// A parameter of a closure, its type is inferred when not given
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClosureParam {
+    pub name: String,
+    pub ty: Option<Type>,
+    pub here: usize,
+}
+
+/// A type parameter, `T: Show + Eq` only accepts types implementing both traits
+#[derive(Debug, PartialEq, Clone)]
+pub struct Generic {
+    pub name: String,
+    pub bounds: Vec<String>,
+    pub here: usize,
+}
+
+/// The signature of a method a trait requires
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraitMethod {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub ret: Type,
+    pub here: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Param {
+    pub name: String,
+    pub ty: Type,
+    pub here: usize,
+}
+
+/// A variable defined by destructuring
+#[derive(Debug, PartialEq, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub mutable: bool,
+    pub here: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct VariantDecl {
+    pub name: String,
+    pub payload: Vec<Type>,
+    pub here: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub value: Expression,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Wildcard {
+        here: usize,
+    },
+    Number {
+        value: u64,
+        here: usize,
+    },
+    Char {
+        value: char,
+        here: usize,
+    },
+    Variant {
+        ty: String,
+        variant: String,
+        bindings: Vec<(String, usize)>,
+        here: usize,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
     Return(Expression),
-    DefineVar { name: String, value: Expression },
+    /// `var name = value;`, `let name = value;` can't be assigned to afterwards
+    DefineVar {
+        name: String,
+        mutable: bool,
+        ty: Option<Type>,
+        value: Expression,
+        here: usize,
+    },
+    /// `var (a, b) = value;`
+    DefineTuple {
+        names: Vec<Binding>,
+        ty: Option<Type>,
+        value: Expression,
+    },
+    Assign {
+        target: Expression,
+        value: Expression,
+    },
+    Struct {
+        name: String,
+        generics: Vec<Generic>,
+        fields: Vec<FieldDecl>,
+        here: usize,
+    },
+    Enum {
+        name: String,
+        generics: Vec<Generic>,
+        variants: Vec<VariantDecl>,
+        here: usize,
+    },
+    Function {
+        name: String,
+        generics: Vec<Generic>,
+        params: Vec<Param>,
+        ret: Type,
+        body: Vec<Statement>,
+        here: usize,
+    },
+    Trait {
+        name: String,
+        methods: Vec<TraitMethod>,
+        here: usize,
+    },
+    /// `impl<T> Trait for Type<T> { methods }`, or `impl Type { methods }` without a trait.
+    /// The methods are `Function`s, named `Type::method` once monomorphization lowers them
+    Impl {
+        generics: Vec<Generic>,
+        trait_name: Option<String>,
+        ty: Type,
+        methods: Vec<Statement>,
+        here: usize,
+    },
+    /// A function defined outside of the program, usually in libc
+    Extern {
+        name: String,
+        params: Vec<Param>,
+        ret: Type,
+        here: usize,
+    },
+    /// A global evaluated at compile time
+    Const {
+        name: String,
+        ty: Type,
+        value: Expression,
+        here: usize,
+    },
+    /// A mutable global, its initializer has to be constant as well
+    Static {
+        name: String,
+        ty: Type,
+        value: Expression,
+        here: usize,
+    },
+    Expression(Expression),
+    /// `for var in start..end { body }`, `..=` includes `end`
+    For {
+        var: String,
+        start: Expression,
+        end: Expression,
+        inclusive: bool,
+        body: Vec<Statement>,
+        here: usize,
+    },
+    /// `mod name;`, replaced by the items of `name.jsl` when modules are loaded
+    Mod {
+        name: String,
+        here: usize,
+    },
+    /// `defer stmt;` runs `stmt` when the enclosing block exits, the latest deferred first.
+    /// Blocks exit at their end or through `return`, `?`, `break` and `continue`
+    Defer {
+        stmt: Box<Statement>,
+        here: usize,
+    },
+    /// `break;` leaves the innermost loop
+    Break {
+        here: usize,
+    },
+    /// `continue;` goes on with the next iteration of the innermost loop
+    Continue {
+        here: usize,
+    },
 }
 
 #[derive(Debug, Error, PartialEq)]
 pub enum ASTError {
+    /// `here` is the last token, the input ends right after it
     #[error("Unexpected EOF found")]
-    UnexpectedEOF,
+    UnexpectedEOF { here: usize },
     #[error("Unexpected token. Got: {got}, expected: {expected}")]
-    UnexpectedToken { got: Token, expected: Token },
+    UnexpectedToken {
+        here: usize,
+        got: Token,
+        expected: Token,
+    },
+}
+
+impl ASTError {
+    pub fn here(&self) -> usize {
+        match self {
+            Self::UnexpectedEOF { here } | Self::UnexpectedToken { here, .. } => *here,
+        }
+    }
+
+    /// Attaches the file, line and column of the error
+    pub fn locate(self, src: &source::SourceMap) -> SyntaxError {
+        let (file, line_number, column_number) = src.locate(self.here());
+        SyntaxError {
+            file,
+            line_number,
+            column_number,
+            error: self,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub struct SyntaxError {
+    pub file: Option<String>,
+    pub line_number: usize,
+    pub column_number: usize,
+    pub error: ASTError,
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error::display_error(
+            f,
+            self.file.as_deref(),
+            (self.line_number, self.column_number),
+            &self.error.to_string(),
+        )
+    }
+}
+
+impl Expression {
+    /// Offset of the token the expression should be reported at
+    pub fn here(&self) -> usize {
+        match self {
+            Self::Number { here, .. }
+            | Self::Float { here, .. }
+            | Self::Char { here, .. }
+            | Self::Str { here, .. }
+            | Self::Variable { here, .. }
+            | Self::Cast { here, .. }
+            | Self::Array { here, .. }
+            | Self::Tuple { here, .. }
+            | Self::Index { here, .. }
+            | Self::Slice { here, .. }
+            | Self::StructLiteral { here, .. }
+            | Self::Field { here, .. }
+            | Self::Variant { here, .. }
+            | Self::Match { here, .. }
+            | Self::Null { here }
+            | Self::AddressOf { here, .. }
+            | Self::Deref { here, .. }
+            | Self::Call { here, .. }
+            | Self::Closure { here, .. }
+            | Self::MethodCall { here, .. }
+            | Self::Try { here, .. } => *here,
+            Self::Binary { op, .. } => op.here(),
+        }
+    }
+}
+
+impl Type {
+    /// The name of the function implementing `method` for this type, `Pair::first` for any
+    /// `Pair<T>`. Only named and primitive types have methods
+    pub fn method_path(&self, method: &str) -> Option<String> {
+        match self {
+            Self::Named(name) | Self::Generic(name, _) => Some(format!("{name}::{method}")),
+            Self::I32 | Self::I64 | Self::F32 | Self::F64 | Self::Char | Self::U8 | Self::Str => {
+                Some(format!("{self}::{method}"))
+            }
+            _ => None,
+        }
+    }
+
+    /// The variants `?` unwraps and returns early with for an instance of the prelude's
+    /// `Option` or `Result`, `Some` and `None` for `Option<i64>`
+    pub fn try_variants(&self) -> Option<(&'static str, &'static str)> {
+        let Self::Named(name) = self else {
+            return None;
+        };
+        match name.split_once('<')?.0 {
+            "Option" => Some(("Some", "None")),
+            "Result" => Some(("Ok", "Err")),
+            _ => None,
+        }
+    }
+
+    /// The type with the named types in `subst` replaced, `Self` or type parameters
+    pub fn substitute(&self, subst: &std::collections::HashMap<String, Type>) -> Type {
+        match self {
+            Self::Named(name) => subst.get(name).cloned().unwrap_or(self.clone()),
+            Self::Array(elem, len) => Self::Array(Box::new(elem.substitute(subst)), *len),
+            Self::Pointer(inner) => Self::Pointer(Box::new(inner.substitute(subst))),
+            Self::Slice(elem) => Self::Slice(Box::new(elem.substitute(subst))),
+            Self::Tuple(elems) => Self::Tuple(elems.iter().map(|e| e.substitute(subst)).collect()),
+            Self::Generic(name, args) => Self::Generic(
+                name.to_string(),
+                args.iter().map(|a| a.substitute(subst)).collect(),
+            ),
+            Self::Function(params, ret) => Self::Function(
+                params.iter().map(|p| p.substitute(subst)).collect(),
+                Box::new(ret.substitute(subst)),
+            ),
+            ty => ty.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::I32 => write!(f, "i32"),
+            Self::I64 => write!(f, "i64"),
+            Self::F32 => write!(f, "f32"),
+            Self::F64 => write!(f, "f64"),
+            Self::Char => write!(f, "char"),
+            Self::U8 => write!(f, "u8"),
+            Self::Unit => write!(f, "()"),
+            Self::Array(elem, len) => write!(f, "[{elem}; {len}]"),
+            Self::Pointer(inner) => write!(f, "*{inner}"),
+            Self::Slice(elem) => write!(f, "[]{elem}"),
+            Self::Str => write!(f, "str"),
+            Self::Tuple(elems) if elems.len() == 1 => write!(f, "({},)", elems[0]),
+            Self::Tuple(elems) => {
+                let elems: Vec<_> = elems.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Self::Named(name) => write!(f, "{name}"),
+            Self::Generic(name, args) => {
+                let args: Vec<_> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{name}<{}>", args.join(", "))
+            }
+            Self::Dyn(name) => write!(f, "dyn {name}"),
+            Self::Function(params, ret) => {
+                let params: Vec<_> = params.iter().map(|p| p.to_string()).collect();
+                match &**ret {
+                    Self::Unit => write!(f, "fn({})", params.join(", ")),
+                    ret => write!(f, "fn({}) -> {ret}", params.join(", ")),
+                }
+            }
+        }
+    }
+}
+
+/// Every statement and expression in `body`, nested ones included, each before the ones inside
+/// it. Closure bodies are left out since they run on their own, callers wanting them flatten
+/// those separately
+pub fn flatten(body: &[Statement]) -> (Vec<&Statement>, Vec<&Expression>) {
+    let mut statements = vec![];
+    let mut expressions = vec![];
+    for st in body {
+        flatten_statement(st, &mut statements, &mut expressions);
+    }
+    (statements, expressions)
+}
+
+fn flatten_statement<'a>(
+    st: &'a Statement,
+    statements: &mut Vec<&'a Statement>,
+    expressions: &mut Vec<&'a Expression>,
+) {
+    statements.push(st);
+    match st {
+        Statement::Return(value)
+        | Statement::Expression(value)
+        | Statement::DefineVar { value, .. }
+        | Statement::DefineTuple { value, .. } => flatten_expression(value, expressions),
+        Statement::Assign { target, value } => {
+            flatten_expression(target, expressions);
+            flatten_expression(value, expressions);
+        }
+        Statement::For {
+            start, end, body, ..
+        } => {
+            flatten_expression(start, expressions);
+            flatten_expression(end, expressions);
+            for st in body {
+                flatten_statement(st, statements, expressions);
+            }
+        }
+        Statement::Defer { stmt, .. } => flatten_statement(stmt, statements, expressions),
+        _ => {}
+    }
+}
+
+fn flatten_expression<'a>(expr: &'a Expression, expressions: &mut Vec<&'a Expression>) {
+    expressions.push(expr);
+    match expr {
+        Expression::Binary { left, right, .. } => {
+            flatten_expression(left, expressions);
+            flatten_expression(right, expressions);
+        }
+        Expression::Index { array, index, .. } => {
+            flatten_expression(array, expressions);
+            flatten_expression(index, expressions);
+        }
+        Expression::Slice {
+            value, start, end, ..
+        } => {
+            flatten_expression(value, expressions);
+            for bound in start.iter().chain(end) {
+                flatten_expression(bound, expressions);
+            }
+        }
+        Expression::Cast { value, .. }
+        | Expression::Field { value, .. }
+        | Expression::AddressOf { value, .. }
+        | Expression::Deref { value, .. }
+        | Expression::Try { value, .. } => flatten_expression(value, expressions),
+        Expression::Call { args: elements, .. }
+        | Expression::Array { elements, .. }
+        | Expression::Tuple { elements, .. }
+        | Expression::Variant { args: elements, .. } => {
+            for e in elements {
+                flatten_expression(e, expressions);
+            }
+        }
+        Expression::StructLiteral { fields, .. } => {
+            for f in fields {
+                flatten_expression(&f.value, expressions);
+            }
+        }
+        Expression::Match { value, arms, .. } => {
+            flatten_expression(value, expressions);
+            for arm in arms {
+                flatten_expression(&arm.value, expressions);
+            }
+        }
+        Expression::MethodCall { value, args, .. } => {
+            flatten_expression(value, expressions);
+            for arg in args {
+                flatten_expression(arg, expressions);
+            }
+        }
+        Expression::Variable { .. }
+        | Expression::Closure { .. }
+        | Expression::Number { .. }
+        | Expression::Float { .. }
+        | Expression::Char { .. }
+        | Expression::Str { .. }
+        | Expression::Null { .. } => {}
+    }
+}
+
+pub fn parse(tokens: &[Token]) -> Result<Vec<Statement>, ASTError> {
+    parse_items(tokens, false)
+}
+
+/// Running out of tokens. Only `parse_items` sees where the input ends, it fills in `here`
+fn eof() -> ASTError {
+    ASTError::UnexpectedEOF { here: 0 }
+}
+
+fn parse_items(tokens: &[Token], items_only: bool) -> Result<Vec<Statement>, ASTError> {
+    let last = tokens.last().map_or(0, |t| t.here());
+    parse_item_list(tokens, items_only).map_err(|e| match e {
+        ASTError::UnexpectedEOF { .. } => ASTError::UnexpectedEOF { here: last },
+        e => e,
+    })
 }
 
-pub fn parse(mut tokens: &[Token]) -> Result<Vec<Statement>, ASTError> {
+fn parse_item_list(mut tokens: &[Token], items_only: bool) -> Result<Vec<Statement>, ASTError> {
     let mut stmts = vec![];
     while !tokens.is_empty() {
-        match tokens.first() {
+        let (rest, stmt) = match tokens.first() {
             None => {
-                return Err(ASTError::UnexpectedEOF);
-            }
-            Some(Token::Var { .. } | Token::Return { .. }) => {
-                let (rest, stmt) = parse_statement(tokens)?;
-                stmts.push(stmt);
-                tokens = rest;
+                return Err(eof());
             }
-            Some(t) => {
+            Some(Token::Struct { .. }) => parse_struct(tokens)?,
+            Some(Token::Enum { .. }) => parse_enum(tokens)?,
+            Some(Token::Fn { .. }) => parse_function(tokens)?,
+            Some(Token::Extern { .. }) => parse_extern(tokens)?,
+            Some(Token::Const { .. } | Token::Static { .. }) => parse_global(tokens)?,
+            Some(Token::Mod { .. }) => parse_mod(tokens)?,
+            Some(Token::Trait { .. }) => parse_trait(tokens)?,
+            Some(Token::Impl { .. }) => parse_impl(tokens)?,
+            Some(t) if items_only => {
                 return Err(ASTError::UnexpectedToken {
+                    here: t.here(),
                     got: t.clone(),
-                    expected: Token::Return { here: 0 },
+                    expected: Token::Fn { here: 0 },
                 });
             }
-        }
+            Some(_) => parse_statement(tokens)?,
+        };
+        stmts.push(stmt);
+        tokens = rest;
     }
     Ok(stmts)
 }
 
-fn parse_statement(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
-    let (rest, stmt) = match tokens.split_first() {
-        Some((Token::Return { .. }, rest)) => {
-            let (rest, expr) = parse_expr(rest)?;
-            (rest, Statement::Return(expr))
-        }
-        Some((Token::Var { here }, rest)) => {
-            let (rest, name) = match rest.split_first() {
-                Some((Token::Ident { value, .. }, rest)) => (rest, value),
-                Some((t, ..)) => {
-                    return Err(ASTError::UnexpectedToken {
-                        got: t.clone(),
-                        expected: Token::Ident {
-                            value: "any".to_string(),
-                            here: *here,
-                        },
-                    });
-                }
-                None => return Err(ASTError::UnexpectedEOF),
-            };
-            let rest = match rest.split_first() {
-                Some((Token::Equal { .. }, rest)) => rest,
-                Some((t, ..)) => {
-                    return Err(ASTError::UnexpectedToken {
-                        got: t.clone(),
-                        expected: Token::Semicolon { here: *here },
-                    });
-                }
-                None => return Err(ASTError::UnexpectedEOF),
-            };
-            let (rest, expr) = parse_expr(rest)?;
-            (
-                rest,
-                Statement::DefineVar {
-                    name: name.to_string(),
-                    value: expr,
-                },
-            )
-        }
-        None => {
-            return Err(ASTError::UnexpectedEOF);
-        }
-        _ => unimplemented!(),
+/// Parses a module file, which can only contain items
+pub fn parse_module(tokens: &[Token]) -> Result<Vec<Statement>, ASTError> {
+    parse_items(tokens, true)
+}
+
+fn parse_mod(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::Mod { here: 0 })?;
+    let (tokens, (name, here)) = parse_ident(tokens)?;
+    let tokens = expect(tokens, Token::Semicolon { here: 0 })?;
+    Ok((tokens, Statement::Mod { name, here }))
+}
+
+/// The segments of `a::b::c` and where each of them starts
+type Path = Vec<(String, usize)>;
+
+fn parse_path(tokens: &[Token]) -> Result<(&[Token], Path), ASTError> {
+    let (mut tokens, first) = parse_ident(tokens)?;
+    let mut segments = vec![first];
+    while let Some((Token::ColonColon { .. }, rest)) = tokens.split_first() {
+        let (rest, segment) = parse_ident(rest)?;
+        segments.push(segment);
+        tokens = rest;
+    }
+    Ok((tokens, segments))
+}
+
+/// `a::b::c` as a single name
+fn join_path(segments: &[(String, usize)]) -> String {
+    segments
+        .iter()
+        .map(|(s, _)| s.as_str())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Parses `var x = value`, `let x = value` or `let mut x = value`, with an optional type
+/// after the name. `(a, mut b)` in place of the name destructures a tuple
+fn parse_define(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    // Everything `var` defines can be assigned to
+    let (var, tokens) = match tokens.split_first() {
+        Some((Token::Var { .. }, rest)) => (true, rest),
+        _ => (false, expect(tokens, Token::Let { here: 0 })?),
     };
-    match rest.split_first() {
-        Some((Token::Semicolon { .. }, rest)) => Ok((rest, stmt)),
-        Some((t, ..)) => Err(ASTError::UnexpectedToken {
-            got: t.clone(),
-            expected: Token::Semicolon { here: 0 },
-        }),
-        None => Err(ASTError::UnexpectedEOF),
+    if let Some((Token::OpenParen { .. }, rest)) = tokens.split_first() {
+        let (rest, names) = parse_list(rest, Token::CloseParen { here: 0 }, |tokens| {
+            parse_binding(tokens, var)
+        })?;
+        let (rest, ty, value) = parse_initializer(rest)?;
+        return Ok((rest, Statement::DefineTuple { names, ty, value }));
     }
+    let (
+        tokens,
+        Binding {
+            name,
+            mutable,
+            here,
+        },
+    ) = parse_binding(tokens, var)?;
+    let (tokens, ty, value) = parse_initializer(tokens)?;
+    Ok((
+        tokens,
+        Statement::DefineVar {
+            name,
+            mutable,
+            ty,
+            value,
+            here,
+        },
+    ))
 }
 
-fn parse_expr(mut tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
-    let (ts, mut left) = parse_mult(tokens)?;
-    tokens = ts;
+/// Parses the optional `: type` and the `= value` of a variable definition
+fn parse_initializer(tokens: &[Token]) -> Result<(&[Token], Option<Type>, Expression), ASTError> {
+    let (tokens, ty) = match tokens.split_first() {
+        Some((Token::Colon { .. }, rest)) => {
+            let (rest, ty) = parse_type(rest)?;
+            (rest, Some(ty))
+        }
+        _ => (tokens, None),
+    };
+    let tokens = expect(tokens, Token::Equal { here: 0 })?;
+    let (tokens, value) = parse_expr(tokens)?;
+    Ok((tokens, ty, value))
+}
 
+/// Parses `name` or `mut name` after `let`, after `var` the binding is always mutable
+fn parse_binding(tokens: &[Token], var: bool) -> Result<(&[Token], Binding), ASTError> {
+    let (tokens, mutable) = match tokens.split_first() {
+        Some((Token::Mut { .. }, rest)) if !var => (rest, true),
+        _ => (tokens, var),
+    };
+    let (tokens, (name, here)) = parse_ident(tokens)?;
+    Ok((
+        tokens,
+        Binding {
+            name,
+            mutable,
+            here,
+        },
+    ))
+}
+
+fn parse_block(tokens: &[Token]) -> Result<(&[Token], Vec<Statement>), ASTError> {
+    let mut tokens = expect(tokens, Token::OpenCurly { here: 0 })?;
+    let mut stmts = vec![];
     loop {
-        let op = match tokens.first() {
-            Some(Token::Plus { here }) => Token::Plus { here: *here },
-            Some(Token::Minus { here }) => Token::Minus { here: *here },
-            _ => return Ok((tokens, left)),
+        match tokens.first() {
+            Some(Token::CloseCurly { .. }) => return Ok((&tokens[1..], stmts)),
+            None => return Err(eof()),
+            Some(_) => {
+                let (rest, stmt) = parse_statement(tokens)?;
+                stmts.push(stmt);
+                tokens = rest;
+            }
+        }
+    }
+}
+
+struct Signature {
+    name: String,
+    generics: Vec<Generic>,
+    params: Vec<Param>,
+    ret: Type,
+    here: usize,
+}
+
+/// Parses `name<generics>(params) -> ret`, a missing return type means `()`
+fn parse_signature(tokens: &[Token], generic: bool) -> Result<(&[Token], Signature), ASTError> {
+    let (tokens, (name, here)) = parse_ident(tokens)?;
+    let (tokens, generics) = if generic {
+        parse_generics(tokens)?
+    } else {
+        (tokens, vec![])
+    };
+    let tokens = expect(tokens, Token::OpenParen { here: 0 })?;
+    let (tokens, params) = parse_list(tokens, Token::CloseParen { here: 0 }, |tokens| {
+        let (tokens, (name, here)) = parse_ident(tokens)?;
+        // A method's `self` is a `Self` unless it says otherwise
+        if name == "self" && !matches!(tokens.first(), Some(Token::Colon { .. })) {
+            let ty = Type::Named(String::from("Self"));
+            return Ok((tokens, Param { name, ty, here }));
+        }
+        let tokens = expect(tokens, Token::Colon { here: 0 })?;
+        let (tokens, ty) = parse_type(tokens)?;
+        Ok((tokens, Param { name, ty, here }))
+    })?;
+    let (tokens, ret) = match tokens.split_first() {
+        Some((Token::Arrow { .. }, rest)) => parse_type(rest)?,
+        _ => (tokens, Type::Unit),
+    };
+    Ok((
+        tokens,
+        Signature {
+            name,
+            generics,
+            params,
+            ret,
+            here,
+        },
+    ))
+}
+
+/// Parses the `<T: Bound, U>` after the name of a generic function, struct or enum or after
+/// `impl`, if there is one
+fn parse_generics(tokens: &[Token]) -> Result<(&[Token], Vec<Generic>), ASTError> {
+    match tokens.split_first() {
+        Some((Token::Less { .. }, rest)) => {
+            parse_list(rest, Token::Greater { here: 0 }, |tokens| {
+                let (mut tokens, (name, here)) = parse_ident(tokens)?;
+                let mut bounds = vec![];
+                if let Some((Token::Colon { .. }, rest)) = tokens.split_first() {
+                    let (rest, path) = parse_path(rest)?;
+                    bounds.push(join_path(&path));
+                    tokens = rest;
+                    while let Some((Token::Plus { .. }, rest)) = tokens.split_first() {
+                        let (rest, path) = parse_path(rest)?;
+                        bounds.push(join_path(&path));
+                        tokens = rest;
+                    }
+                }
+                Ok((tokens, Generic { name, bounds, here }))
+            })
+        }
+        _ => Ok((tokens, vec![])),
+    }
+}
+
+fn parse_function(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::Fn { here: 0 })?;
+    let (
+        tokens,
+        Signature {
+            name,
+            generics,
+            params,
+            ret,
+            here,
+        },
+    ) = parse_signature(tokens, true)?;
+    let (tokens, body) = parse_block(tokens)?;
+    Ok((
+        tokens,
+        Statement::Function {
+            name,
+            generics,
+            params,
+            ret,
+            body,
+            here,
+        },
+    ))
+}
+
+fn parse_extern(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::Extern { here: 0 })?;
+    let tokens = expect(tokens, Token::Fn { here: 0 })?;
+    let (
+        tokens,
+        Signature {
+            name,
+            params,
+            ret,
+            here,
+            ..
+        },
+    ) = parse_signature(tokens, false)?;
+    let tokens = expect(tokens, Token::Semicolon { here: 0 })?;
+    Ok((
+        tokens,
+        Statement::Extern {
+            name,
+            params,
+            ret,
+            here,
+        },
+    ))
+}
+
+/// Parses `trait Name { fn method(self) -> T; ... }`
+fn parse_trait(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::Trait { here: 0 })?;
+    let (tokens, (name, here)) = parse_ident(tokens)?;
+    let mut tokens = expect(tokens, Token::OpenCurly { here: 0 })?;
+    let mut methods = vec![];
+    while !matches!(tokens.first(), Some(Token::CloseCurly { .. })) {
+        let rest = expect(tokens, Token::Fn { here: 0 })?;
+        let (
+            rest,
+            Signature {
+                name,
+                params,
+                ret,
+                here,
+                ..
+            },
+        ) = parse_signature(rest, false)?;
+        tokens = expect(rest, Token::Semicolon { here: 0 })?;
+        methods.push(TraitMethod {
+            name,
+            params,
+            ret,
+            here,
+        });
+    }
+    Ok((
+        &tokens[1..],
+        Statement::Trait {
+            name,
+            methods,
+            here,
+        },
+    ))
+}
+
+/// Parses `impl<T> Trait for Type<T> { fn ... }`, the trait and the type parameters are
+/// optional. The impl is reported at the type
+fn parse_impl(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::Impl { here: 0 })?;
+    let (tokens, generics) = parse_generics(tokens)?;
+    let here = tokens.first().map_or(0, Token::here);
+    let (tokens, first) = parse_type(tokens)?;
+    let (tokens, trait_name, ty, here) = match (first, tokens.split_first()) {
+        (Type::Named(trait_name), Some((Token::For { .. }, rest))) => {
+            let here = rest.first().map_or(0, Token::here);
+            let (rest, ty) = parse_type(rest)?;
+            (rest, Some(trait_name), ty, here)
+        }
+        (ty, _) => (tokens, None, ty, here),
+    };
+    let mut tokens = expect(tokens, Token::OpenCurly { here: 0 })?;
+    let mut methods = vec![];
+    while !matches!(tokens.first(), Some(Token::CloseCurly { .. })) {
+        let (rest, method) = parse_function(tokens)?;
+        methods.push(method);
+        tokens = rest;
+    }
+    Ok((
+        &tokens[1..],
+        Statement::Impl {
+            generics,
+            trait_name,
+            ty,
+            methods,
+            here,
+        },
+    ))
+}
+
+/// Parses `const NAME: T = value;` or the same with `static`
+fn parse_global(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let (is_const, tokens) = match tokens.split_first() {
+        Some((Token::Const { .. }, rest)) => (true, rest),
+        _ => (false, expect(tokens, Token::Static { here: 0 })?),
+    };
+    let (tokens, (name, here)) = parse_ident(tokens)?;
+    let tokens = expect(tokens, Token::Colon { here: 0 })?;
+    let (tokens, ty) = parse_type(tokens)?;
+    let tokens = expect(tokens, Token::Equal { here: 0 })?;
+    let (tokens, value) = parse_expr(tokens)?;
+    let tokens = expect(tokens, Token::Semicolon { here: 0 })?;
+    let global = if is_const {
+        Statement::Const {
+            name,
+            ty,
+            value,
+            here,
+        }
+    } else {
+        Statement::Static {
+            name,
+            ty,
+            value,
+            here,
+        }
+    };
+    Ok((tokens, global))
+}
+
+fn parse_struct(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::Struct { here: 0 })?;
+    let (tokens, (name, here)) = parse_ident(tokens)?;
+    let (tokens, generics) = parse_generics(tokens)?;
+    let tokens = expect(tokens, Token::OpenCurly { here: 0 })?;
+    let (tokens, fields) = parse_list(tokens, Token::CloseCurly { here: 0 }, |tokens| {
+        let (tokens, (name, here)) = parse_ident(tokens)?;
+        let tokens = expect(tokens, Token::Colon { here: 0 })?;
+        let (tokens, ty) = parse_type(tokens)?;
+        Ok((tokens, FieldDecl { name, ty, here }))
+    })?;
+    Ok((
+        tokens,
+        Statement::Struct {
+            name,
+            generics,
+            fields,
+            here,
+        },
+    ))
+}
+
+fn parse_enum(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::Enum { here: 0 })?;
+    let (tokens, (name, here)) = parse_ident(tokens)?;
+    let (tokens, generics) = parse_generics(tokens)?;
+    let tokens = expect(tokens, Token::OpenCurly { here: 0 })?;
+    let (tokens, variants) = parse_list(tokens, Token::CloseCurly { here: 0 }, |tokens| {
+        let (tokens, (name, here)) = parse_ident(tokens)?;
+        let (tokens, payload) = match tokens.split_first() {
+            Some((Token::OpenParen { .. }, rest)) => {
+                parse_list(rest, Token::CloseParen { here: 0 }, parse_type)?
+            }
+            _ => (tokens, vec![]),
+        };
+        Ok((
+            tokens,
+            VariantDecl {
+                name,
+                payload,
+                here,
+            },
+        ))
+    })?;
+    Ok((
+        tokens,
+        Statement::Enum {
+            name,
+            generics,
+            variants,
+            here,
+        },
+    ))
+}
+
+fn parse_statement(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    // Blocks don't need a semicolon after them
+    if let Some(Token::For { .. }) = tokens.first() {
+        return parse_for(tokens);
+    }
+    // The deferred statement brings its own semicolon
+    if let Some((Token::Defer { here }, rest)) = tokens.split_first() {
+        let (rest, stmt) = parse_statement(rest)?;
+        return Ok((
+            rest,
+            Statement::Defer {
+                stmt: Box::new(stmt),
+                here: *here,
+            },
+        ));
+    }
+    let (rest, stmt) = match tokens.split_first() {
+        Some((Token::Return { .. }, rest)) => {
+            let (rest, expr) = parse_expr(rest)?;
+            (rest, Statement::Return(expr))
+        }
+        Some((Token::Var { .. } | Token::Let { .. }, _)) => parse_define(tokens)?,
+        Some((Token::Break { here }, rest)) => (rest, Statement::Break { here: *here }),
+        Some((Token::Continue { here }, rest)) => (rest, Statement::Continue { here: *here }),
+        None => {
+            return Err(eof());
+        }
+        Some(_) => {
+            let (rest, expr) = parse_expr(tokens)?;
+            match rest.split_first() {
+                Some((Token::Equal { .. }, rest)) => {
+                    let (rest, value) = parse_expr(rest)?;
+                    (
+                        rest,
+                        Statement::Assign {
+                            target: expr,
+                            value,
+                        },
+                    )
+                }
+                _ => (rest, Statement::Expression(expr)),
+            }
+        }
+    };
+    match rest.split_first() {
+        Some((Token::Semicolon { .. }, rest)) => Ok((rest, stmt)),
+        Some((t, ..)) => Err(ASTError::UnexpectedToken {
+            here: t.here(),
+            got: t.clone(),
+            expected: Token::Semicolon { here: 0 },
+        }),
+        None => Err(eof()),
+    }
+}
+
+fn parse_for(tokens: &[Token]) -> Result<(&[Token], Statement), ASTError> {
+    let tokens = expect(tokens, Token::For { here: 0 })?;
+    let (tokens, (var, here)) = parse_ident(tokens)?;
+    let tokens = expect(tokens, Token::In { here: 0 })?;
+    let (tokens, start) = parse_expr(tokens)?;
+    let (tokens, inclusive) = match tokens.split_first() {
+        Some((Token::DotDot { .. }, rest)) => (rest, false),
+        Some((Token::DotDotEqual { .. }, rest)) => (rest, true),
+        Some((t, _)) => {
+            return Err(ASTError::UnexpectedToken {
+                here: t.here(),
+                got: t.clone(),
+                expected: Token::DotDot { here: 0 },
+            });
+        }
+        None => return Err(eof()),
+    };
+    let (tokens, end) = parse_expr(tokens)?;
+    // `for i in 0..n {}` parses as an empty struct literal `n {}`, which is never a range
+    let (tokens, end, body) = match end {
+        Expression::StructLiteral { name, fields, here } if fields.is_empty() => {
+            (tokens, Expression::Variable { name, here }, vec![])
+        }
+        end => {
+            let (tokens, body) = parse_block(tokens)?;
+            (tokens, end, body)
+        }
+    };
+    Ok((
+        tokens,
+        Statement::For {
+            var,
+            start,
+            end,
+            inclusive,
+            body,
+            here,
+        },
+    ))
+}
+
+fn parse_expr(mut tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
+    let (ts, mut left) = parse_mult(tokens)?;
+    tokens = ts;
+
+    loop {
+        let op = match tokens.first() {
+            Some(Token::Plus { here }) => Token::Plus { here: *here },
+            Some(Token::Minus { here }) => Token::Minus { here: *here },
+            _ => return Ok((tokens, left)),
         };
         tokens = &tokens[1..];
 
@@ -130,182 +1165,1458 @@ fn parse_expr(mut tokens: &[Token]) -> Result<(&[Token], Expression), ASTError>
             right: Box::new(right),
         }
     }
-}
+}
+
+fn parse_type(tokens: &[Token]) -> Result<(&[Token], Type), ASTError> {
+    match tokens.split_first() {
+        Some((Token::Ident { .. }, _)) => {
+            let (rest, path) = parse_path(tokens)?;
+            if let Some((Token::Less { .. }, rest)) = rest.split_first() {
+                let (rest, args) = parse_list(rest, Token::Greater { here: 0 }, parse_type)?;
+                return Ok((rest, Type::Generic(join_path(&path), args.into())));
+            }
+            let ty = match join_path(&path).as_str() {
+                "i32" => Type::I32,
+                "i64" => Type::I64,
+                "f32" => Type::F32,
+                "f64" => Type::F64,
+                "char" => Type::Char,
+                "u8" => Type::U8,
+                "str" => Type::Str,
+                name => Type::Named(name.to_string()),
+            };
+            Ok((rest, ty))
+        }
+        Some((Token::Star { .. }, rest)) => {
+            let (rest, inner) = parse_type(rest)?;
+            Ok((rest, Type::Pointer(Box::new(inner))))
+        }
+        Some((Token::Dyn { .. }, rest)) => {
+            let (rest, path) = parse_path(rest)?;
+            Ok((rest, Type::Dyn(join_path(&path))))
+        }
+        Some((Token::Fn { .. }, rest)) => {
+            let rest = expect(rest, Token::OpenParen { here: 0 })?;
+            let (rest, params) = parse_list(rest, Token::CloseParen { here: 0 }, parse_type)?;
+            let (rest, ret) = match rest.split_first() {
+                Some((Token::Arrow { .. }, rest)) => parse_type(rest)?,
+                _ => (rest, Type::Unit),
+            };
+            Ok((rest, Type::Function(params.into(), Box::new(ret))))
+        }
+        Some((Token::OpenParen { .. }, rest)) => {
+            if let Some((Token::CloseParen { .. }, rest)) = rest.split_first() {
+                return Ok((rest, Type::Unit));
+            }
+            // `(T)` is just `T`, a tuple needs a comma
+            let (rest, first) = parse_type(rest)?;
+            match rest.split_first() {
+                Some((Token::Comma { .. }, rest)) => {
+                    let (rest, mut elems) =
+                        parse_list(rest, Token::CloseParen { here: 0 }, parse_type)?;
+                    elems.insert(0, first);
+                    Ok((rest, Type::Tuple(elems)))
+                }
+                _ => Ok((expect(rest, Token::CloseParen { here: 0 })?, first)),
+            }
+        }
+        Some((Token::OpenBracket { .. }, [Token::CloseBracket { .. }, rest @ ..])) => {
+            let (rest, elem) = parse_type(rest)?;
+            Ok((rest, Type::Slice(Box::new(elem))))
+        }
+        Some((Token::OpenBracket { .. }, rest)) => {
+            let (rest, elem) = parse_type(rest)?;
+            let rest = expect(rest, Token::Semicolon { here: 0 })?;
+            let (rest, len) = match rest.split_first() {
+                Some((Token::Number { value, .. }, rest)) => (rest, *value),
+                Some((t, ..)) => {
+                    return Err(ASTError::UnexpectedToken {
+                        here: t.here(),
+                        got: t.clone(),
+                        expected: Token::Number {
+                            value: 0,
+                            here: 0,
+                            len: 0,
+                        },
+                    });
+                }
+                None => return Err(eof()),
+            };
+            let rest = expect(rest, Token::CloseBracket { here: 0 })?;
+            Ok((rest, Type::Array(Box::new(elem), len)))
+        }
+        Some((t, ..)) => Err(ASTError::UnexpectedToken {
+            here: t.here(),
+            got: t.clone(),
+            expected: Token::Ident {
+                value: "type".to_string(),
+                here: 0,
+            },
+        }),
+        None => Err(eof()),
+    }
+}
+
+fn parse_mult(mut tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
+    let (ts, mut left) = parse_cast(tokens)?;
+    tokens = ts;
+
+    loop {
+        let op = match tokens.first() {
+            Some(Token::Star { here }) => Token::Star { here: *here },
+            Some(Token::Slash { here }) => Token::Slash { here: *here },
+            _ => return Ok((tokens, left)),
+        };
+        tokens = &tokens[1..];
+
+        let (ts, right) = parse_cast(tokens)?;
+        tokens = ts;
+        left = Expression::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        }
+    }
+}
+
+fn parse_ident(tokens: &[Token]) -> Result<(&[Token], (String, usize)), ASTError> {
+    match tokens.split_first() {
+        Some((Token::Ident { value, here }, rest)) => Ok((rest, (value.to_string(), *here))),
+        Some((t, ..)) => Err(ASTError::UnexpectedToken {
+            here: t.here(),
+            got: t.clone(),
+            expected: Token::Ident {
+                value: "any".to_string(),
+                here: 0,
+            },
+        }),
+        None => Err(eof()),
+    }
+}
+
+/// Parses `item`s separated by commas up to and including `close`, allowing a trailing comma
+fn parse_list<'a, T>(
+    mut tokens: &'a [Token],
+    close: Token,
+    mut item: impl FnMut(&'a [Token]) -> Result<(&'a [Token], T), ASTError>,
+) -> Result<(&'a [Token], Vec<T>), ASTError> {
+    let mut items = vec![];
+    while tokens
+        .first()
+        .is_some_and(|t| std::mem::discriminant(t) != std::mem::discriminant(&close))
+    {
+        let (ts, i) = item(tokens)?;
+        items.push(i);
+        match ts.split_first() {
+            Some((Token::Comma { .. }, rest)) => tokens = rest,
+            _ => {
+                tokens = ts;
+                break;
+            }
+        }
+    }
+    Ok((expect(tokens, close)?, items))
+}
+
+/// Consumes a token of the same kind as `expected`, the fields of `expected` are ignored
+fn expect(tokens: &[Token], expected: Token) -> Result<&[Token], ASTError> {
+    match tokens.split_first() {
+        Some((t, rest)) if std::mem::discriminant(t) == std::mem::discriminant(&expected) => {
+            Ok(rest)
+        }
+        Some((t, ..)) => Err(ASTError::UnexpectedToken {
+            here: t.here(),
+            got: t.clone(),
+            expected,
+        }),
+        None => Err(eof()),
+    }
+}
+
+fn parse_cast(mut tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
+    let (ts, mut value) = parse_unary(tokens)?;
+    tokens = ts;
+
+    while let Some((Token::As { here }, rest)) = tokens.split_first() {
+        let (ts, to) = parse_type(rest)?;
+        tokens = ts;
+        value = Expression::Cast {
+            value: Box::new(value),
+            to,
+            here: *here,
+        };
+    }
+    Ok((tokens, value))
+}
+
+fn parse_unary(tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
+    match tokens.split_first() {
+        Some((Token::Star { here }, rest)) => {
+            let (rest, value) = parse_unary(rest)?;
+            Ok((
+                rest,
+                Expression::Deref {
+                    value: Box::new(value),
+                    here: *here,
+                },
+            ))
+        }
+        Some((Token::Ampersand { here }, rest)) => {
+            let (rest, value) = parse_unary(rest)?;
+            Ok((
+                rest,
+                Expression::AddressOf {
+                    value: Box::new(value),
+                    here: *here,
+                },
+            ))
+        }
+        _ => parse_postfix(tokens),
+    }
+}
+
+fn parse_postfix(mut tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
+    let (ts, mut value) = parse_primary(tokens)?;
+    tokens = ts;
+
+    loop {
+        match tokens.split_first() {
+            Some((Token::OpenBracket { here }, rest)) => {
+                let (ts, start) = match rest.first() {
+                    Some(Token::DotDot { .. } | Token::DotDotEqual { .. }) => (rest, None),
+                    _ => {
+                        let (ts, start) = parse_expr(rest)?;
+                        (ts, Some(Box::new(start)))
+                    }
+                };
+                let (ts, end, inclusive) = match ts.split_first() {
+                    Some((Token::DotDot { .. }, [Token::CloseBracket { .. }, ..])) => {
+                        (&ts[1..], None, false)
+                    }
+                    Some((Token::DotDot { .. }, ts)) => {
+                        let (ts, end) = parse_expr(ts)?;
+                        (ts, Some(Box::new(end)), false)
+                    }
+                    Some((Token::DotDotEqual { .. }, ts)) => {
+                        let (ts, end) = parse_expr(ts)?;
+                        (ts, Some(Box::new(end)), true)
+                    }
+                    _ => {
+                        tokens = expect(ts, Token::CloseBracket { here: 0 })?;
+                        value = Expression::Index {
+                            array: Box::new(value),
+                            index: start.unwrap(),
+                            here: *here,
+                        };
+                        continue;
+                    }
+                };
+                tokens = expect(ts, Token::CloseBracket { here: 0 })?;
+                value = Expression::Slice {
+                    value: Box::new(value),
+                    start,
+                    end,
+                    inclusive,
+                    here: *here,
+                };
+            }
+            Some((
+                Token::Dot { .. },
+                [
+                    Token::Number {
+                        value: index, here, ..
+                    },
+                    rest @ ..,
+                ],
+            )) => {
+                tokens = rest;
+                value = Expression::Field {
+                    value: Box::new(value),
+                    field: index.to_string(),
+                    here: *here,
+                };
+            }
+            Some((Token::Dot { .. }, rest)) => {
+                let (ts, (field, here)) = parse_ident(rest)?;
+                if let Some((Token::OpenParen { .. }, rest)) = ts.split_first() {
+                    let (ts, args) = parse_list(rest, Token::CloseParen { here: 0 }, parse_expr)?;
+                    tokens = ts;
+                    value = Expression::MethodCall {
+                        value: Box::new(value),
+                        method: field,
+                        args,
+                        here,
+                    };
+                    continue;
+                }
+                tokens = ts;
+                value = Expression::Field {
+                    value: Box::new(value),
+                    field,
+                    here,
+                };
+            }
+            Some((Token::Question { here }, rest)) => {
+                tokens = rest;
+                value = Expression::Try {
+                    value: Box::new(value),
+                    here: *here,
+                };
+            }
+            _ => return Ok((tokens, value)),
+        }
+    }
+}
+
+fn parse_pattern(tokens: &[Token]) -> Result<(&[Token], Pattern), ASTError> {
+    match tokens.split_first() {
+        Some((Token::Number { value, here, .. }, rest)) => Ok((
+            rest,
+            Pattern::Number {
+                value: *value,
+                here: *here,
+            },
+        )),
+        Some((Token::Char { value, here }, rest)) => Ok((
+            rest,
+            Pattern::Char {
+                value: *value,
+                here: *here,
+            },
+        )),
+        Some((Token::Ident { value, here }, rest)) if value == "_" => {
+            Ok((rest, Pattern::Wildcard { here: *here }))
+        }
+        Some((Token::Ident { .. }, rest)) => {
+            expect(rest, Token::ColonColon { here: 0 })?;
+            let (rest, mut path) = parse_path(tokens)?;
+            let (variant, here) = path.pop().unwrap();
+            let (rest, bindings) = match rest.split_first() {
+                Some((Token::OpenParen { .. }, rest)) => {
+                    parse_list(rest, Token::CloseParen { here: 0 }, parse_ident)?
+                }
+                _ => (rest, vec![]),
+            };
+            Ok((
+                rest,
+                Pattern::Variant {
+                    ty: join_path(&path),
+                    variant,
+                    bindings,
+                    here,
+                },
+            ))
+        }
+        Some((t, ..)) => Err(ASTError::UnexpectedToken {
+            here: t.here(),
+            got: t.clone(),
+            expected: Token::Ident {
+                value: "_".to_string(),
+                here: 0,
+            },
+        }),
+        None => Err(eof()),
+    }
+}
+
+/// `x {` only starts a struct literal when `}` or `field:` follows, so that the braces of
+/// `match x { ... }` aren't taken for one. `tokens` starts right after the name
+fn is_struct_literal(tokens: &[Token]) -> bool {
+    matches!(
+        tokens.get(0..2),
+        Some([Token::OpenCurly { .. }, Token::CloseCurly { .. }])
+    ) || matches!(
+        tokens.get(0..3),
+        Some([
+            Token::OpenCurly { .. },
+            Token::Ident { .. },
+            Token::Colon { .. }
+        ])
+    )
+}
+
+/// Parses the `{ field: value, ... }` following the name of a struct literal
+fn parse_struct_literal(
+    tokens: &[Token],
+    name: String,
+    here: usize,
+) -> Result<(&[Token], Expression), ASTError> {
+    let tokens = expect(tokens, Token::OpenCurly { here: 0 })?;
+    let (tokens, fields) = parse_list(tokens, Token::CloseCurly { here: 0 }, |tokens| {
+        let (tokens, (name, here)) = parse_ident(tokens)?;
+        let tokens = expect(tokens, Token::Colon { here: 0 })?;
+        let (tokens, value) = parse_expr(tokens)?;
+        Ok((tokens, FieldInit { name, value, here }))
+    })?;
+    Ok((tokens, Expression::StructLiteral { name, fields, here }))
+}
+
+/// Parses a closure after its opening `|`
+fn parse_closure(tokens: &[Token], here: usize) -> Result<(&[Token], Expression), ASTError> {
+    let (tokens, params) = parse_list(tokens, Token::Pipe { here: 0 }, |tokens| {
+        let (tokens, (name, here)) = parse_ident(tokens)?;
+        let (tokens, ty) = match tokens.split_first() {
+            Some((Token::Colon { .. }, rest)) => {
+                let (rest, ty) = parse_type(rest)?;
+                (rest, Some(ty))
+            }
+            _ => (tokens, None),
+        };
+        Ok((tokens, ClosureParam { name, ty, here }))
+    })?;
+    let (tokens, ret, body) = match tokens.split_first() {
+        Some((Token::Arrow { .. }, rest)) => {
+            let (rest, ret) = parse_type(rest)?;
+            let (rest, body) = parse_block(rest)?;
+            (rest, Some(ret), body)
+        }
+        Some((Token::OpenCurly { .. }, _)) => {
+            let (rest, body) = parse_block(tokens)?;
+            (rest, Some(Type::Unit), body)
+        }
+        _ => {
+            let (rest, value) = parse_expr(tokens)?;
+            (rest, None, vec![Statement::Return(value)])
+        }
+    };
+    Ok((
+        tokens,
+        Expression::Closure {
+            params,
+            ret,
+            body,
+            here,
+        },
+    ))
+}
+
+fn parse_primary(tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
+    match tokens.first() {
+        Some(Token::OpenBracket { here }) => {
+            let (tokens, elements) =
+                parse_list(&tokens[1..], Token::CloseBracket { here: 0 }, parse_expr)?;
+            Ok((
+                tokens,
+                Expression::Array {
+                    elements,
+                    here: *here,
+                },
+            ))
+        }
+        Some(Token::Match { here }) => {
+            let (tokens, value) = parse_expr(&tokens[1..])?;
+            let tokens = expect(tokens, Token::OpenCurly { here: 0 })?;
+            let (tokens, arms) = parse_list(tokens, Token::CloseCurly { here: 0 }, |tokens| {
+                let (tokens, pattern) = parse_pattern(tokens)?;
+                let tokens = expect(tokens, Token::FatArrow { here: 0 })?;
+                let (tokens, value) = parse_expr(tokens)?;
+                Ok((tokens, MatchArm { pattern, value }))
+            })?;
+            Ok((
+                tokens,
+                Expression::Match {
+                    value: Box::new(value),
+                    arms,
+                    here: *here,
+                },
+            ))
+        }
+        Some(Token::Null { here }) => Ok((&tokens[1..], Expression::Null { here: *here })),
+        Some(Token::Pipe { here }) => parse_closure(&tokens[1..], *here),
+        Some(Token::Ident { value, here })
+            if matches!(tokens.get(1), Some(Token::OpenParen { .. })) =>
+        {
+            let (tokens, args) =
+                parse_list(&tokens[2..], Token::CloseParen { here: 0 }, parse_expr)?;
+            Ok((
+                tokens,
+                Expression::Call {
+                    name: value.to_string(),
+                    args,
+                    here: *here,
+                },
+            ))
+        }
+        Some(Token::Float { value, here, len }) => Ok((
+            &tokens[1..],
+            Expression::Float {
+                value: *value,
+                here: *here,
+                len: *len,
+            },
+        )),
+        Some(Token::Char { value, here }) => Ok((
+            &tokens[1..],
+            Expression::Char {
+                value: *value,
+                here: *here,
+            },
+        )),
+        Some(Token::Str { value, here }) => Ok((
+            &tokens[1..],
+            Expression::Str {
+                value: value.to_string(),
+                here: *here,
+            },
+        )),
+        Some(Token::Number { value, here, len }) => Ok((
+            &tokens[1..],
+            Expression::Number {
+                value: *value,
+                here: *here,
+                len: *len,
+            },
+        )),
+        Some(Token::Ident { .. }) if matches!(tokens.get(1), Some(Token::ColonColon { .. })) => {
+            let (tokens, mut path) = parse_path(tokens)?;
+            if is_struct_literal(tokens) {
+                let here = path.last().unwrap().1;
+                return parse_struct_literal(tokens, join_path(&path), here);
+            }
+            // `a::b` is left for the module resolver when `a` turns out to be a module
+            let (variant, here) = path.pop().unwrap();
+            let (tokens, args) = match tokens.split_first() {
+                Some((Token::OpenParen { .. }, rest)) => {
+                    parse_list(rest, Token::CloseParen { here: 0 }, parse_expr)?
+                }
+                _ => (tokens, vec![]),
+            };
+            Ok((
+                tokens,
+                Expression::Variant {
+                    ty: join_path(&path),
+                    variant,
+                    args,
+                    here,
+                },
+            ))
+        }
+        Some(Token::Ident { value, here }) if is_struct_literal(&tokens[1..]) => {
+            parse_struct_literal(&tokens[1..], value.to_string(), *here)
+        }
+        Some(Token::Ident { value, here }) => Ok((
+            &tokens[1..],
+            Expression::Variable {
+                here: *here,
+                name: value.to_string(),
+            },
+        )),
+        Some(Token::OpenParen { here }) => {
+            let tokens = &tokens[1..];
+            let (ts, expr) = parse_expr(tokens)?;
+            let tokens = ts;
+            // `(a)` is just `a`, a tuple needs a comma
+            if let Some((Token::Comma { .. }, rest)) = tokens.split_first() {
+                let (tokens, mut elements) =
+                    parse_list(rest, Token::CloseParen { here: 0 }, parse_expr)?;
+                elements.insert(0, expr);
+                return Ok((
+                    tokens,
+                    Expression::Tuple {
+                        elements,
+                        here: *here,
+                    },
+                ));
+            }
+            match tokens.first() {
+                Some(Token::CloseParen { .. }) => Ok((&tokens[1..], expr)),
+                None => Err(eof())?,
+                Some(t) => Err(ASTError::UnexpectedToken {
+                    here: t.here(),
+                    got: t.clone(),
+                    expected: Token::CloseParen { here: 0 },
+                })?,
+            }
+        }
+        Some(t) => Err(ASTError::UnexpectedToken {
+            here: t.here(),
+            got: t.clone(),
+            expected: Token::OpenParen { here: 0 },
+        })?,
+        None => Err(eof()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let tokens = vec![];
+        assert_eq!(parse(&tokens), Ok(vec![]));
+    }
+
+    #[test]
+    fn number_expr() {
+        let tokens = vec![Token::Number {
+            value: 0,
+            here: 0,
+            len: 1,
+        }];
+        let empty: &[Token] = &[];
+        let (rest, expr) = parse_expr(&tokens).unwrap();
+        assert_eq!(rest, empty);
+        assert_eq!(
+            expr,
+            Expression::Number {
+                value: 0,
+                here: 0,
+                len: 1
+            }
+        );
+    }
+
+    #[test]
+    fn binary_expr() {
+        let tokens = vec![
+            Token::Number {
+                value: 1,
+                here: 0,
+                len: 1,
+            },
+            Token::Plus { here: 1 },
+            Token::Number {
+                value: 1,
+                here: 2,
+                len: 1,
+            },
+        ];
+        let empty: &[Token] = &[];
+        let (rest, expr) = parse_expr(&tokens).unwrap();
+        assert_eq!(rest, empty);
+        assert_eq!(
+            expr,
+            Expression::Binary {
+                left: Box::new(Expression::Number {
+                    value: 1,
+                    here: 0,
+                    len: 1
+                }),
+                op: Token::Plus { here: 1 },
+                right: Box::new(Expression::Number {
+                    value: 1,
+                    here: 2,
+                    len: 1
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn statement_return() {
+        let tokens = vec![
+            Token::Return { here: 0 },
+            Token::Number {
+                value: 0,
+                here: 7,
+                len: 1,
+            },
+            Token::Semicolon { here: 8 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::Return(Expression::Number {
+                value: 0,
+                here: 7,
+                len: 1
+            })])
+        );
+    }
+
+    #[test]
+    fn statement_var() {
+        let tokens = vec![
+            Token::Var { here: 0 },
+            Token::Ident {
+                value: String::from("hello"),
+                here: 1,
+            },
+            Token::Equal { here: 2 },
+            Token::Number {
+                value: 0,
+                len: 1,
+                here: 3,
+            },
+            Token::Semicolon { here: 4 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::DefineVar {
+                name: String::from("hello"),
+                mutable: true,
+                ty: None,
+                value: Expression::Number {
+                    value: 0,
+                    len: 1,
+                    here: 3
+                },
+                here: 1,
+            }])
+        );
+    }
+
+    #[test]
+    fn cast_binds_tighter_than_mult() {
+        let tokens = vec![
+            Token::Float {
+                value: 1.5,
+                here: 0,
+                len: 3,
+            },
+            Token::Star { here: 4 },
+            Token::Number {
+                value: 2,
+                here: 6,
+                len: 1,
+            },
+            Token::As { here: 8 },
+            Token::Ident {
+                value: String::from("f64"),
+                here: 11,
+            },
+        ];
+        let empty: &[Token] = &[];
+        let (rest, expr) = parse_expr(&tokens).unwrap();
+        assert_eq!(rest, empty);
+        assert_eq!(
+            expr,
+            Expression::Binary {
+                left: Box::new(Expression::Float {
+                    value: 1.5,
+                    here: 0,
+                    len: 3
+                }),
+                op: Token::Star { here: 4 },
+                right: Box::new(Expression::Cast {
+                    value: Box::new(Expression::Number {
+                        value: 2,
+                        here: 6,
+                        len: 1
+                    }),
+                    to: Type::F64,
+                    here: 8,
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn indexed_assignment() {
+        let tokens = vec![
+            Token::Ident {
+                value: String::from("a"),
+                here: 0,
+            },
+            Token::OpenBracket { here: 1 },
+            Token::Number {
+                value: 1,
+                here: 2,
+                len: 1,
+            },
+            Token::CloseBracket { here: 3 },
+            Token::Equal { here: 5 },
+            Token::OpenBracket { here: 7 },
+            Token::Number {
+                value: 2,
+                here: 8,
+                len: 1,
+            },
+            Token::Comma { here: 9 },
+            Token::CloseBracket { here: 10 },
+            Token::OpenBracket { here: 11 },
+            Token::Number {
+                value: 0,
+                here: 12,
+                len: 1,
+            },
+            Token::CloseBracket { here: 13 },
+            Token::Semicolon { here: 14 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::Assign {
+                target: Expression::Index {
+                    array: Box::new(Expression::Variable {
+                        name: String::from("a"),
+                        here: 0
+                    }),
+                    index: Box::new(Expression::Number {
+                        value: 1,
+                        here: 2,
+                        len: 1
+                    }),
+                    here: 1,
+                },
+                value: Expression::Index {
+                    array: Box::new(Expression::Array {
+                        elements: vec![Expression::Number {
+                            value: 2,
+                            here: 8,
+                            len: 1
+                        }],
+                        here: 7,
+                    }),
+                    index: Box::new(Expression::Number {
+                        value: 0,
+                        here: 12,
+                        len: 1
+                    }),
+                    here: 11,
+                },
+            }])
+        );
+    }
+
+    #[test]
+    fn struct_declaration() {
+        let tokens = vec![
+            Token::Struct { here: 0 },
+            Token::Ident {
+                value: String::from("P"),
+                here: 7,
+            },
+            Token::OpenCurly { here: 9 },
+            Token::Ident {
+                value: String::from("x"),
+                here: 11,
+            },
+            Token::Colon { here: 12 },
+            Token::Ident {
+                value: String::from("i64"),
+                here: 14,
+            },
+            Token::Comma { here: 17 },
+            Token::CloseCurly { here: 19 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::Struct {
+                name: String::from("P"),
+                generics: vec![],
+                fields: vec![FieldDecl {
+                    name: String::from("x"),
+                    ty: Type::I64,
+                    here: 11
+                }],
+                here: 7,
+            }])
+        );
+    }
+
+    #[test]
+    fn generic_struct() {
+        // struct L<T> { next: *L<T> }
+        let tokens = vec![
+            Token::Struct { here: 0 },
+            Token::Ident {
+                value: String::from("L"),
+                here: 7,
+            },
+            Token::Less { here: 8 },
+            Token::Ident {
+                value: String::from("T"),
+                here: 9,
+            },
+            Token::Greater { here: 10 },
+            Token::OpenCurly { here: 12 },
+            Token::Ident {
+                value: String::from("next"),
+                here: 14,
+            },
+            Token::Colon { here: 18 },
+            Token::Star { here: 20 },
+            Token::Ident {
+                value: String::from("L"),
+                here: 21,
+            },
+            Token::Less { here: 22 },
+            Token::Ident {
+                value: String::from("T"),
+                here: 23,
+            },
+            Token::Greater { here: 24 },
+            Token::CloseCurly { here: 26 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::Struct {
+                name: String::from("L"),
+                generics: vec![Generic {
+                    name: String::from("T"),
+                    bounds: vec![],
+                    here: 9
+                }],
+                fields: vec![FieldDecl {
+                    name: String::from("next"),
+                    ty: Type::Pointer(Box::new(Type::Generic(
+                        String::from("L"),
+                        Box::new([Type::Named(String::from("T"))])
+                    ))),
+                    here: 14
+                }],
+                here: 7,
+            }])
+        );
+    }
+
+    #[test]
+    fn method_call() {
+        // a.f(1).g
+        let tokens = vec![
+            Token::Ident {
+                value: String::from("a"),
+                here: 0,
+            },
+            Token::Dot { here: 1 },
+            Token::Ident {
+                value: String::from("f"),
+                here: 2,
+            },
+            Token::OpenParen { here: 3 },
+            Token::Number {
+                value: 1,
+                here: 4,
+                len: 1,
+            },
+            Token::CloseParen { here: 5 },
+            Token::Dot { here: 6 },
+            Token::Ident {
+                value: String::from("g"),
+                here: 7,
+            },
+        ];
+        let (_, expr) = parse_expr(&tokens).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Field {
+                value: Box::new(Expression::MethodCall {
+                    value: Box::new(Expression::Variable {
+                        name: String::from("a"),
+                        here: 0
+                    }),
+                    method: String::from("f"),
+                    args: vec![Expression::Number {
+                        value: 1,
+                        here: 4,
+                        len: 1
+                    }],
+                    here: 2,
+                }),
+                field: String::from("g"),
+                here: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn struct_literal_field() {
+        let tokens = vec![
+            Token::Ident {
+                value: String::from("P"),
+                here: 0,
+            },
+            Token::OpenCurly { here: 2 },
+            Token::Ident {
+                value: String::from("x"),
+                here: 4,
+            },
+            Token::Colon { here: 5 },
+            Token::Number {
+                value: 1,
+                here: 7,
+                len: 1,
+            },
+            Token::CloseCurly { here: 9 },
+            Token::Dot { here: 10 },
+            Token::Ident {
+                value: String::from("x"),
+                here: 11,
+            },
+        ];
+        let empty: &[Token] = &[];
+        let (rest, expr) = parse_expr(&tokens).unwrap();
+        assert_eq!(rest, empty);
+        assert_eq!(
+            expr,
+            Expression::Field {
+                value: Box::new(Expression::StructLiteral {
+                    name: String::from("P"),
+                    fields: vec![FieldInit {
+                        name: String::from("x"),
+                        value: Expression::Number {
+                            value: 1,
+                            here: 7,
+                            len: 1
+                        },
+                        here: 4,
+                    }],
+                    here: 0,
+                }),
+                field: String::from("x"),
+                here: 11,
+            }
+        );
+    }
 
-fn parse_mult(mut tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
-    let (ts, mut left) = parse_primary(tokens)?;
-    tokens = ts;
+    #[test]
+    fn match_expr() {
+        let tokens = vec![
+            Token::Match { here: 0 },
+            Token::Ident {
+                value: String::from("s"),
+                here: 6,
+            },
+            Token::OpenCurly { here: 8 },
+            Token::Ident {
+                value: String::from("S"),
+                here: 10,
+            },
+            Token::ColonColon { here: 11 },
+            Token::Ident {
+                value: String::from("A"),
+                here: 13,
+            },
+            Token::OpenParen { here: 14 },
+            Token::Ident {
+                value: String::from("x"),
+                here: 15,
+            },
+            Token::CloseParen { here: 16 },
+            Token::FatArrow { here: 18 },
+            Token::Ident {
+                value: String::from("x"),
+                here: 21,
+            },
+            Token::Comma { here: 22 },
+            Token::Ident {
+                value: String::from("_"),
+                here: 24,
+            },
+            Token::FatArrow { here: 26 },
+            Token::Number {
+                value: 0,
+                here: 29,
+                len: 1,
+            },
+            Token::CloseCurly { here: 31 },
+        ];
+        let empty: &[Token] = &[];
+        let (rest, expr) = parse_expr(&tokens).unwrap();
+        assert_eq!(rest, empty);
+        assert_eq!(
+            expr,
+            Expression::Match {
+                value: Box::new(Expression::Variable {
+                    name: String::from("s"),
+                    here: 6
+                }),
+                arms: vec![
+                    MatchArm {
+                        pattern: Pattern::Variant {
+                            ty: String::from("S"),
+                            variant: String::from("A"),
+                            bindings: vec![(String::from("x"), 15)],
+                            here: 13,
+                        },
+                        value: Expression::Variable {
+                            name: String::from("x"),
+                            here: 21
+                        },
+                    },
+                    MatchArm {
+                        pattern: Pattern::Wildcard { here: 24 },
+                        value: Expression::Number {
+                            value: 0,
+                            here: 29,
+                            len: 1
+                        },
+                    },
+                ],
+                here: 0,
+            }
+        );
+    }
 
-    loop {
-        let op = match tokens.first() {
-            Some(Token::Star { here }) => Token::Star { here: *here },
-            _ => return Ok((tokens, left)),
-        };
-        tokens = &tokens[1..];
+    #[test]
+    fn function_with_pointer_param() {
+        // fn f(p: *i64) { *p = 1; }
+        let tokens = vec![
+            Token::Fn { here: 0 },
+            Token::Ident {
+                value: String::from("f"),
+                here: 3,
+            },
+            Token::OpenParen { here: 4 },
+            Token::Ident {
+                value: String::from("p"),
+                here: 5,
+            },
+            Token::Colon { here: 6 },
+            Token::Star { here: 8 },
+            Token::Ident {
+                value: String::from("i64"),
+                here: 9,
+            },
+            Token::CloseParen { here: 12 },
+            Token::OpenCurly { here: 14 },
+            Token::Star { here: 16 },
+            Token::Ident {
+                value: String::from("p"),
+                here: 17,
+            },
+            Token::Equal { here: 19 },
+            Token::Number {
+                value: 1,
+                here: 21,
+                len: 1,
+            },
+            Token::Semicolon { here: 22 },
+            Token::CloseCurly { here: 24 },
+        ];
 
-        let (ts, right) = parse_primary(tokens)?;
-        tokens = ts;
-        left = Expression::Binary {
-            left: Box::new(left),
-            op,
-            right: Box::new(right),
-        }
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::Function {
+                name: String::from("f"),
+                generics: vec![],
+                params: vec![Param {
+                    name: String::from("p"),
+                    ty: Type::Pointer(Box::new(Type::I64)),
+                    here: 5,
+                }],
+                ret: Type::Unit,
+                body: vec![Statement::Assign {
+                    target: Expression::Deref {
+                        value: Box::new(Expression::Variable {
+                            name: String::from("p"),
+                            here: 17,
+                        }),
+                        here: 16,
+                    },
+                    value: Expression::Number {
+                        value: 1,
+                        here: 21,
+                        len: 1,
+                    },
+                }],
+                here: 3,
+            }])
+        );
     }
-}
 
-fn parse_primary(tokens: &[Token]) -> Result<(&[Token], Expression), ASTError> {
-    match tokens.first() {
-        Some(Token::Number { value, here, len }) => Ok((
-            &tokens[1..],
-            Expression::Number {
-                value: *value,
-                here: *here,
-                len: *len,
+    #[test]
+    fn for_loop() {
+        // for i in 0..=n {}
+        let tokens = vec![
+            Token::For { here: 0 },
+            Token::Ident {
+                value: String::from("i"),
+                here: 4,
             },
-        )),
-        Some(Token::Ident { value, here }) => Ok((
-            &tokens[1..],
-            Expression::Variable {
-                here: *here,
-                name: value.to_string(),
+            Token::In { here: 6 },
+            Token::Number {
+                value: 0,
+                here: 9,
+                len: 1,
             },
-        )),
-        Some(Token::OpenParen { .. }) => {
-            let tokens = &tokens[1..];
-            let (ts, expr) = parse_expr(tokens)?;
-            let tokens = ts;
-            match tokens.first() {
-                Some(Token::CloseParen { .. }) => Ok((&tokens[1..], expr)),
-                None => Err(ASTError::UnexpectedEOF)?,
-                Some(t) => Err(ASTError::UnexpectedToken {
-                    got: t.clone(),
-                    expected: Token::CloseParen { here: 0 },
-                })?,
-            }
-        }
-        Some(t) => Err(ASTError::UnexpectedToken {
-            got: t.clone(),
-            expected: Token::OpenParen { here: 0 },
-        })?,
-        None => Err(ASTError::UnexpectedEOF),
+            Token::DotDotEqual { here: 10 },
+            Token::Ident {
+                value: String::from("n"),
+                here: 13,
+            },
+            Token::OpenCurly { here: 15 },
+            Token::CloseCurly { here: 16 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::For {
+                var: String::from("i"),
+                start: Expression::Number {
+                    value: 0,
+                    here: 9,
+                    len: 1,
+                },
+                end: Expression::Variable {
+                    name: String::from("n"),
+                    here: 13,
+                },
+                inclusive: true,
+                body: vec![],
+                here: 4,
+            }])
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn defer() {
+        // defer f();
+        let tokens = vec![
+            Token::Defer { here: 0 },
+            Token::Ident {
+                value: String::from("f"),
+                here: 6,
+            },
+            Token::OpenParen { here: 7 },
+            Token::CloseParen { here: 8 },
+            Token::Semicolon { here: 9 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![Statement::Defer {
+                stmt: Box::new(Statement::Expression(Expression::Call {
+                    name: String::from("f"),
+                    args: vec![],
+                    here: 6,
+                })),
+                here: 0,
+            }])
+        );
+    }
 
     #[test]
-    fn empty() {
-        let tokens = vec![];
-        assert_eq!(parse(&tokens), Ok(vec![]));
+    fn loop_control() {
+        // break; continue;
+        let tokens = vec![
+            Token::Break { here: 0 },
+            Token::Semicolon { here: 5 },
+            Token::Continue { here: 7 },
+            Token::Semicolon { here: 15 },
+        ];
+
+        assert_eq!(
+            parse(&tokens),
+            Ok(vec![
+                Statement::Break { here: 0 },
+                Statement::Continue { here: 7 }
+            ])
+        );
     }
 
     #[test]
-    fn number_expr() {
-        let tokens = vec![Token::Number {
-            value: 0,
-            here: 0,
-            len: 1,
-        }];
-        let empty: &[Token] = &[];
+    fn closure() {
+        // |a, b: i64| a + b
+        let tokens = vec![
+            Token::Pipe { here: 0 },
+            Token::Ident {
+                value: String::from("a"),
+                here: 1,
+            },
+            Token::Comma { here: 2 },
+            Token::Ident {
+                value: String::from("b"),
+                here: 4,
+            },
+            Token::Colon { here: 5 },
+            Token::Ident {
+                value: String::from("i64"),
+                here: 7,
+            },
+            Token::Pipe { here: 10 },
+            Token::Ident {
+                value: String::from("a"),
+                here: 12,
+            },
+            Token::Plus { here: 14 },
+            Token::Ident {
+                value: String::from("b"),
+                here: 16,
+            },
+        ];
         let (rest, expr) = parse_expr(&tokens).unwrap();
-        assert_eq!(rest, empty);
+        assert!(rest.is_empty());
         assert_eq!(
             expr,
-            Expression::Number {
-                value: 0,
+            Expression::Closure {
+                params: vec![
+                    ClosureParam {
+                        name: String::from("a"),
+                        ty: None,
+                        here: 1,
+                    },
+                    ClosureParam {
+                        name: String::from("b"),
+                        ty: Some(Type::I64),
+                        here: 4,
+                    },
+                ],
+                ret: None,
+                body: vec![Statement::Return(Expression::Binary {
+                    left: Box::new(Expression::Variable {
+                        name: String::from("a"),
+                        here: 12,
+                    }),
+                    op: Token::Plus { here: 14 },
+                    right: Box::new(Expression::Variable {
+                        name: String::from("b"),
+                        here: 16,
+                    }),
+                })],
                 here: 0,
-                len: 1
             }
         );
     }
 
     #[test]
-    fn binary_expr() {
+    fn slice_expr() {
+        // s[1..], s[..=n]
         let tokens = vec![
-            Token::Number {
-                value: 1,
+            Token::Ident {
+                value: String::from("s"),
                 here: 0,
-                len: 1,
             },
-            Token::Plus { here: 1 },
+            Token::OpenBracket { here: 1 },
             Token::Number {
                 value: 1,
                 here: 2,
                 len: 1,
             },
+            Token::DotDot { here: 3 },
+            Token::CloseBracket { here: 5 },
         ];
-        let empty: &[Token] = &[];
-        let (rest, expr) = parse_expr(&tokens).unwrap();
-        assert_eq!(rest, empty);
+        let (_, expr) = parse_expr(&tokens).unwrap();
         assert_eq!(
             expr,
-            Expression::Binary {
-                left: Box::new(Expression::Number {
-                    value: 1,
+            Expression::Slice {
+                value: Box::new(Expression::Variable {
+                    name: String::from("s"),
                     here: 0,
-                    len: 1
                 }),
-                op: Token::Plus { here: 1 },
-                right: Box::new(Expression::Number {
+                start: Some(Box::new(Expression::Number {
                     value: 1,
                     here: 2,
-                    len: 1
+                    len: 1,
+                })),
+                end: None,
+                inclusive: false,
+                here: 1,
+            }
+        );
+
+        let tokens = vec![
+            Token::Ident {
+                value: String::from("s"),
+                here: 0,
+            },
+            Token::OpenBracket { here: 1 },
+            Token::DotDotEqual { here: 2 },
+            Token::Ident {
+                value: String::from("n"),
+                here: 5,
+            },
+            Token::CloseBracket { here: 6 },
+        ];
+        let (_, expr) = parse_expr(&tokens).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Slice {
+                value: Box::new(Expression::Variable {
+                    name: String::from("s"),
+                    here: 0,
                 }),
+                start: None,
+                end: Some(Box::new(Expression::Variable {
+                    name: String::from("n"),
+                    here: 5,
+                })),
+                inclusive: true,
+                here: 1,
             }
         );
     }
 
     #[test]
-    fn statement_return() {
+    fn try_operator() {
+        // f()?.len
         let tokens = vec![
-            Token::Return { here: 0 },
-            Token::Number {
-                value: 0,
-                here: 7,
-                len: 1,
+            Token::Ident {
+                value: String::from("f"),
+                here: 0,
+            },
+            Token::OpenParen { here: 1 },
+            Token::CloseParen { here: 2 },
+            Token::Question { here: 3 },
+            Token::Dot { here: 4 },
+            Token::Ident {
+                value: String::from("len"),
+                here: 5,
             },
-            Token::Semicolon { here: 8 },
         ];
-
+        let (_, expr) = parse_expr(&tokens).unwrap();
         assert_eq!(
-            parse(&tokens),
-            Ok(vec![Statement::Return(Expression::Number {
-                value: 0,
-                here: 7,
-                len: 1
-            })])
+            expr,
+            Expression::Field {
+                value: Box::new(Expression::Try {
+                    value: Box::new(Expression::Call {
+                        name: String::from("f"),
+                        args: vec![],
+                        here: 0,
+                    }),
+                    here: 3,
+                }),
+                field: String::from("len"),
+                here: 5,
+            }
         );
     }
 
     #[test]
-    fn statement_var() {
+    fn module_paths() {
+        // mod a; a::b::P {}
         let tokens = vec![
-            Token::Var { here: 0 },
+            Token::Mod { here: 0 },
             Token::Ident {
-                value: String::from("hello"),
-                here: 1,
+                value: String::from("a"),
+                here: 4,
             },
-            Token::Equal { here: 2 },
-            Token::Number {
-                value: 0,
-                len: 1,
-                here: 3,
+            Token::Semicolon { here: 5 },
+            Token::Ident {
+                value: String::from("a"),
+                here: 7,
             },
-            Token::Semicolon { here: 4 },
+            Token::ColonColon { here: 8 },
+            Token::Ident {
+                value: String::from("b"),
+                here: 10,
+            },
+            Token::ColonColon { here: 11 },
+            Token::Ident {
+                value: String::from("P"),
+                here: 13,
+            },
+            Token::OpenCurly { here: 15 },
+            Token::CloseCurly { here: 16 },
+            Token::Semicolon { here: 17 },
         ];
 
         assert_eq!(
             parse(&tokens),
-            Ok(vec![Statement::DefineVar {
-                name: String::from("hello"),
-                value: Expression::Number {
-                    value: 0,
-                    len: 1,
-                    here: 3
-                }
-            }])
+            Ok(vec![
+                Statement::Mod {
+                    name: String::from("a"),
+                    here: 4,
+                },
+                Statement::Expression(Expression::StructLiteral {
+                    name: String::from("a::b::P"),
+                    fields: vec![],
+                    here: 13,
+                }),
+            ])
+        );
+        assert_eq!(
+            parse_module(&tokens[3..]),
+            Err(ASTError::UnexpectedToken {
+                here: tokens[3].here(),
+                got: tokens[3].clone(),
+                expected: Token::Fn { here: 0 },
+            })
         );
     }
 }
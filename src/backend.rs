@@ -1,4 +1,5 @@
-use crate::ast;
+use crate::{ast, consteval, escape, monomorph, runtime, source};
+use inkwell::types::BasicType;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -15,28 +16,154 @@ pub enum BackendError {
     TargetMachine,
     #[error("Failed to output IR: {0}")]
     OutputIR(inkwell::support::LLVMString),
+    #[error("Optimization failed: {0}")]
+    Optimize(inkwell::support::LLVMString),
+    #[error("Failed to write the runtime: {0}")]
+    Runtime(std::io::Error),
+    #[error("Failed to run gcc: {0}")]
+    Gcc(std::io::Error),
+    #[error("Linking failed, gcc exited with {0}")]
+    Link(std::process::ExitStatus),
+}
+
+pub struct Options {
+    /// 0 to 3, like `-O` in C compilers
+    pub opt_level: u8,
+    /// Panic instead of wrapping when `+`, `-` or `*` overflow
+    pub overflow_checks: bool,
+    /// Print the stack when panicking, the runtime is then linked against libunwind
+    pub backtrace: bool,
+}
+
+/// Where each variable in scope lives, by name
+type Variables<'ctx> =
+    std::collections::HashMap<String, (inkwell::values::PointerValue<'ctx>, ast::Type)>;
+
+/// A closure whose body is compiled once the function creating it is done
+struct PendingClosure<'ctx> {
+    func: inkwell::values::FunctionValue<'ctx>,
+    /// The struct holding the captured variables, which the function gets a pointer to
+    env: inkwell::types::StructType<'ctx>,
+    captures: Vec<(String, ast::Type)>,
+    params: Vec<ast::ClosureParam>,
+    ret: ast::Type,
+    body: Vec<ast::Statement>,
 }
 
 struct Backend<'ctx> {
     ctx: &'ctx inkwell::context::Context,
+    src: &'ctx source::SourceMap,
     builder: inkwell::builder::Builder<'ctx>,
     module: inkwell::module::Module<'ctx>,
-    variables: std::collections::HashMap<String, inkwell::values::PointerValue<'ctx>>,
+    target_machine: inkwell::targets::TargetMachine,
+    overflow_checks: bool,
+    variables: Variables<'ctx>,
+    /// Named LLVM struct types along with their fields in declaration order
+    structs:
+        std::collections::HashMap<String, (inkwell::types::StructType<'ctx>, Vec<ast::FieldDecl>)>,
+    /// Enums as `{ i64 tag, [N x i64] payload }` along with every variant and the layout of
+    /// its payload
+    enums: std::collections::HashMap<
+        String,
+        (
+            inkwell::types::StructType<'ctx>,
+            Vec<(ast::VariantDecl, inkwell::types::StructType<'ctx>)>,
+        ),
+    >,
+    /// Every `const` and `static`, locals shadow them
+    globals: Variables<'ctx>,
+    /// Every function along with its parameter and return types
+    functions: std::collections::HashMap<
+        String,
+        (
+            inkwell::values::FunctionValue<'ctx>,
+            Vec<ast::Type>,
+            ast::Type,
+        ),
+    >,
+    traits: std::collections::HashMap<String, Vec<ast::TraitMethod>>,
+    /// The vtable of every type cast to a trait object, by the type and the trait
+    vtables: std::collections::HashMap<(String, String), inkwell::values::PointerValue<'ctx>>,
+    closures: Vec<PendingClosure<'ctx>>,
+    /// Closures created so far, they're numbered in order
+    closure_count: usize,
+    /// Which parameters closures passed to each function can outlive the call through
+    escaping: escape::Params,
+    /// Closures in the body being compiled that can't outlive it, by offset. Their environment
+    /// goes on the stack
+    local_closures: std::collections::HashSet<usize>,
+    /// Return type of the function or closure being compiled, `?` returns early with it
+    ret: ast::Type,
+    /// What each enclosing block deferred, innermost last, along with the variables in scope
+    /// at the `defer`
+    deferred: Vec<Vec<(ast::Statement, Variables<'ctx>)>>,
+    /// Where `continue` and `break` jump to in each enclosing loop, innermost last, along with
+    /// how many blocks were deferring statements outside of the loop
+    loops: Vec<(
+        inkwell::basic_block::BasicBlock<'ctx>,
+        inkwell::basic_block::BasicBlock<'ctx>,
+        usize,
+    )>,
 }
 
 impl<'ctx> Backend<'ctx> {
-    fn new(ctx: &'ctx inkwell::context::Context) -> Result<Self, BackendError> {
+    fn new(
+        ctx: &'ctx inkwell::context::Context,
+        src: &'ctx source::SourceMap,
+        options: &Options,
+    ) -> Result<Self, BackendError> {
         inkwell::targets::Target::initialize_native(
             &inkwell::targets::InitializationConfig::default(),
         )
         .map_err(BackendError::Target)?;
 
+        let triple = inkwell::targets::TargetMachine::get_default_triple();
+        let target =
+            inkwell::targets::Target::from_triple(&triple).map_err(BackendError::CompileTarget)?;
+        let target_machine = target
+            .create_target_machine(
+                &triple,
+                "generic",
+                "",
+                match options.opt_level {
+                    0 => inkwell::OptimizationLevel::None,
+                    1 => inkwell::OptimizationLevel::Less,
+                    2 => inkwell::OptimizationLevel::Default,
+                    _ => inkwell::OptimizationLevel::Aggressive,
+                },
+                // gcc links position independent executables by default
+                inkwell::targets::RelocMode::PIC,
+                inkwell::targets::CodeModel::Default,
+            )
+            .ok_or(BackendError::TargetMachine)?;
+
+        let module = ctx.create_module("main");
+        module.set_triple(&triple);
+        module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+
         let builder = ctx.create_builder();
         Ok(Self {
             ctx,
+            src,
             builder,
-            module: ctx.create_module("main"),
+            module,
+            target_machine,
+            overflow_checks: options.overflow_checks,
             variables: std::collections::HashMap::new(),
+            structs: std::collections::HashMap::new(),
+            enums: std::collections::HashMap::new(),
+            functions: std::collections::HashMap::new(),
+            globals: std::collections::HashMap::new(),
+            traits: std::collections::HashMap::new(),
+            vtables: std::collections::HashMap::new(),
+            closures: vec![],
+            closure_count: 0,
+            // `main` returns the process exit code
+            ret: ast::Type::I64,
+            deferred: vec![],
+            loops: vec![],
+            escaping: escape::Params::new(),
+            local_closures: std::collections::HashSet::new(),
         })
     }
 
@@ -49,77 +176,2184 @@ impl<'ctx> Backend<'ctx> {
         self.builder.position_at_end(main_block);
     }
 
+    /// Defines the C `main` calling `fn main`, with the command line as its `[]str`
+    fn main_shim(&mut self) -> Result<(), inkwell::builder::BuilderError> {
+        let i32_type = self.ctx.i32_type();
+        let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+        let main_type = i32_type.fn_type(&[i32_type.into(), ptr_type.into()], false);
+        let main_func = self.module.add_function("main", main_type, None);
+        let main_block = self.ctx.append_basic_block(main_func, "entry");
+        self.builder.position_at_end(main_block);
+
+        let (func, params, _) = self.functions["main"].clone();
+        let mut args = vec![];
+        if !params.is_empty() {
+            let slice_type = self.llvm_type(&params[0]);
+            let jz_args = self.module.add_function(
+                "jz_args",
+                slice_type.fn_type(&[i32_type.into(), ptr_type.into()], false),
+                None,
+            );
+            let argc = main_func.get_nth_param(0).unwrap();
+            let argv = main_func.get_nth_param(1).unwrap();
+            let slice = self
+                .builder
+                .build_call(jz_args, &[argc.into(), argv.into()], "args")?
+                .try_as_basic_value()
+                .left()
+                .unwrap();
+            args.push(slice.into());
+        }
+        let code = self
+            .builder
+            .build_call(func, &args, "code")?
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        self.builder.build_return(Some(&code))?;
+        Ok(())
+    }
+
+    fn declare_types(&mut self, program: &[ast::Statement]) {
+        // Create every type up front so fields can refer to types declared later
+        let mut decls = std::collections::HashMap::new();
+        for st in program {
+            match st {
+                ast::Statement::Struct { name, fields, .. } => {
+                    let t = self.ctx.opaque_struct_type(name);
+                    self.structs.insert(name.to_string(), (t, fields.clone()));
+                    decls.insert(name.as_str(), st);
+                }
+                ast::Statement::Enum { name, .. } => {
+                    let t = self.ctx.opaque_struct_type(name);
+                    self.enums.insert(name.to_string(), (t, vec![]));
+                    decls.insert(name.as_str(), st);
+                }
+                _ => {}
+            }
+        }
+        for name in decls.keys() {
+            self.define_type(name, &decls);
+        }
+    }
+
+    /// Sets the body of `name` after the bodies of every type it holds by value, since the
+    /// size of an enum's payload depends on them
+    fn define_type(
+        &mut self,
+        name: &str,
+        decls: &std::collections::HashMap<&str, &ast::Statement>,
+    ) {
+        match decls[name] {
+            ast::Statement::Struct { fields, .. } => {
+                let t = self.structs[name].0;
+                if !t.is_opaque() {
+                    return;
+                }
+                for f in fields {
+                    self.define_dependencies(&f.ty, decls);
+                }
+                let field_types: Vec<_> = fields.iter().map(|f| self.llvm_type(&f.ty)).collect();
+                t.set_body(&field_types, false);
+            }
+            ast::Statement::Enum { variants, .. } => {
+                let t = self.enums[name].0;
+                if !t.is_opaque() {
+                    return;
+                }
+                for ty in variants.iter().flat_map(|v| &v.payload) {
+                    self.define_dependencies(ty, decls);
+                }
+                let payloads: Vec<_> = variants
+                    .iter()
+                    .map(|v| {
+                        let types: Vec<_> = v.payload.iter().map(|t| self.llvm_type(t)).collect();
+                        (v.clone(), self.ctx.struct_type(&types, false))
+                    })
+                    .collect();
+                let target_data = self.target_machine.get_target_data();
+                let size = payloads
+                    .iter()
+                    .map(|(_, p)| target_data.get_abi_size(p))
+                    .max()
+                    .unwrap_or(0);
+                // Storing the payload as i64 words keeps it aligned for every type we have
+                let i64_type = self.ctx.i64_type();
+                t.set_body(
+                    &[
+                        i64_type.into(),
+                        i64_type.array_type(size.div_ceil(8) as u32).into(),
+                    ],
+                    false,
+                );
+                self.enums.get_mut(name).unwrap().1 = payloads;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn define_dependencies(
+        &mut self,
+        ty: &ast::Type,
+        decls: &std::collections::HashMap<&str, &ast::Statement>,
+    ) {
+        match ty {
+            ast::Type::Array(elem, _) => self.define_dependencies(elem, decls),
+            ast::Type::Tuple(elems) => {
+                for elem in elems {
+                    self.define_dependencies(elem, decls);
+                }
+            }
+            ast::Type::Named(name) => self.define_type(name, decls),
+            _ => {}
+        }
+    }
+
+    fn function_type(
+        &self,
+        params: &[ast::Type],
+        ret: &ast::Type,
+    ) -> inkwell::types::FunctionType<'ctx> {
+        let param_types: Vec<inkwell::types::BasicMetadataTypeEnum> =
+            params.iter().map(|t| self.llvm_type(t).into()).collect();
+        match ret {
+            ast::Type::Unit => self.ctx.void_type().fn_type(&param_types, false),
+            ret => self.llvm_type(ret).fn_type(&param_types, false),
+        }
+    }
+
+    fn declare_functions(&mut self, program: &[ast::Statement]) {
+        for builtin in runtime::builtins() {
+            let Some(symbol) = builtin.symbol else {
+                continue;
+            };
+            let t = self.function_type(&builtin.params, &builtin.ret);
+            let func = self.module.add_function(symbol, t, None);
+            self.functions.insert(
+                builtin.name.to_string(),
+                (func, builtin.params, builtin.ret),
+            );
+        }
+        for st in program {
+            let (ast::Statement::Function {
+                name, params, ret, ..
+            }
+            | ast::Statement::Extern {
+                name, params, ret, ..
+            }) = st
+            else {
+                continue;
+            };
+            let param_types: Vec<_> = params.iter().map(|p| p.ty.clone()).collect();
+            let t = self.function_type(&param_types, ret);
+            // Externs are C's own functions, `main` becomes `jz.main` called by the C entry point
+            let symbol = match st {
+                ast::Statement::Extern { .. } => name.to_string(),
+                _ => monomorph::mangle(name),
+            };
+            let func = self
+                .module
+                .get_function(&symbol)
+                .unwrap_or_else(|| self.module.add_function(&symbol, t, None));
+            self.functions
+                .insert(name.to_string(), (func, param_types, ret.clone()));
+        }
+    }
+
+    fn define_globals(
+        &mut self,
+        program: &[ast::Statement],
+        values: &std::collections::HashMap<String, consteval::Value>,
+    ) {
+        for st in program {
+            let (is_const, name, ty) = match st {
+                ast::Statement::Const { name, ty, .. } => (true, name, ty),
+                ast::Statement::Static { name, ty, .. } => (false, name, ty),
+                _ => continue,
+            };
+            let global = self.module.add_global(self.llvm_type(ty), None, name);
+            global.set_initializer(&self.const_value(&values[name], ty));
+            global.set_constant(is_const);
+            global.set_linkage(inkwell::module::Linkage::Internal);
+            self.globals
+                .insert(name.to_string(), (global.as_pointer_value(), ty.clone()));
+        }
+    }
+
+    fn const_value(
+        &self,
+        value: &consteval::Value,
+        ty: &ast::Type,
+    ) -> inkwell::values::BasicValueEnum<'ctx> {
+        use consteval::Value;
+        match (value, ty) {
+            (Value::I32(v), _) => self.ctx.i32_type().const_int(*v as u64, true).into(),
+            (Value::I64(v), _) => self.ctx.i64_type().const_int(*v as u64, true).into(),
+            (Value::F32(v), _) => self.ctx.f32_type().const_float(f64::from(*v)).into(),
+            (Value::F64(v), _) => self.ctx.f64_type().const_float(*v).into(),
+            (Value::Char(c), _) => self.ctx.i32_type().const_int(u64::from(*c), false).into(),
+            (Value::U8(v), _) => self.ctx.i8_type().const_int(u64::from(*v), false).into(),
+            (Value::Str(value), _) => self.const_str(value).into(),
+            (Value::Null, _) => self
+                .ctx
+                .ptr_type(inkwell::AddressSpace::default())
+                .const_null()
+                .into(),
+            (Value::Array(values), ast::Type::Array(elem, _)) => {
+                use inkwell::values::BasicValue;
+                let values: Vec<_> = values.iter().map(|v| self.const_value(v, elem)).collect();
+                // Every element has the same type, `const_array` just needs it spelled out
+                match self.llvm_type(elem) {
+                    inkwell::types::BasicTypeEnum::IntType(t) => t.const_array(
+                        &values
+                            .iter()
+                            .map(|v| v.into_int_value())
+                            .collect::<Vec<_>>(),
+                    ),
+                    inkwell::types::BasicTypeEnum::FloatType(t) => t.const_array(
+                        &values
+                            .iter()
+                            .map(|v| v.into_float_value())
+                            .collect::<Vec<_>>(),
+                    ),
+                    inkwell::types::BasicTypeEnum::PointerType(t) => t.const_array(
+                        &values
+                            .iter()
+                            .map(|v| v.into_pointer_value())
+                            .collect::<Vec<_>>(),
+                    ),
+                    inkwell::types::BasicTypeEnum::ArrayType(t) => t.const_array(
+                        &values
+                            .iter()
+                            .map(|v| v.into_array_value())
+                            .collect::<Vec<_>>(),
+                    ),
+                    inkwell::types::BasicTypeEnum::StructType(t) => t.const_array(
+                        &values
+                            .iter()
+                            .map(|v| v.into_struct_value())
+                            .collect::<Vec<_>>(),
+                    ),
+                    inkwell::types::BasicTypeEnum::VectorType(_) => unreachable!(),
+                }
+                .as_basic_value_enum()
+            }
+            (Value::Struct(values), ast::Type::Named(name)) => {
+                let (t, fields) = &self.structs[name];
+                let values: Vec<_> = values
+                    .iter()
+                    .zip(fields)
+                    .map(|(v, f)| self.const_value(v, &f.ty))
+                    .collect();
+                t.const_named_struct(&values).into()
+            }
+            (Value::Struct(values), ast::Type::Tuple(elems)) => {
+                let values: Vec<_> = values
+                    .iter()
+                    .zip(elems)
+                    .map(|(v, ty)| self.const_value(v, ty))
+                    .collect();
+                self.ctx.const_struct(&values, false).into()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn lookup_variable(&self, name: &str) -> (inkwell::values::PointerValue<'ctx>, ast::Type) {
+        self.variables
+            .get(name)
+            .or_else(|| self.globals.get(name))
+            .cloned()
+            .unwrap()
+    }
+
+    /// Allocas in the entry block are only allocated once, even when the code that needs
+    /// them runs in a loop
+    fn build_entry_alloca<T: inkwell::types::BasicType<'ctx>>(
+        &self,
+        t: T,
+        name: &str,
+    ) -> Result<inkwell::values::PointerValue<'ctx>, inkwell::builder::BuilderError> {
+        let entry = self.current_function().get_first_basic_block().unwrap();
+        let builder = self.ctx.create_builder();
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(t, name)
+    }
+
+    fn current_function(&self) -> inkwell::values::FunctionValue<'ctx> {
+        self.builder
+            .get_insert_block()
+            .and_then(|b| b.get_parent())
+            .unwrap()
+    }
+
+    fn llvm_type(&self, ty: &ast::Type) -> inkwell::types::BasicTypeEnum<'ctx> {
+        match ty {
+            ast::Type::I32 => self.ctx.i32_type().into(),
+            ast::Type::I64 => self.ctx.i64_type().into(),
+            ast::Type::F32 => self.ctx.f32_type().into(),
+            ast::Type::F64 => self.ctx.f64_type().into(),
+            ast::Type::Char => self.ctx.i32_type().into(),
+            ast::Type::U8 => self.ctx.i8_type().into(),
+            ast::Type::Unit => self.ctx.struct_type(&[], false).into(),
+            ast::Type::Array(elem, len) => self.llvm_type(elem).array_type(*len as u32).into(),
+            // Trait objects are fat, `{ data, vtable }`
+            ast::Type::Pointer(inner) if matches!(**inner, ast::Type::Dyn(_)) => {
+                let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+                self.ctx
+                    .struct_type(&[ptr_type.into(), ptr_type.into()], false)
+                    .into()
+            }
+            ast::Type::Pointer(_) => self.ctx.ptr_type(inkwell::AddressSpace::default()).into(),
+            // Slices and strings are `{ pointer, length }`
+            ast::Type::Slice(_) | ast::Type::Str => {
+                let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+                self.ctx
+                    .struct_type(&[ptr_type.into(), self.ctx.i64_type().into()], false)
+                    .into()
+            }
+            // Function values are `{ function, environment }`, the function taking the
+            // environment before its parameters
+            ast::Type::Function(..) => {
+                let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+                self.ctx
+                    .struct_type(&[ptr_type.into(), ptr_type.into()], false)
+                    .into()
+            }
+            ast::Type::Tuple(elems) => {
+                let elem_types: Vec<_> = elems.iter().map(|e| self.llvm_type(e)).collect();
+                self.ctx.struct_type(&elem_types, false).into()
+            }
+            ast::Type::Named(name) => match self.structs.get(name) {
+                Some((t, _)) => (*t).into(),
+                None => self.enums[name].0.into(),
+            },
+            // Replaced by their instances when monomorphizing
+            ast::Type::Generic(..) => unreachable!(),
+            // Only exists behind a pointer
+            ast::Type::Dyn(_) => unreachable!(),
+        }
+    }
+
+    /// The function `name` as a function value, through a wrapper that ignores the
+    /// environment pointer
+    fn function_value(
+        &mut self,
+        name: &str,
+    ) -> Result<(inkwell::values::BasicValueEnum<'ctx>, ast::Type), inkwell::builder::BuilderError>
+    {
+        let (func, params, ret) = self.functions[name].clone();
+        let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+        let symbol = format!("{}.value", monomorph::mangle(name));
+        let wrapper = match self.module.get_function(&symbol) {
+            Some(wrapper) => wrapper,
+            None => {
+                let params: Vec<inkwell::types::BasicMetadataTypeEnum> =
+                    std::iter::once(ptr_type.into())
+                        .chain(func.get_params().iter().map(|p| p.get_type().into()))
+                        .collect();
+                let wrapper_type = match func.get_type().get_return_type() {
+                    Some(t) => t.fn_type(&params, false),
+                    None => self.ctx.void_type().fn_type(&params, false),
+                };
+                let wrapper = self.module.add_function(
+                    &symbol,
+                    wrapper_type,
+                    Some(inkwell::module::Linkage::Private),
+                );
+                let outer = self.builder.get_insert_block();
+                let entry = self.ctx.append_basic_block(wrapper, "entry");
+                self.builder.position_at_end(entry);
+                let args: Vec<inkwell::values::BasicMetadataValueEnum> =
+                    wrapper.get_param_iter().skip(1).map(|v| v.into()).collect();
+                match self
+                    .builder
+                    .build_call(func, &args, "call")?
+                    .try_as_basic_value()
+                    .left()
+                {
+                    Some(value) => self.builder.build_return(Some(&value))?,
+                    None => self.builder.build_return(None)?,
+                };
+                if let Some(block) = outer {
+                    self.builder.position_at_end(block);
+                }
+                wrapper
+            }
+        };
+        let value = self.ctx.const_struct(
+            &[
+                wrapper.as_global_value().as_pointer_value().into(),
+                ptr_type.const_null().into(),
+            ],
+            false,
+        );
+        Ok((
+            value.into(),
+            ast::Type::Function(params.into(), Box::new(ret)),
+        ))
+    }
+
+    /// Copies the variables the closure uses into its environment, and queues its body to be
+    /// compiled as a function of its own
+    fn closure(
+        &mut self,
+        params: Vec<ast::ClosureParam>,
+        ret: ast::Type,
+        body: Vec<ast::Statement>,
+        here: usize,
+    ) -> Result<(inkwell::values::BasicValueEnum<'ctx>, ast::Type), inkwell::builder::BuilderError>
+    {
+        let mut names = vec![];
+        body_names(&body, &mut names);
+        let captures: Vec<_> = names
+            .into_iter()
+            .filter(|name| params.iter().all(|p| p.name != *name))
+            .filter_map(|name| {
+                let (_, ty) = self.variables.get(&name)?;
+                Some((name, ty.clone()))
+            })
+            .collect();
+        let field_types: Vec<_> = captures.iter().map(|(_, ty)| self.llvm_type(ty)).collect();
+        let env_type = self.ctx.struct_type(&field_types, false);
+        let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+        // A closure that can outlive the function creating it has its environment on the heap,
+        // where it's never freed
+        let env = if captures.is_empty() {
+            ptr_type.const_null()
+        } else {
+            let env = if self.local_closures.contains(&here) {
+                self.build_entry_alloca(env_type, "env")?
+            } else {
+                let alloc = self.functions["alloc"].0;
+                let size = env_type.size_of().unwrap();
+                self.builder
+                    .build_call(alloc, &[size.into()], "env")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_pointer_value()
+            };
+            for (i, (name, ty)) in captures.iter().enumerate() {
+                let (ptr, _) = self.variables[name].clone();
+                let value = self.builder.build_load(self.llvm_type(ty), ptr, name)?;
+                let field = self
+                    .builder
+                    .build_struct_gep(env_type, env, i as u32, name)?;
+                self.builder.build_store(field, value)?;
+            }
+            env
+        };
+
+        let param_types: Vec<_> = params.iter().map(|p| p.ty.clone().unwrap()).collect();
+        let fn_params: Vec<_> = std::iter::once(ast::Type::Pointer(Box::new(ast::Type::Unit)))
+            .chain(param_types.iter().cloned())
+            .collect();
+        let func = self.module.add_function(
+            &format!("closure.{}", self.closure_count),
+            self.function_type(&fn_params, &ret),
+            Some(inkwell::module::Linkage::Private),
+        );
+        self.closure_count += 1;
+        self.closures.push(PendingClosure {
+            func,
+            env: env_type,
+            captures,
+            params,
+            ret: ret.clone(),
+            body,
+        });
+
+        let ty = ast::Type::Function(param_types.into(), Box::new(ret));
+        let value = self.llvm_type(&ty).into_struct_type().get_undef();
+        let value = self
+            .builder
+            .build_insert_value(
+                value,
+                func.as_global_value().as_pointer_value(),
+                0,
+                "function",
+            )?
+            .into_struct_value();
+        let value = self
+            .builder
+            .build_insert_value(value, env, 1, "closure")?
+            .into_struct_value();
+        Ok((value.into(), ty))
+    }
+
+    /// The table of `ty`'s implementations of the methods of `trait_name`, in the order the
+    /// trait declares them
+    fn vtable(
+        &mut self,
+        ty: &ast::Type,
+        trait_name: &str,
+    ) -> Result<inkwell::values::PointerValue<'ctx>, inkwell::builder::BuilderError> {
+        let key = (ty.to_string(), trait_name.to_string());
+        if let Some(vtable) = self.vtables.get(&key) {
+            return Ok(*vtable);
+        }
+        let mut entries = vec![];
+        for method in self.traits[trait_name].clone() {
+            let (func, ..) = self.functions[&format!("{ty}::{}", method.name)].clone();
+            let func = match method.params[0].ty {
+                ast::Type::Pointer(_) => func,
+                _ => self.dyn_shim(func, ty, &method)?,
+            };
+            entries.push(func.as_global_value().as_pointer_value());
+        }
+        let init = self
+            .ctx
+            .ptr_type(inkwell::AddressSpace::default())
+            .const_array(&entries);
+        let name = monomorph::mangle(&format!("vtable.{ty}.{trait_name}"));
+        let global = self.module.add_global(init.get_type(), None, &name);
+        global.set_initializer(&init);
+        global.set_constant(true);
+        global.set_linkage(inkwell::module::Linkage::Private);
+        let vtable = global.as_pointer_value();
+        self.vtables.insert(key, vtable);
+        Ok(vtable)
+    }
+
+    /// Wraps a method taking `self` by value so it can be called through a trait object,
+    /// which only has a pointer to it
+    fn dyn_shim(
+        &mut self,
+        func: inkwell::values::FunctionValue<'ctx>,
+        ty: &ast::Type,
+        method: &ast::TraitMethod,
+    ) -> Result<inkwell::values::FunctionValue<'ctx>, inkwell::builder::BuilderError> {
+        let subst = std::collections::HashMap::from([(String::from("Self"), ty.clone())]);
+        let params: Vec<_> = std::iter::once(ast::Type::Pointer(Box::new(ast::Type::Unit)))
+            .chain(method.params[1..].iter().map(|p| p.ty.substitute(&subst)))
+            .collect();
+        let shim_type = self.function_type(&params, &method.ret.substitute(&subst));
+        let name = monomorph::mangle(&format!("{ty}::{}.dyn", method.name));
+        let shim =
+            self.module
+                .add_function(&name, shim_type, Some(inkwell::module::Linkage::Private));
+        let outer = self.builder.get_insert_block();
+        let entry = self.ctx.append_basic_block(shim, "entry");
+        self.builder.position_at_end(entry);
+        let data = shim.get_nth_param(0).unwrap().into_pointer_value();
+        let receiver = self.builder.build_load(self.llvm_type(ty), data, "self")?;
+        let args: Vec<inkwell::values::BasicMetadataValueEnum> = std::iter::once(receiver)
+            .chain(shim.get_param_iter().skip(1))
+            .map(|v| v.into())
+            .collect();
+        match self
+            .builder
+            .build_call(func, &args, "call")?
+            .try_as_basic_value()
+            .left()
+        {
+            Some(value) => self.builder.build_return(Some(&value))?,
+            None => self.builder.build_return(None)?,
+        };
+        if let Some(block) = outer {
+            self.builder.position_at_end(block);
+        }
+        Ok(shim)
+    }
+
+    /// The type a `*ty` steps over in pointer arithmetic, `*()` moves one byte at a time
+    fn pointee_type(&self, ty: &ast::Type) -> inkwell::types::BasicTypeEnum<'ctx> {
+        match ty {
+            ast::Type::Unit => self.ctx.i8_type().into(),
+            ty => self.llvm_type(ty),
+        }
+    }
+
+    /// Reports a panic at `here` through the runtime and aborts. `message` is a printf
+    /// format string for `args`
+    fn panic(
+        &mut self,
+        here: usize,
+        message: &str,
+        args: &[inkwell::values::BasicMetadataValueEnum<'ctx>],
+    ) -> Result<(), inkwell::builder::BuilderError> {
+        let jz_panic = self.module.get_function("jz_panic").unwrap_or_else(|| {
+            let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+            let t = self
+                .ctx
+                .void_type()
+                .fn_type(&[ptr_type.into(), ptr_type.into()], true);
+            self.module.add_function("jz_panic", t, None)
+        });
+
+        let (line, column) = self.src.get_position(here);
+        let file = self.src.path(here).unwrap_or("<input>");
+        let location = self
+            .builder
+            .build_global_string_ptr(&format!("./{file}:{line}:{column}"), "location")?;
+        let format = self.builder.build_global_string_ptr(message, "panic")?;
+
+        let mut call_args = vec![
+            location.as_pointer_value().into(),
+            format.as_pointer_value().into(),
+        ];
+        call_args.extend_from_slice(args);
+        self.builder.build_call(jz_panic, &call_args, "")?;
+        self.builder.build_unreachable()?;
+        Ok(())
+    }
+
+    /// A string literal, its bytes live in a private constant global without a NUL terminator
+    fn const_str(&self, value: &str) -> inkwell::values::StructValue<'ctx> {
+        let bytes = self.ctx.const_string(value.as_bytes(), false);
+        let global = self.module.add_global(bytes.get_type(), None, "str");
+        global.set_initializer(&bytes);
+        global.set_constant(true);
+        global.set_linkage(inkwell::module::Linkage::Private);
+        global.set_unnamed_addr(true);
+        let len = self.ctx.i64_type().const_int(value.len() as u64, false);
+        self.ctx
+            .const_struct(&[global.as_pointer_value().into(), len.into()], false)
+    }
+
+    fn check_bounds(
+        &mut self,
+        index: inkwell::values::IntValue<'ctx>,
+        len: inkwell::values::IntValue<'ctx>,
+        here: usize,
+    ) -> Result<(), inkwell::builder::BuilderError> {
+        if let (Some(index), Some(len)) = (
+            index.get_zero_extended_constant(),
+            len.get_zero_extended_constant(),
+        ) && index < len
+        {
+            return Ok(());
+        }
+        let func = self.current_function();
+        let in_bounds = self.ctx.append_basic_block(func, "in_bounds");
+        let out_of_bounds = self.ctx.append_basic_block(func, "out_of_bounds");
+
+        // Unsigned, so negative indices fail the check as well
+        let ok = self.builder.build_int_compare(
+            inkwell::IntPredicate::ULT,
+            index,
+            len,
+            "bounds_check",
+        )?;
+        self.builder
+            .build_conditional_branch(ok, in_bounds, out_of_bounds)?;
+
+        self.builder.position_at_end(out_of_bounds);
+        self.panic(
+            here,
+            "index out of bounds: the len is %ld but the index is %ld",
+            &[len.into(), index.into()],
+        )?;
+        self.builder.position_at_end(in_bounds);
+        Ok(())
+    }
+
+    /// The pointer and length of the slice or string stored at `ptr`
+    fn load_slice(
+        &mut self,
+        ptr: inkwell::values::PointerValue<'ctx>,
+        ty: &ast::Type,
+    ) -> Result<
+        (
+            inkwell::values::PointerValue<'ctx>,
+            inkwell::values::IntValue<'ctx>,
+        ),
+        inkwell::builder::BuilderError,
+    > {
+        let slice = self
+            .builder
+            .build_load(self.llvm_type(ty), ptr, "slice")?
+            .into_struct_value();
+        let base = self
+            .builder
+            .build_extract_value(slice, 0, "ptr")?
+            .into_pointer_value();
+        let len = self
+            .builder
+            .build_extract_value(slice, 1, "len")?
+            .into_int_value();
+        Ok((base, len))
+    }
+
+    /// `value[start..end]`, panicking unless `start <= end <= len`
+    fn slice(
+        &mut self,
+        value: ast::Expression,
+        start: Option<Box<ast::Expression>>,
+        end: Option<Box<ast::Expression>>,
+        inclusive: bool,
+        here: usize,
+    ) -> Result<(inkwell::values::BasicValueEnum<'ctx>, ast::Type), inkwell::builder::BuilderError>
+    {
+        let i64_type = self.ctx.i64_type();
+        let (ptr, ty) = self.eval_place(value)?;
+        // Pointers carry no length, their slices are only checked for `start <= end`
+        let (base, len, slice_ty) = match &ty {
+            ast::Type::Array(elem, len) => (
+                ptr,
+                Some(i64_type.const_int(*len, false)),
+                ast::Type::Slice(elem.clone()),
+            ),
+            ast::Type::Slice(_) | ast::Type::Str => {
+                let (base, len) = self.load_slice(ptr, &ty)?;
+                (base, Some(len), ty.clone())
+            }
+            ast::Type::Pointer(elem) => {
+                let base = self
+                    .builder
+                    .build_load(
+                        self.ctx.ptr_type(inkwell::AddressSpace::default()),
+                        ptr,
+                        "base",
+                    )?
+                    .into_pointer_value();
+                (base, None, ast::Type::Slice(elem.clone()))
+            }
+            _ => unreachable!(),
+        };
+        let start = match start {
+            Some(start) => self.eval_expression(*start)?.0.into_int_value(),
+            None => i64_type.const_zero(),
+        };
+        let end = match end {
+            Some(end) => {
+                let end = self.eval_expression(*end)?.0.into_int_value();
+                if inclusive {
+                    self.builder
+                        .build_int_add(end, i64_type.const_int(1, false), "end")?
+                } else {
+                    end
+                }
+            }
+            None => len.unwrap(),
+        };
+
+        let func = self.current_function();
+        let ordered = self.ctx.append_basic_block(func, "ordered");
+        let misordered = self.ctx.append_basic_block(func, "misordered");
+        // Signed, so a negative start is caught by `end <= len` or here
+        let ok = self.builder.build_int_compare(
+            inkwell::IntPredicate::SLE,
+            start,
+            end,
+            "slice_order",
+        )?;
+        let non_negative = self.builder.build_int_compare(
+            inkwell::IntPredicate::SGE,
+            start,
+            i64_type.const_zero(),
+            "slice_start",
+        )?;
+        let ok = self.builder.build_and(ok, non_negative, "slice_check")?;
+        self.builder
+            .build_conditional_branch(ok, ordered, misordered)?;
+        self.builder.position_at_end(misordered);
+        self.panic(
+            here,
+            "slice index starts at %ld but ends at %ld",
+            &[start.into(), end.into()],
+        )?;
+        self.builder.position_at_end(ordered);
+
+        if let Some(len) = len {
+            let in_bounds = self.ctx.append_basic_block(func, "in_bounds");
+            let out_of_bounds = self.ctx.append_basic_block(func, "out_of_bounds");
+            let ok = self.builder.build_int_compare(
+                inkwell::IntPredicate::ULE,
+                end,
+                len,
+                "slice_end",
+            )?;
+            self.builder
+                .build_conditional_branch(ok, in_bounds, out_of_bounds)?;
+            self.builder.position_at_end(out_of_bounds);
+            self.panic(
+                here,
+                "range end index %ld out of range for slice of length %ld",
+                &[end.into(), len.into()],
+            )?;
+            self.builder.position_at_end(in_bounds);
+        }
+
+        let elem_type = self.llvm_type(&slice_elem(&slice_ty));
+        let base = match ty {
+            ast::Type::Array(..) => unsafe {
+                self.builder.build_in_bounds_gep(
+                    self.llvm_type(&ty),
+                    base,
+                    &[i64_type.const_zero(), start],
+                    "slice",
+                )?
+            },
+            _ => unsafe {
+                self.builder
+                    .build_in_bounds_gep(elem_type, base, &[start], "slice")?
+            },
+        };
+        let len = self.builder.build_int_sub(end, start, "len")?;
+        let slice = self.llvm_type(&slice_ty).into_struct_type().get_undef();
+        let slice = self
+            .builder
+            .build_insert_value(slice, base, 0, "ptr")?
+            .into_struct_value();
+        let slice = self
+            .builder
+            .build_insert_value(slice, len, 1, "slice")?
+            .into_struct_value();
+        Ok((slice.into(), slice_ty))
+    }
+
+    /// Returns a pointer to the memory `value` lives in, values that aren't places get
+    /// spilled to the stack
+    fn eval_place(
+        &mut self,
+        value: ast::Expression,
+    ) -> Result<(inkwell::values::PointerValue<'ctx>, ast::Type), inkwell::builder::BuilderError>
+    {
+        match value {
+            ast::Expression::Variable { name, .. } => Ok(self.lookup_variable(&name)),
+            ast::Expression::Index { array, index, here } => {
+                let (ptr, ty) = self.eval_place(*array)?;
+                let index = self.eval_expression(*index)?.0.into_int_value();
+                match ty {
+                    ast::Type::Array(elem, len) => {
+                        let len_value = self.ctx.i64_type().const_int(len, false);
+                        self.check_bounds(index, len_value, here)?;
+                        let array_type = self.llvm_type(&ast::Type::Array(elem.clone(), len));
+                        let zero = self.ctx.i64_type().const_zero();
+                        let ptr = unsafe {
+                            self.builder.build_in_bounds_gep(
+                                array_type,
+                                ptr,
+                                &[zero, index],
+                                "elem",
+                            )?
+                        };
+                        Ok((ptr, *elem))
+                    }
+                    // Pointers carry no length, so there is nothing to check against
+                    ast::Type::Pointer(elem) => {
+                        let base = self
+                            .builder
+                            .build_load(
+                                self.ctx.ptr_type(inkwell::AddressSpace::default()),
+                                ptr,
+                                "base",
+                            )?
+                            .into_pointer_value();
+                        let elem_type = self.llvm_type(&elem);
+                        let ptr =
+                            unsafe { self.builder.build_gep(elem_type, base, &[index], "elem")? };
+                        Ok((ptr, *elem))
+                    }
+                    ast::Type::Slice(_) | ast::Type::Str => {
+                        let (base, len) = self.load_slice(ptr, &ty)?;
+                        self.check_bounds(index, len, here)?;
+                        let elem = slice_elem(&ty);
+                        let elem_type = self.llvm_type(&elem);
+                        let ptr = unsafe {
+                            self.builder
+                                .build_in_bounds_gep(elem_type, base, &[index], "elem")?
+                        };
+                        Ok((ptr, elem))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            ast::Expression::Field { value, field, .. } => {
+                let (mut ptr, mut ty) = self.eval_place(*value)?;
+                if let ast::Type::Pointer(inner) = ty {
+                    ptr = self
+                        .builder
+                        .build_load(
+                            self.ctx.ptr_type(inkwell::AddressSpace::default()),
+                            ptr,
+                            "deref",
+                        )?
+                        .into_pointer_value();
+                    ty = *inner;
+                }
+                match &ty {
+                    ast::Type::Slice(_) | ast::Type::Str if field == "len" => {
+                        let slice_type = self.llvm_type(&ty).into_struct_type();
+                        let len_ptr = self.builder.build_struct_gep(slice_type, ptr, 1, "len")?;
+                        return Ok((len_ptr, ast::Type::I64));
+                    }
+                    // Arrays don't store their length, so it's spilled like any other value
+                    ast::Type::Array(_, len) if field == "len" => {
+                        let len = self.ctx.i64_type().const_int(*len, false);
+                        let len_ptr = self.build_entry_alloca(len.get_type(), "len")?;
+                        self.builder.build_store(len_ptr, len)?;
+                        return Ok((len_ptr, ast::Type::I64));
+                    }
+                    _ => {}
+                }
+                if let ast::Type::Tuple(elems) = &ty {
+                    let index: u32 = field.parse().unwrap();
+                    let tuple_type = self.llvm_type(&ty).into_struct_type();
+                    let elem_ptr = self
+                        .builder
+                        .build_struct_gep(tuple_type, ptr, index, "elem")?;
+                    return Ok((elem_ptr, elems[index as usize].clone()));
+                }
+                let ast::Type::Named(name) = ty else {
+                    unreachable!()
+                };
+                let (struct_type, fields) = &self.structs[&name];
+                let index = fields.iter().position(|f| f.name == field).unwrap();
+                let field_type = fields[index].ty.clone();
+                let field_ptr =
+                    self.builder
+                        .build_struct_gep(*struct_type, ptr, index as u32, &field)?;
+                Ok((field_ptr, field_type))
+            }
+            ast::Expression::Deref { value, .. } => {
+                let (ptr, ty) = self.eval_expression(*value)?;
+                let ast::Type::Pointer(inner) = ty else {
+                    unreachable!()
+                };
+                Ok((ptr.into_pointer_value(), *inner))
+            }
+            value => {
+                let (value, ty) = self.eval_expression(value)?;
+                let ptr = self.build_entry_alloca(value.get_type(), "tmp")?;
+                self.builder.build_store(ptr, value)?;
+                Ok((ptr, ty))
+            }
+        }
+    }
+
     fn eval_expression(
         &mut self,
         value: ast::Expression,
-    ) -> Result<inkwell::values::IntValue<'ctx>, inkwell::builder::BuilderError> {
-        let t = self.ctx.i64_type();
+    ) -> Result<(inkwell::values::BasicValueEnum<'ctx>, ast::Type), inkwell::builder::BuilderError>
+    {
+        use inkwell::values::BasicValueEnum;
         match value {
+            ast::Expression::Variable { name, .. }
+                if !self.variables.contains_key(&name) && !self.globals.contains_key(&name) =>
+            {
+                self.function_value(&name)
+            }
             ast::Expression::Variable { name, .. } => {
-                let ptr = self.variables.get(&name).unwrap();
-                let loaded = self
-                    .builder
-                    .build_load(t, *ptr, &name)
-                    .unwrap()
-                    .into_int_value();
-                Ok(loaded)
+                let (ptr, ty) = self.lookup_variable(&name);
+                let value = self.builder.build_load(self.llvm_type(&ty), ptr, &name)?;
+                Ok((value, ty))
             }
-            ast::Expression::Number { value, .. } => Ok(t.const_int(value, false)),
+            ast::Expression::Number { value, .. } => Ok((
+                self.ctx.i64_type().const_int(value, false).into(),
+                ast::Type::I64,
+            )),
+            ast::Expression::Float { value, .. } => Ok((
+                self.ctx.f64_type().const_float(value).into(),
+                ast::Type::F64,
+            )),
+            ast::Expression::Char { value, .. } => Ok((
+                self.ctx
+                    .i32_type()
+                    .const_int(u64::from(value), false)
+                    .into(),
+                ast::Type::Char,
+            )),
+            ast::Expression::Str { value, .. } => {
+                Ok((self.const_str(&value).into(), ast::Type::Str))
+            }
+            ast::Expression::Slice {
+                value,
+                start,
+                end,
+                inclusive,
+                here,
+            } => self.slice(*value, start, end, inclusive, here),
+            ast::Expression::Null { .. } => Ok((
+                self.ctx
+                    .ptr_type(inkwell::AddressSpace::default())
+                    .const_null()
+                    .into(),
+                ast::Type::Pointer(Box::new(ast::Type::Unit)),
+            )),
             ast::Expression::Binary { left, op, right } => {
-                let left = self.eval_expression(*left)?;
-                let right = self.eval_expression(*right)?;
+                let (left, ty) = self.eval_expression(*left)?;
+                let (right, _) = self.eval_expression(*right)?;
 
-                match op {
-                    crate::lexer::Token::Plus { .. } => {
-                        self.builder.build_int_add(left, right, "add")
+                match (left, right) {
+                    (BasicValueEnum::PointerValue(left), BasicValueEnum::PointerValue(right)) => {
+                        // Distance in elements, like C
+                        let ast::Type::Pointer(inner) = &ty else {
+                            unreachable!()
+                        };
+                        let i64_type = self.ctx.i64_type();
+                        let size = self
+                            .target_machine
+                            .get_target_data()
+                            .get_abi_size(&self.pointee_type(inner));
+                        let left = self.builder.build_ptr_to_int(left, i64_type, "addr")?;
+                        let right = self.builder.build_ptr_to_int(right, i64_type, "addr")?;
+                        let bytes = self.builder.build_int_sub(left, right, "bytes")?;
+                        let distance = self.builder.build_int_signed_div(
+                            bytes,
+                            i64_type.const_int(size, false),
+                            "distance",
+                        )?;
+                        Ok((distance.into(), ast::Type::I64))
+                    }
+                    (BasicValueEnum::PointerValue(left), BasicValueEnum::IntValue(right)) => {
+                        let ast::Type::Pointer(inner) = &ty else {
+                            unreachable!()
+                        };
+                        let offset = match op {
+                            crate::lexer::Token::Plus { .. } => right,
+                            crate::lexer::Token::Minus { .. } => {
+                                self.builder.build_int_neg(right, "neg")?
+                            }
+                            _ => unreachable!(),
+                        };
+                        let elem_type = self.pointee_type(inner);
+                        let ptr = unsafe {
+                            self.builder
+                                .build_gep(elem_type, left, &[offset], "ptr_offset")?
+                        };
+                        Ok((ptr.into(), ty))
                     }
-                    crate::lexer::Token::Minus { .. } => {
-                        self.builder.build_int_sub(left, right, "sub")
+                    (BasicValueEnum::IntValue(left), BasicValueEnum::IntValue(right))
+                        if self.overflow_checks
+                            && !matches!(op, crate::lexer::Token::Slash { .. }) =>
+                    {
+                        let value = self.checked_int_op(left, &op, right, ty != ast::Type::U8)?;
+                        Ok((value.into(), ty))
                     }
-                    crate::lexer::Token::Star { .. } => {
-                        self.builder.build_int_mul(left, right, "mul")
+                    (BasicValueEnum::IntValue(left), BasicValueEnum::IntValue(right)) => {
+                        if let crate::lexer::Token::Slash { here } = op {
+                            self.check_division(left, right, here, ty != ast::Type::U8)?;
+                        }
+                        let value = match op {
+                            crate::lexer::Token::Plus { .. } => {
+                                self.builder.build_int_add(left, right, "add")
+                            }
+                            crate::lexer::Token::Minus { .. } => {
+                                self.builder.build_int_sub(left, right, "sub")
+                            }
+                            crate::lexer::Token::Star { .. } => {
+                                self.builder.build_int_mul(left, right, "mul")
+                            }
+                            crate::lexer::Token::Slash { .. } if ty == ast::Type::U8 => {
+                                self.builder.build_int_unsigned_div(left, right, "div")
+                            }
+                            crate::lexer::Token::Slash { .. } => {
+                                self.builder.build_int_signed_div(left, right, "div")
+                            }
+                            _ => unreachable!(),
+                        }?;
+                        Ok((value.into(), ty))
                     }
+                    (BasicValueEnum::FloatValue(left), BasicValueEnum::FloatValue(right)) => {
+                        let value = match op {
+                            crate::lexer::Token::Plus { .. } => {
+                                self.builder.build_float_add(left, right, "fadd")
+                            }
+                            crate::lexer::Token::Minus { .. } => {
+                                self.builder.build_float_sub(left, right, "fsub")
+                            }
+                            crate::lexer::Token::Star { .. } => {
+                                self.builder.build_float_mul(left, right, "fmul")
+                            }
+                            crate::lexer::Token::Slash { .. } => {
+                                self.builder.build_float_div(left, right, "fdiv")
+                            }
+                            _ => unreachable!(),
+                        }?;
+                        Ok((value.into(), ty))
+                    }
+                    // The type checker rejects mixed operands
                     _ => unreachable!(),
                 }
             }
+            ast::Expression::Array { elements, .. } => {
+                let len = elements.len();
+                let values = elements
+                    .into_iter()
+                    .map(|e| self.eval_expression(e))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let elem = values[0].1.clone();
+                let t = self.llvm_type(&elem).array_type(len as u32);
+                let mut array = t.get_undef();
+                for (i, (v, _)) in values.into_iter().enumerate() {
+                    array = self
+                        .builder
+                        .build_insert_value(array, v, i as u32, "array")?
+                        .into_array_value();
+                }
+                Ok((array.into(), ast::Type::Array(Box::new(elem), len as u64)))
+            }
+            ast::Expression::Tuple { elements, .. } => {
+                let mut values = vec![];
+                let mut types = vec![];
+                for e in elements {
+                    let (v, ty) = self.eval_expression(e)?;
+                    values.push(v);
+                    types.push(ty);
+                }
+                let ty = ast::Type::Tuple(types);
+                let mut tuple = self.llvm_type(&ty).into_struct_type().get_undef();
+                for (i, v) in values.into_iter().enumerate() {
+                    tuple = self
+                        .builder
+                        .build_insert_value(tuple, v, i as u32, "tuple")?
+                        .into_struct_value();
+                }
+                Ok((tuple.into(), ty))
+            }
+            ast::Expression::StructLiteral { name, fields, .. } => {
+                let (t, decls) = self.structs[&name].clone();
+                let mut value = t.get_undef();
+                for init in fields {
+                    let index = decls.iter().position(|f| f.name == init.name).unwrap() as u32;
+                    let (v, _) = self.eval_expression(init.value)?;
+                    value = self
+                        .builder
+                        .build_insert_value(value, v, index, &init.name)?
+                        .into_struct_value();
+                }
+                Ok((value.into(), ast::Type::Named(name)))
+            }
+            ast::Expression::Variant {
+                ty, variant, args, ..
+            } => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.eval_expression(arg)?.0);
+                }
+                let value = self.build_variant(&ty, &variant, &values)?;
+                Ok((value, ast::Type::Named(ty)))
+            }
+            ast::Expression::Try { value, .. } => self.eval_try(*value),
+            ast::Expression::Match { value, arms, .. } => self.eval_match(*value, arms),
+            value @ (ast::Expression::Index { .. }
+            | ast::Expression::Field { .. }
+            | ast::Expression::Deref { .. }) => {
+                let (ptr, ty) = self.eval_place(value)?;
+                let value = self.builder.build_load(self.llvm_type(&ty), ptr, "load")?;
+                Ok((value, ty))
+            }
+            ast::Expression::AddressOf { value, .. } => {
+                let (ptr, ty) = self.eval_place(*value)?;
+                Ok((ptr.into(), ast::Type::Pointer(Box::new(ty))))
+            }
+            ast::Expression::Call { name, args, .. } if self.variables.contains_key(&name) => {
+                let (ptr, ty) = self.lookup_variable(&name);
+                let ast::Type::Function(params, ret) = ty else {
+                    unreachable!()
+                };
+                let callee = self
+                    .builder
+                    .build_load(
+                        self.llvm_type(&ast::Type::Function(params.clone(), ret.clone())),
+                        ptr,
+                        &name,
+                    )?
+                    .into_struct_value();
+                let func = self
+                    .builder
+                    .build_extract_value(callee, 0, "function")?
+                    .into_pointer_value();
+                let env = self.builder.build_extract_value(callee, 1, "env")?;
+                let params: Vec<_> = std::iter::once(ast::Type::Pointer(Box::new(ast::Type::Unit)))
+                    .chain(params.iter().cloned())
+                    .collect();
+                let fn_type = self.function_type(&params, &ret);
+                let mut values: Vec<inkwell::values::BasicMetadataValueEnum> = vec![env.into()];
+                for arg in args {
+                    values.push(self.eval_expression(arg)?.0.into());
+                }
+                let value = self
+                    .builder
+                    .build_indirect_call(fn_type, func, &values, "call")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap_or_else(|| self.ctx.const_struct(&[], false).into());
+                Ok((value, *ret))
+            }
+            ast::Expression::Closure {
+                params,
+                ret,
+                body,
+                here,
+            } => self.closure(params, ret.unwrap(), body, here),
+            ast::Expression::Call { name, args, here } if name == "panic" => {
+                let (message, _) = self.eval_expression(args[0].clone())?;
+                let message = message.into_struct_value();
+                let ptr = self.builder.build_extract_value(message, 0, "ptr")?;
+                let len = self
+                    .builder
+                    .build_extract_value(message, 1, "len")?
+                    .into_int_value();
+                let len = self
+                    .builder
+                    .build_int_truncate(len, self.ctx.i32_type(), "len")?;
+                self.panic(here, "%.*s", &[len.into(), ptr.into()])?;
+                // Code after the call can't run, it still needs a block to go in
+                let dead = self
+                    .ctx
+                    .append_basic_block(self.current_function(), "after_panic");
+                self.builder.position_at_end(dead);
+                Ok((self.ctx.const_struct(&[], false).into(), ast::Type::Unit))
+            }
+            ast::Expression::Call { name, args, .. } if !self.functions.contains_key(&name) => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.eval_expression(arg)?.0);
+                }
+                Ok((self.eval_builtin(&name, &values)?, ast::Type::I64))
+            }
+            ast::Expression::Call { name, args, .. } => {
+                let (func, _, ret) = self.functions[&name].clone();
+                let mut values: Vec<inkwell::values::BasicMetadataValueEnum> = vec![];
+                for arg in args {
+                    values.push(self.eval_expression(arg)?.0.into());
+                }
+                let value = self
+                    .builder
+                    .build_call(func, &values, "call")?
+                    .try_as_basic_value()
+                    .left()
+                    // Functions returning `()` are void in LLVM
+                    .unwrap_or_else(|| self.ctx.const_struct(&[], false).into());
+                Ok((value, ret))
+            }
+            // Monomorphization turned every other method call into a `Call`
+            ast::Expression::MethodCall {
+                value,
+                method,
+                args,
+                ..
+            } => {
+                let (object, ty) = self.eval_expression(*value)?;
+                let ast::Type::Pointer(inner) = ty else {
+                    unreachable!()
+                };
+                let ast::Type::Dyn(trait_name) = *inner else {
+                    unreachable!()
+                };
+                let (index, decl) = self.traits[&trait_name]
+                    .iter()
+                    .enumerate()
+                    .find(|(_, m)| m.name == method)
+                    .map(|(i, m)| (i, m.clone()))
+                    .unwrap();
+                let object = object.into_struct_value();
+                let data = self.builder.build_extract_value(object, 0, "data")?;
+                let vtable = self
+                    .builder
+                    .build_extract_value(object, 1, "vtable")?
+                    .into_pointer_value();
+                let ptr_type = self.ctx.ptr_type(inkwell::AddressSpace::default());
+                let index = self.ctx.i64_type().const_int(index as u64, false);
+                let slot = unsafe { self.builder.build_gep(ptr_type, vtable, &[index], "slot")? };
+                let func = self
+                    .builder
+                    .build_load(ptr_type, slot, "method")?
+                    .into_pointer_value();
+                // Shims take by-value receivers by pointer too
+                let params: Vec<_> = std::iter::once(ast::Type::Pointer(Box::new(ast::Type::Unit)))
+                    .chain(decl.params[1..].iter().map(|p| p.ty.clone()))
+                    .collect();
+                let fn_type = self.function_type(&params, &decl.ret);
+                let mut values: Vec<inkwell::values::BasicMetadataValueEnum> = vec![data.into()];
+                for arg in args {
+                    values.push(self.eval_expression(arg)?.0.into());
+                }
+                let value = self
+                    .builder
+                    .build_indirect_call(fn_type, func, &values, "call")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap_or_else(|| self.ctx.const_struct(&[], false).into());
+                Ok((value, decl.ret))
+            }
+            ast::Expression::Cast { value, to, .. } => {
+                use inkwell::types::BasicTypeEnum;
+                let (value, from) = self.eval_expression(*value)?;
+                if let ast::Type::Pointer(inner) = &to
+                    && let ast::Type::Dyn(trait_name) = &**inner
+                    && from != to
+                {
+                    let ast::Type::Pointer(ty) = &from else {
+                        unreachable!()
+                    };
+                    let vtable = self.vtable(ty, trait_name)?;
+                    let object = self.llvm_type(&to).into_struct_type().get_undef();
+                    let object = self
+                        .builder
+                        .build_insert_value(object, value, 0, "data")?
+                        .into_struct_value();
+                    let object = self
+                        .builder
+                        .build_insert_value(object, vtable, 1, "object")?
+                        .into_struct_value();
+                    return Ok((object.into(), to));
+                }
+                // Bytes and characters are zero extended
+                let signed = !matches!(from, ast::Type::U8 | ast::Type::Char);
+                let value = match (value, self.llvm_type(&to)) {
+                    (BasicValueEnum::IntValue(v), BasicTypeEnum::IntType(t)) => self
+                        .builder
+                        .build_int_cast_sign_flag(v, t, signed, "icast")?
+                        .into(),
+                    (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(t)) if signed => {
+                        self.builder.build_signed_int_to_float(v, t, "itof")?.into()
+                    }
+                    (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(t)) => self
+                        .builder
+                        .build_unsigned_int_to_float(v, t, "itof")?
+                        .into(),
+                    (BasicValueEnum::FloatValue(v), BasicTypeEnum::IntType(t)) => {
+                        self.float_to_int(v, t, to != ast::Type::U8)?
+                    }
+                    (BasicValueEnum::FloatValue(v), BasicTypeEnum::FloatType(t)) => {
+                        self.builder.build_float_cast(v, t, "fcast")?.into()
+                    }
+                    (BasicValueEnum::PointerValue(v), BasicTypeEnum::PointerType(_)) => v.into(),
+                    (BasicValueEnum::PointerValue(v), BasicTypeEnum::IntType(t)) => {
+                        self.builder.build_ptr_to_int(v, t, "ptoi")?.into()
+                    }
+                    (BasicValueEnum::IntValue(v), BasicTypeEnum::PointerType(t)) => {
+                        self.builder.build_int_to_ptr(v, t, "itop")?.into()
+                    }
+                    // A slice or string to the pointer to its first element
+                    (BasicValueEnum::StructValue(v), BasicTypeEnum::PointerType(_)) => {
+                        self.builder.build_extract_value(v, 0, "ptr")?
+                    }
+                    // Strings and byte slices share their layout, as do trait objects of the
+                    // same trait
+                    (BasicValueEnum::StructValue(v), BasicTypeEnum::StructType(_)) => v.into(),
+                    _ => unreachable!(),
+                };
+                Ok((value, to))
+            }
+        }
+    }
+
+    /// Saturates out of range values and turns NaN into 0 like `consteval`, where plain
+    /// `fptosi` and `fptoui` produce poison
+    fn float_to_int(
+        &mut self,
+        value: inkwell::values::FloatValue<'ctx>,
+        ty: inkwell::types::IntType<'ctx>,
+        signed: bool,
+    ) -> Result<inkwell::values::BasicValueEnum<'ctx>, inkwell::builder::BuilderError> {
+        let name = match signed {
+            true => "llvm.fptosi.sat",
+            false => "llvm.fptoui.sat",
+        };
+        let intrinsic = inkwell::intrinsics::Intrinsic::find(name).unwrap();
+        let func = intrinsic
+            .get_declaration(&self.module, &[ty.into(), value.get_type().into()])
+            .unwrap();
+        Ok(self
+            .builder
+            .build_call(func, &[value.into()], "ftoi")?
+            .try_as_basic_value()
+            .left()
+            .unwrap())
+    }
+
+    fn call_intrinsic(
+        &mut self,
+        name: &str,
+        args: &[inkwell::values::IntValue<'ctx>],
+    ) -> Result<inkwell::values::BasicValueEnum<'ctx>, inkwell::builder::BuilderError> {
+        let intrinsic = inkwell::intrinsics::Intrinsic::find(name).unwrap();
+        let func = intrinsic
+            .get_declaration(&self.module, &[args[0].get_type().into()])
+            .unwrap();
+        let args: Vec<inkwell::values::BasicMetadataValueEnum> =
+            args.iter().map(|a| (*a).into()).collect();
+        Ok(self
+            .builder
+            .build_call(func, &args, name)?
+            .try_as_basic_value()
+            .left()
+            .unwrap())
+    }
+
+    /// Returns the wrapped result of `left op right` along with whether it overflowed
+    fn int_op_with_overflow(
+        &mut self,
+        left: inkwell::values::IntValue<'ctx>,
+        op: &crate::lexer::Token,
+        right: inkwell::values::IntValue<'ctx>,
+        signed: bool,
+    ) -> Result<
+        (
+            inkwell::values::IntValue<'ctx>,
+            inkwell::values::IntValue<'ctx>,
+        ),
+        inkwell::builder::BuilderError,
+    > {
+        let name = match op {
+            crate::lexer::Token::Plus { .. } => "add",
+            crate::lexer::Token::Minus { .. } => "sub",
+            crate::lexer::Token::Star { .. } => "mul",
+            _ => unreachable!(),
+        };
+        let sign = if signed { 's' } else { 'u' };
+        let intrinsic = format!("llvm.{sign}{name}.with.overflow");
+        let result = self
+            .call_intrinsic(&intrinsic, &[left, right])?
+            .into_struct_value();
+        let value = self.builder.build_extract_value(result, 0, "value")?;
+        let overflowed = self.builder.build_extract_value(result, 1, "overflowed")?;
+        Ok((value.into_int_value(), overflowed.into_int_value()))
+    }
+
+    /// `left op right`, panicking at the operator if it overflows
+    fn checked_int_op(
+        &mut self,
+        left: inkwell::values::IntValue<'ctx>,
+        op: &crate::lexer::Token,
+        right: inkwell::values::IntValue<'ctx>,
+        signed: bool,
+    ) -> Result<inkwell::values::IntValue<'ctx>, inkwell::builder::BuilderError> {
+        let (value, overflowed) = self.int_op_with_overflow(left, op, right, signed)?;
+        let func = self.current_function();
+        let ok = self.ctx.append_basic_block(func, "no_overflow");
+        let overflow = self.ctx.append_basic_block(func, "overflow");
+        self.builder
+            .build_conditional_branch(overflowed, overflow, ok)?;
+
+        self.builder.position_at_end(overflow);
+        let verb = match op {
+            crate::lexer::Token::Plus { .. } => "add",
+            crate::lexer::Token::Minus { .. } => "subtract",
+            _ => "multiply",
+        };
+        self.panic(op.here(), &format!("attempt to {verb} with overflow"), &[])?;
+        self.builder.position_at_end(ok);
+        Ok(value)
+    }
+
+    /// Panics at the operator when dividing by zero, or dividing the smallest signed value by
+    /// -1, both of which LLVM leaves undefined
+    fn check_division(
+        &mut self,
+        left: inkwell::values::IntValue<'ctx>,
+        right: inkwell::values::IntValue<'ctx>,
+        here: usize,
+        signed: bool,
+    ) -> Result<(), inkwell::builder::BuilderError> {
+        let t = right.get_type();
+        let func = self.current_function();
+        let non_zero = self.ctx.append_basic_block(func, "non_zero");
+        let zero = self.ctx.append_basic_block(func, "zero");
+        let is_zero = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            right,
+            t.const_zero(),
+            "is_zero",
+        )?;
+        self.builder
+            .build_conditional_branch(is_zero, zero, non_zero)?;
+        self.builder.position_at_end(zero);
+        self.panic(here, "attempt to divide by zero", &[])?;
+        self.builder.position_at_end(non_zero);
+        if !signed {
+            return Ok(());
+        }
+
+        let ok = self.ctx.append_basic_block(func, "no_overflow");
+        let overflow = self.ctx.append_basic_block(func, "overflow");
+        let min = t.const_int(1 << (t.get_bit_width() - 1), false);
+        let is_min =
+            self.builder
+                .build_int_compare(inkwell::IntPredicate::EQ, left, min, "is_min")?;
+        let is_minus_one = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            right,
+            t.const_all_ones(),
+            "is_minus_one",
+        )?;
+        let overflowed = self.builder.build_and(is_min, is_minus_one, "overflowed")?;
+        self.builder
+            .build_conditional_branch(overflowed, overflow, ok)?;
+        self.builder.position_at_end(overflow);
+        self.panic(here, "attempt to divide with overflow", &[])?;
+        self.builder.position_at_end(ok);
+        Ok(())
+    }
+
+    /// Expands the builtins that have no symbol in the runtime, see `runtime::builtins`
+    fn eval_builtin(
+        &mut self,
+        name: &str,
+        args: &[inkwell::values::BasicValueEnum<'ctx>],
+    ) -> Result<inkwell::values::BasicValueEnum<'ctx>, inkwell::builder::BuilderError> {
+        use crate::lexer::Token;
+        let left = args[0].into_int_value();
+        let right = args[1].into_int_value();
+        let op = match name.rsplit('_').next() {
+            Some("add") => Token::Plus { here: 0 },
+            Some("sub") => Token::Minus { here: 0 },
+            _ => Token::Star { here: 0 },
+        };
+        match name {
+            "wrapping_add" => Ok(self.builder.build_int_add(left, right, "add")?.into()),
+            "wrapping_sub" => Ok(self.builder.build_int_sub(left, right, "sub")?.into()),
+            "wrapping_mul" => Ok(self.builder.build_int_mul(left, right, "mul")?.into()),
+            "saturating_add" => self.call_intrinsic("llvm.sadd.sat", &[left, right]),
+            "saturating_sub" => self.call_intrinsic("llvm.ssub.sat", &[left, right]),
+            "checked_add" | "checked_sub" | "checked_mul" => {
+                let (value, overflowed) = self.int_op_with_overflow(left, &op, right, true)?;
+                self.builder
+                    .build_store(args[2].into_pointer_value(), value)?;
+                let i64_type = self.ctx.i64_type();
+                Ok(self
+                    .builder
+                    .build_int_z_extend(overflowed, i64_type, "overflowed")?
+                    .into())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// The value of `variant` of the enum `ty` holding `values`
+    fn build_variant(
+        &mut self,
+        ty: &str,
+        variant: &str,
+        values: &[inkwell::values::BasicValueEnum<'ctx>],
+    ) -> Result<inkwell::values::BasicValueEnum<'ctx>, inkwell::builder::BuilderError> {
+        let (t, variants) = self.enums[ty].clone();
+        let tag = variants
+            .iter()
+            .position(|(v, _)| v.name == variant)
+            .unwrap();
+        let payload_type = variants[tag].1;
+        let mut payload = payload_type.get_undef();
+        for (i, v) in values.iter().enumerate() {
+            payload = self
+                .builder
+                .build_insert_value(payload, *v, i as u32, "payload")?
+                .into_struct_value();
         }
+
+        let ptr = self.build_entry_alloca(t, variant)?;
+        let tag_ptr = self.builder.build_struct_gep(t, ptr, 0, "tag")?;
+        self.builder
+            .build_store(tag_ptr, self.ctx.i64_type().const_int(tag as u64, false))?;
+        let payload_ptr = self.builder.build_struct_gep(t, ptr, 1, "payload")?;
+        self.builder.build_store(payload_ptr, payload)?;
+        self.builder.build_load(t, ptr, "variant")
     }
 
-    fn define_variable(&mut self, name: &str, value: ast::Expression) -> Result<(), BackendError> {
+    /// `value?`: branches on the tag, returning a `None` or an `Err` from the function as its
+    /// own and carrying on with the payload of a `Some` or an `Ok`
+    fn eval_try(
+        &mut self,
+        value: ast::Expression,
+    ) -> Result<(inkwell::values::BasicValueEnum<'ctx>, ast::Type), inkwell::builder::BuilderError>
+    {
+        let (ptr, ty) = self.eval_place(value)?;
+        let (ok, err) = ty.try_variants().unwrap();
+        let (t, variants) = self.enums[&ty.to_string()].clone();
+        let tag_of = |name: &str| variants.iter().position(|(v, _)| v.name == name).unwrap();
+        let (ok, err) = (tag_of(ok), tag_of(err));
+
         let i64_type = self.ctx.i64_type();
-        let ptr = self
+        let tag_ptr = self.builder.build_struct_gep(t, ptr, 0, "tag")?;
+        let tag = self
             .builder
-            .build_alloca(i64_type, name)
+            .build_load(i64_type, tag_ptr, "tag")?
+            .into_int_value();
+        let payload_ptr = self.builder.build_struct_gep(t, ptr, 1, "payload")?;
+        let failed = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            tag,
+            i64_type.const_int(err as u64, false),
+            "failed",
+        )?;
+        let func = self.current_function();
+        let failure = self.ctx.append_basic_block(func, "try_failure");
+        let success = self.ctx.append_basic_block(func, "try_success");
+        self.builder
+            .build_conditional_branch(failed, failure, success)?;
+
+        self.builder.position_at_end(failure);
+        let (decl, payload_type) = &variants[err];
+        let mut values = vec![];
+        for (i, ty) in decl.payload.iter().enumerate() {
+            let field_ptr =
+                self.builder
+                    .build_struct_gep(*payload_type, payload_ptr, i as u32, "error")?;
+            values.push(
+                self.builder
+                    .build_load(self.llvm_type(ty), field_ptr, "error")?,
+            );
+        }
+        let ret = self.ret.to_string();
+        let early = self.build_variant(&ret, &decl.name, &values)?;
+        // Deferred statements only ever fail to build IR
+        self.exit_blocks(self.deferred.len()).map_err(|e| match e {
+            BackendError::IRBuild(e) => e,
+            _ => unreachable!(),
+        })?;
+        self.builder.build_return(Some(&early))?;
+
+        self.builder.position_at_end(success);
+        let (decl, payload_type) = &variants[ok];
+        let ty = decl.payload[0].clone();
+        let field_ptr = self
+            .builder
+            .build_struct_gep(*payload_type, payload_ptr, 0, "value")?;
+        let value = self
+            .builder
+            .build_load(self.llvm_type(&ty), field_ptr, "value")?;
+        Ok((value, ty))
+    }
+
+    fn eval_match(
+        &mut self,
+        value: ast::Expression,
+        arms: Vec<ast::MatchArm>,
+    ) -> Result<(inkwell::values::BasicValueEnum<'ctx>, ast::Type), inkwell::builder::BuilderError>
+    {
+        let here = value.here();
+        let (ptr, ty) = self.eval_place(value)?;
+        let t = self.llvm_type(&ty);
+        let variants = match &ty {
+            ast::Type::Named(name) => self.enums.get(name).map(|(_, variants)| variants.clone()),
+            _ => None,
+        };
+
+        let i64_type = self.ctx.i64_type();
+        // Enums switch on their tag, integers on their value
+        let discriminant = match variants {
+            Some(_) => {
+                let tag_ptr = self
+                    .builder
+                    .build_struct_gep(t.into_struct_type(), ptr, 0, "tag")?;
+                self.builder.build_load(i64_type, tag_ptr, "tag")?
+            }
+            None => self.builder.build_load(t, ptr, "scrutinee")?,
+        }
+        .into_int_value();
+
+        let func = self.current_function();
+        let end = self.ctx.append_basic_block(func, "match_end");
+        let default = self.ctx.append_basic_block(func, "match_default");
+        let mut cases: Vec<(inkwell::values::IntValue<'ctx>, _)> = vec![];
+        let mut arm_blocks = vec![];
+        let mut has_default = false;
+        for arm in arms {
+            let case = match &arm.pattern {
+                // Nothing after a wildcard can match
+                ast::Pattern::Wildcard { .. } => {
+                    has_default = true;
+                    arm_blocks.push((arm, default));
+                    break;
+                }
+                ast::Pattern::Number { value, .. } => {
+                    discriminant.get_type().const_int(*value, false)
+                }
+                ast::Pattern::Char { value, .. } => {
+                    discriminant.get_type().const_int(u64::from(*value), false)
+                }
+                ast::Pattern::Variant { variant, .. } => {
+                    let variants = variants.as_ref().unwrap();
+                    let tag = variants
+                        .iter()
+                        .position(|(v, _)| v.name == *variant)
+                        .unwrap();
+                    i64_type.const_int(tag as u64, false)
+                }
+            };
+            // Only the first arm for a value can ever be taken
+            if cases.iter().any(|(c, _)| *c == case) {
+                continue;
+            }
+            let block = self.ctx.append_basic_block(func, "match_arm");
+            cases.push((case, block));
+            arm_blocks.push((arm, block));
+        }
+
+        self.builder.build_switch(discriminant, default, &cases)?;
+        if !has_default {
+            // The type checker made sure the arms are exhaustive, only a corrupted enum ends
+            // up here
+            self.builder.position_at_end(default);
+            self.panic(
+                here,
+                "entered unreachable code: no match arm for the value",
+                &[],
+            )?;
+        }
+
+        let mut incoming = vec![];
+        let mut result_type = ast::Type::Unit;
+        for (arm, block) in arm_blocks {
+            self.builder.position_at_end(block);
+            let mut shadowed = vec![];
+            if let ast::Pattern::Variant {
+                variant, bindings, ..
+            } = &arm.pattern
+            {
+                let variants = variants.as_ref().unwrap();
+                let (decl, payload_type) =
+                    variants.iter().find(|(v, _)| v.name == *variant).unwrap();
+                let payload_ptr =
+                    self.builder
+                        .build_struct_gep(t.into_struct_type(), ptr, 1, "payload")?;
+                for (i, (name, _)) in bindings.iter().enumerate() {
+                    if name == "_" {
+                        continue;
+                    }
+                    // Bindings are copies, assigning to them doesn't touch the scrutinee
+                    let field_type = self.llvm_type(&decl.payload[i]);
+                    let field_ptr = self.builder.build_struct_gep(
+                        *payload_type,
+                        payload_ptr,
+                        i as u32,
+                        name,
+                    )?;
+                    let v = self.builder.build_load(field_type, field_ptr, name)?;
+                    let binding = self.build_entry_alloca(field_type, name)?;
+                    self.builder.build_store(binding, v)?;
+                    let old = self
+                        .variables
+                        .insert(name.to_string(), (binding, decl.payload[i].clone()));
+                    shadowed.push((name.to_string(), old));
+                }
+            }
+
+            let (v, ty) = self.eval_expression(arm.value)?;
+            result_type = ty;
+            incoming.push((v, self.builder.get_insert_block().unwrap()));
+            self.builder.build_unconditional_branch(end)?;
+
+            for (name, old) in shadowed.into_iter().rev() {
+                match old {
+                    Some(old) => self.variables.insert(name, old),
+                    None => self.variables.remove(&name),
+                };
+            }
+        }
+
+        self.builder.position_at_end(end);
+        let phi = self.builder.build_phi(incoming[0].0.get_type(), "match")?;
+        let incoming: Vec<_> = incoming
+            .iter()
+            .map(|(v, b)| (v as &dyn inkwell::values::BasicValue<'ctx>, *b))
+            .collect();
+        phi.add_incoming(&incoming);
+        Ok((phi.as_basic_value(), result_type))
+    }
+
+    fn define_variable(
+        &mut self,
+        name: &str,
+        ty: Option<&ast::Type>,
+        value: ast::Expression,
+    ) -> Result<(), BackendError> {
+        let (value, got) = self.eval_expression(value).map_err(BackendError::IRBuild)?;
+        // The type checker made sure the value matches the annotation, which can only be more
+        // specific, like `*i64` for `null`
+        self.bind_variable(name, value, ty.cloned().unwrap_or(got))
+    }
+
+    fn bind_variable(
+        &mut self,
+        name: &str,
+        value: inkwell::values::BasicValueEnum<'ctx>,
+        ty: ast::Type,
+    ) -> Result<(), BackendError> {
+        let ptr = self
+            .build_entry_alloca(value.get_type(), name)
             .map_err(BackendError::IRBuild)?;
-        let value = self.eval_expression(value).map_err(BackendError::IRBuild)?;
         self.builder
             .build_store(ptr, value)
             .map_err(BackendError::IRBuild)?;
-        self.variables.insert(name.to_string(), ptr);
+        self.variables.insert(name.to_string(), (ptr, ty));
         Ok(())
     }
-}
 
-pub fn compile(name: &str, program: &[ast::Statement]) -> Result<(), BackendError> {
-    let ctx = inkwell::context::Context::create();
-    let mut backend = Backend::new(&ctx)?;
-    backend.begin_main();
+    fn compile_function(
+        &mut self,
+        name: &str,
+        params: &[ast::Param],
+        ret: &ast::Type,
+        body: &[ast::Statement],
+    ) -> Result<(), BackendError> {
+        let func = self.functions[name].0;
+        let entry = self.ctx.append_basic_block(func, "entry");
+        self.builder.position_at_end(entry);
 
-    for st in program {
+        // Functions only see their parameters
+        let outer = std::mem::take(&mut self.variables);
+        for (i, p) in params.iter().enumerate() {
+            let value = func.get_nth_param(i as u32).unwrap();
+            let ptr = self
+                .build_entry_alloca(value.get_type(), &p.name)
+                .map_err(BackendError::IRBuild)?;
+            self.builder
+                .build_store(ptr, value)
+                .map_err(BackendError::IRBuild)?;
+            self.variables
+                .insert(p.name.to_string(), (ptr, p.ty.clone()));
+        }
+        let outer_ret = std::mem::replace(&mut self.ret, ret.clone());
+        let outer_closures = std::mem::replace(
+            &mut self.local_closures,
+            escape::local_closures(body, params.iter().map(|p| p.name.clone()), &self.escaping),
+        );
+        self.compile_block(body)?;
+        if !self.is_terminated() {
+            // The type checker made sure functions returning a value end in a return
+            match ret {
+                ast::Type::Unit => self.builder.build_return(None),
+                _ => self.builder.build_unreachable(),
+            }
+            .map_err(BackendError::IRBuild)?;
+        }
+        self.variables = outer;
+        self.ret = outer_ret;
+        self.local_closures = outer_closures;
+        Ok(())
+    }
+
+    fn compile_closure(&mut self, closure: PendingClosure<'ctx>) -> Result<(), BackendError> {
+        let entry = self.ctx.append_basic_block(closure.func, "entry");
+        self.builder.position_at_end(entry);
+
+        // Closures see their own copies of the variables they capture along with their
+        // parameters
+        let outer = std::mem::take(&mut self.variables);
+        let env = closure.func.get_nth_param(0).unwrap().into_pointer_value();
+        for (i, (name, ty)) in closure.captures.iter().enumerate() {
+            let field = self
+                .builder
+                .build_struct_gep(closure.env, env, i as u32, name)
+                .map_err(BackendError::IRBuild)?;
+            let value = self
+                .builder
+                .build_load(self.llvm_type(ty), field, name)
+                .map_err(BackendError::IRBuild)?;
+            self.bind_variable(name, value, ty.clone())?;
+        }
+        for (i, p) in closure.params.iter().enumerate() {
+            let value = closure.func.get_nth_param(i as u32 + 1).unwrap();
+            self.bind_variable(&p.name, value, p.ty.clone().unwrap())?;
+        }
+        let outer_ret = std::mem::replace(&mut self.ret, closure.ret);
+        let bound = closure.captures.iter().map(|(name, _)| name.clone());
+        let bound = bound.chain(closure.params.iter().map(|p| p.name.clone()));
+        let outer_closures = std::mem::replace(
+            &mut self.local_closures,
+            escape::local_closures(&closure.body, bound, &self.escaping),
+        );
+        self.compile_block(&closure.body)?;
+        if !self.is_terminated() {
+            match self.ret {
+                ast::Type::Unit => self.builder.build_return(None),
+                _ => self.builder.build_unreachable(),
+            }
+            .map_err(BackendError::IRBuild)?;
+        }
+        self.variables = outer;
+        self.ret = outer_ret;
+        self.local_closures = outer_closures;
+        Ok(())
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|b| b.get_terminator())
+            .is_some()
+    }
+
+    fn compile_block(&mut self, body: &[ast::Statement]) -> Result<(), BackendError> {
+        self.deferred.push(vec![]);
+        for st in body {
+            // Anything after a return is dead code
+            if self.is_terminated() {
+                break;
+            }
+            self.compile_statement(st)?;
+        }
+        // A `return`, `break` or `continue` already ran what the block deferred
+        if !self.is_terminated() {
+            self.exit_blocks(1)?;
+        }
+        self.deferred.pop();
+        Ok(())
+    }
+
+    /// Emits what the innermost `blocks` blocks deferred, innermost block and latest `defer`
+    /// first, as the code leaving them
+    fn exit_blocks(&mut self, blocks: usize) -> Result<(), BackendError> {
+        let deferred: Vec<_> = self
+            .deferred
+            .iter()
+            .rev()
+            .take(blocks)
+            .flat_map(|block| block.iter().rev())
+            .cloned()
+            .collect();
+        for (st, variables) in deferred {
+            // A block of its own, so a `defer` inside a `defer` runs right away
+            let outer = std::mem::replace(&mut self.variables, variables);
+            self.compile_block(std::slice::from_ref(&st))?;
+            self.variables = outer;
+        }
+        Ok(())
+    }
+
+    /// Emits everything of a `for` loop except its body, leaving the builder in the body with
+    /// the loop variable defined. Returns the blocks the body continues and breaks to
+    fn begin_for(
+        &mut self,
+        var: &str,
+        start: ast::Expression,
+        end: ast::Expression,
+        inclusive: bool,
+    ) -> Result<
+        (
+            inkwell::basic_block::BasicBlock<'ctx>,
+            inkwell::basic_block::BasicBlock<'ctx>,
+        ),
+        inkwell::builder::BuilderError,
+    > {
+        let (start, ty) = self.eval_expression(start)?;
+        let (end, _) = self.eval_expression(end)?;
+        let (start, end) = (start.into_int_value(), end.into_int_value());
+        let int_type = start.get_type();
+
+        let func = self.current_function();
+        let cond = self.ctx.append_basic_block(func, "for_cond");
+        let body = self.ctx.append_basic_block(func, "for_body");
+        let step = self.ctx.append_basic_block(func, "for_step");
+        let exit = self.ctx.append_basic_block(func, "for_end");
+
+        let counter = self.build_entry_alloca(int_type, "counter")?;
+        self.builder.build_store(counter, start)?;
+        self.builder.build_unconditional_branch(cond)?;
+
+        self.builder.position_at_end(cond);
+        let i = self
+            .builder
+            .build_load(int_type, counter, var)?
+            .into_int_value();
+        let predicate = match (ty == ast::Type::U8, inclusive) {
+            (true, true) => inkwell::IntPredicate::ULE,
+            (true, false) => inkwell::IntPredicate::ULT,
+            (false, true) => inkwell::IntPredicate::SLE,
+            (false, false) => inkwell::IntPredicate::SLT,
+        };
+        let in_range = self
+            .builder
+            .build_int_compare(predicate, i, end, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, body, exit)?;
+
+        self.builder.position_at_end(step);
+        if inclusive {
+            // Stop before incrementing so `..=MAX` doesn't overflow and loop forever
+            let increment = self.ctx.append_basic_block(func, "for_increment");
+            let last = self
+                .builder
+                .build_int_compare(inkwell::IntPredicate::EQ, i, end, "last")?;
+            self.builder
+                .build_conditional_branch(last, exit, increment)?;
+            self.builder.position_at_end(increment);
+        }
+        let next = self
+            .builder
+            .build_int_add(i, int_type.const_int(1, false), "next")?;
+        self.builder.build_store(counter, next)?;
+        self.builder.build_unconditional_branch(cond)?;
+
+        // The body gets a copy of the counter
+        self.builder.position_at_end(body);
+        let copy = self.build_entry_alloca(int_type, var)?;
+        self.builder.build_store(copy, i)?;
+        self.variables.insert(var.to_string(), (copy, ty));
+        Ok((step, exit))
+    }
+
+    fn compile_for(
+        &mut self,
+        var: &str,
+        start: &ast::Expression,
+        end: &ast::Expression,
+        inclusive: bool,
+        body: &[ast::Statement],
+    ) -> Result<(), BackendError> {
+        // Neither the loop variable nor anything defined in the body outlives the loop
+        let outer = self.variables.clone();
+        let (step, exit) = self
+            .begin_for(var, start.clone(), end.clone(), inclusive)
+            .map_err(BackendError::IRBuild)?;
+        self.loops.push((step, exit, self.deferred.len()));
+        self.compile_block(body)?;
+        self.loops.pop();
+        self.variables = outer;
+        if !self.is_terminated() {
+            self.builder
+                .build_unconditional_branch(step)
+                .map_err(BackendError::IRBuild)?;
+        }
+        self.builder.position_at_end(exit);
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, st: &ast::Statement) -> Result<(), BackendError> {
         match st {
             ast::Statement::Return(value) => {
-                let value = backend
+                let (value, _) = self
                     .eval_expression(value.clone())
                     .map_err(BackendError::IRBuild)?;
-                backend
-                    .builder
-                    .build_return(Some(&value))
+                // `()` isn't returned at all, as in `|x| print_i64(x)`
+                let value = self
+                    .current_function()
+                    .get_type()
+                    .get_return_type()
+                    .map(|_| &value as &dyn inkwell::values::BasicValue<'ctx>);
+                self.exit_blocks(self.deferred.len())?;
+                self.builder
+                    .build_return(value)
                     .map_err(BackendError::IRBuild)?;
             }
-            ast::Statement::DefineVar { name, value } => {
-                backend.define_variable(name, value.clone())?;
+            ast::Statement::DefineVar {
+                name, ty, value, ..
+            } => {
+                self.define_variable(name, ty.as_ref(), value.clone())?;
+            }
+            ast::Statement::DefineTuple { names, ty, value } => {
+                let (value, got) = self
+                    .eval_expression(value.clone())
+                    .map_err(BackendError::IRBuild)?;
+                let ast::Type::Tuple(elems) = ty.clone().unwrap_or(got) else {
+                    unreachable!()
+                };
+                for (i, (binding, ty)) in names.iter().zip(elems).enumerate() {
+                    let elem = self
+                        .builder
+                        .build_extract_value(value.into_struct_value(), i as u32, &binding.name)
+                        .map_err(BackendError::IRBuild)?;
+                    self.bind_variable(&binding.name, elem, ty)?;
+                }
+            }
+            ast::Statement::Assign { target, value } => {
+                let (value, _) = self
+                    .eval_expression(value.clone())
+                    .map_err(BackendError::IRBuild)?;
+                let (ptr, _) = self
+                    .eval_place(target.clone())
+                    .map_err(BackendError::IRBuild)?;
+                self.builder
+                    .build_store(ptr, value)
+                    .map_err(BackendError::IRBuild)?;
+            }
+            ast::Statement::Expression(value) => {
+                self.eval_expression(value.clone())
+                    .map_err(BackendError::IRBuild)?;
+            }
+            ast::Statement::For {
+                var,
+                start,
+                end,
+                inclusive,
+                body,
+                ..
+            } => self.compile_for(var, start, end, *inclusive, body)?,
+            ast::Statement::Break { .. } | ast::Statement::Continue { .. } => {
+                let (step, exit, outer) = *self.loops.last().unwrap();
+                self.exit_blocks(self.deferred.len() - outer)?;
+                let target = match st {
+                    ast::Statement::Break { .. } => exit,
+                    _ => step,
+                };
+                self.builder
+                    .build_unconditional_branch(target)
+                    .map_err(BackendError::IRBuild)?;
+            }
+            ast::Statement::Defer { stmt, .. } => {
+                let variables = self.variables.clone();
+                self.deferred
+                    .last_mut()
+                    .unwrap()
+                    .push(((**stmt).clone(), variables));
             }
+            // Functions are compiled separately, the rest is handled up front
+            ast::Statement::Function { .. }
+            | ast::Statement::Extern { .. }
+            | ast::Statement::Struct { .. }
+            | ast::Statement::Enum { .. }
+            | ast::Statement::Trait { .. }
+            | ast::Statement::Const { .. }
+            | ast::Statement::Static { .. }
+            | ast::Statement::Mod { .. } => {}
+            // Lowered into functions by `monomorph`
+            ast::Statement::Impl { .. } => unreachable!(),
         }
+        Ok(())
+    }
+}
+
+pub fn compile(
+    name: &str,
+    program: &[ast::Statement],
+    globals: &std::collections::HashMap<String, consteval::Value>,
+    src: &source::SourceMap,
+    options: &Options,
+) -> Result<(), BackendError> {
+    let ctx = inkwell::context::Context::create();
+    let mut backend = Backend::new(&ctx, src, options)?;
+    backend.declare_types(program);
+    for st in program {
+        if let ast::Statement::Trait { name, methods, .. } = st {
+            backend.traits.insert(name.to_string(), methods.clone());
+        }
+    }
+    backend.declare_functions(program);
+    backend.define_globals(program, globals);
+    backend.escaping = escape::escaping_params(program);
+
+    for st in program {
+        if let ast::Statement::Function {
+            name,
+            params,
+            ret,
+            body,
+            ..
+        } = st
+        {
+            backend.compile_function(name, params, ret, body)?;
+        }
+    }
+
+    if backend.functions.contains_key("main") {
+        backend.main_shim().map_err(BackendError::IRBuild)?;
+    } else {
+        backend.begin_main();
+        backend.local_closures = escape::local_closures(program, [], &backend.escaping);
+        backend.compile_block(program)?;
+        if !backend.is_terminated() {
+            // Falling off the end of the program exits successfully
+            let zero = ctx.i64_type().const_zero();
+            backend
+                .builder
+                .build_return(Some(&zero))
+                .map_err(BackendError::IRBuild)?;
+        }
+    }
+
+    // Closures can create closures of their own
+    while let Some(closure) = backend.closures.pop() {
+        backend.compile_closure(closure)?;
     }
 
     backend
@@ -127,42 +2361,171 @@ pub fn compile(name: &str, program: &[ast::Statement]) -> Result<(), BackendErro
         .verify()
         .map_err(BackendError::IRVerification)?;
 
-    let triple = inkwell::targets::TargetMachine::get_default_triple();
-    let target =
-        inkwell::targets::Target::from_triple(&triple).map_err(BackendError::CompileTarget)?;
-
-    let target_machine = target
-        .create_target_machine(
-            &triple,
-            "generic",
-            "",
-            inkwell::OptimizationLevel::Aggressive,
-            inkwell::targets::RelocMode::Default,
-            inkwell::targets::CodeModel::Default,
-        )
-        .ok_or(BackendError::TargetMachine)?;
+    if options.opt_level > 0 {
+        backend
+            .module
+            .run_passes(
+                &format!("default<O{}>", options.opt_level.min(3)),
+                &backend.target_machine,
+                inkwell::passes::PassBuilderOptions::create(),
+            )
+            .map_err(BackendError::Optimize)?;
+    }
 
-    target_machine
+    backend
+        .target_machine
         .write_to_file(
             &backend.module,
             inkwell::targets::FileType::Object,
             format!("{name}.o").as_ref(),
         )
         .map_err(BackendError::OutputIR)?;
-    std::process::Command::new("gcc")
-        .arg(format!("{name}.o"))
-        .arg("-o")
-        .arg(name)
-        .spawn()
-        .unwrap()
-        .wait()
-        .unwrap();
+    let runtime = format!("{name}.runtime.c");
+    std::fs::write(&runtime, runtime::SOURCE).map_err(BackendError::Runtime)?;
+    let mut gcc = std::process::Command::new("gcc");
+    gcc.arg(format!("{name}.o")).arg(&runtime);
+    if options.backtrace {
+        gcc.arg("-DJZ_BACKTRACE").arg("-lunwind");
+    }
+    let status = gcc.arg("-o").arg(name).status();
 
     std::process::Command::new("rm")
         .arg(format!("{name}.o"))
+        .arg(&runtime)
         .spawn()
         .unwrap()
         .wait()
         .unwrap();
-    Ok(())
+    match status.map_err(BackendError::Gcc)? {
+        status if status.success() => Ok(()),
+        status => Err(BackendError::Link(status)),
+    }
+}
+
+/// Every variable and function name `st` mentions, in order of appearance
+/// The variables and functions `body` uses, closures inside it included, in order of first use
+fn body_names(body: &[ast::Statement], names: &mut Vec<String>) {
+    for expr in ast::flatten(body).1 {
+        match expr {
+            ast::Expression::Variable { name, .. } | ast::Expression::Call { name, .. } => {
+                if !names.contains(name) {
+                    names.push(name.to_string());
+                }
+            }
+            ast::Expression::Closure { body, .. } => body_names(body, names),
+            _ => {}
+        }
+    }
+}
+
+/// The element type of a slice or string
+fn slice_elem(ty: &ast::Type) -> ast::Type {
+    match ty {
+        ast::Type::Slice(elem) => *elem.clone(),
+        ast::Type::Str => ast::Type::U8,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fold, infer, modules, traits, typeck};
+
+    /// Compiles `s` in a fresh directory, runs it and returns what it printed
+    fn run(test: &str, s: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("jizzle-backend-{test}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.jsl");
+        std::fs::write(&main, s).unwrap();
+        let src = source::Source::from_file(main.to_string_lossy().to_string()).unwrap();
+        let (src, mut program) = modules::load(src).unwrap();
+        traits::check(&program, &src).unwrap();
+        let (instantiations, receivers) = infer::infer(&mut program, &src).unwrap();
+        let (program, instances) =
+            monomorph::monomorphize(program, &instantiations, &receivers, &src).unwrap();
+        typeck::check(&program, &src, &instances).unwrap();
+        let (program, _) = fold::fold_program(program, &src, true);
+        let globals = consteval::evaluate(&program, &src).unwrap();
+        let options = Options {
+            opt_level: 0,
+            overflow_checks: true,
+            backtrace: false,
+        };
+        let exe = dir.join("main").to_string_lossy().to_string();
+        compile(&exe, &program, &globals, &src, &options).unwrap();
+        let output = std::process::Command::new(&exe)
+            .env("JIZZLE_EMPTY", "")
+            .env_remove("JIZZLE_UNSET")
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn libc_names() {
+        assert_eq!(
+            run(
+                "libc_names",
+                "fn malloc(n: i64) -> i64 { return 0; }\nfn strlen(s: str) -> i64 { return 7; }\nlet p = alloc(16);\nfree(p);\nprint_i64(malloc(16) + strlen(\"a\"));"
+            ),
+            "7\n"
+        );
+    }
+
+    #[test]
+    fn defer_on_return() {
+        assert_eq!(
+            run(
+                "defer_on_return",
+                "fn f() -> i64 {\n    defer print_i64(1);\n    defer print_i64(2);\n    return 3;\n}\nprint_i64(f());"
+            ),
+            "2\n1\n3\n"
+        );
+    }
+
+    #[test]
+    fn defer_on_try() {
+        assert_eq!(
+            run(
+                "defer_on_try",
+                "fn check(n: i64) -> Option<i64> {\n    return match n { 0 => Option::None, _ => Option::Some(n) };\n}\nfn f(n: i64) -> Option<i64> {\n    defer print_i64(1);\n    defer print_i64(2);\n    let value = check(n)?;\n    print_i64(value);\n    return Option::Some(value);\n}\nf(0);\nf(5);"
+            ),
+            "2\n1\n5\n2\n1\n"
+        );
+    }
+
+    #[test]
+    fn defer_on_break_and_continue() {
+        assert_eq!(
+            run(
+                "defer_on_break_and_continue",
+                "fn f() {\n    defer print_i64(100);\n    for i in 0..3 {\n        defer print_i64(i);\n        for j in 0..3 {\n            defer print_i64(j + 10);\n            break;\n        }\n        continue;\n    }\n    for i in 0..3 {\n        defer print_i64(i + 20);\n        break;\n    }\n    print_i64(50);\n}\nf();"
+            ),
+            "10\n0\n10\n1\n10\n2\n20\n50\n100\n"
+        );
+    }
+
+    #[test]
+    fn unsigned_ranges() {
+        assert_eq!(
+            run(
+                "unsigned_ranges",
+                "var n = 0;\nfor i in 0 as u8..200 as u8 { n = n + 1; }\nfor i in 250 as u8..=255 as u8 { n = n + 1; }\nprint_i64(n);"
+            ),
+            "206\n"
+        );
+    }
+
+    #[test]
+    fn unset_env() {
+        assert_eq!(
+            run(
+                "unset_env",
+                "print_i64(has_env(\"JIZZLE_EMPTY\"));\nprint_i64(has_env(\"JIZZLE_UNSET\"));\nprint_i64(env(\"JIZZLE_EMPTY\").len + env(\"JIZZLE_UNSET\").len);"
+            ),
+            "1\n0\n0\n"
+        );
+    }
 }
@@ -0,0 +1,335 @@
+use crate::{ast, error, lexer::Token, source};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ConstError {
+    NotConstant {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    Overflow {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        op: Token,
+    },
+    DivisionByZero {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+}
+
+/// A value known at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    U8(u8),
+    Str(String),
+    Array(Vec<Value>),
+    /// Fields in declaration order, or the elements of a tuple
+    Struct(Vec<Value>),
+    Null,
+}
+
+struct Evaluator<'a> {
+    src: &'a source::SourceMap,
+    /// Field names of every struct in declaration order
+    structs: std::collections::HashMap<String, Vec<String>>,
+    /// Constants defined so far, statics can't be read at compile time
+    consts: std::collections::HashMap<String, Value>,
+}
+
+impl Evaluator<'_> {
+    fn position(&self, here: usize) -> (Option<String>, usize, usize) {
+        self.src.locate(here)
+    }
+
+    fn not_constant(&self, here: usize) -> ConstError {
+        let (file, line_number, column_number) = self.position(here);
+        ConstError::NotConstant {
+            file,
+            line_number,
+            column_number,
+        }
+    }
+
+    fn eval(&self, expr: &ast::Expression) -> Result<Value, ConstError> {
+        match expr {
+            // Same bits LLVM's `const_int` would produce
+            ast::Expression::Number { value, .. } => Ok(Value::I64(*value as i64)),
+            ast::Expression::Float { value, .. } => Ok(Value::F64(*value)),
+            ast::Expression::Char { value, .. } => Ok(Value::Char(*value)),
+            ast::Expression::Str { value, .. } => Ok(Value::Str(value.to_string())),
+            ast::Expression::Null { .. } => Ok(Value::Null),
+            ast::Expression::Variable { name, here } => self
+                .consts
+                .get(name)
+                .cloned()
+                .ok_or_else(|| self.not_constant(*here)),
+            ast::Expression::Binary { left, op, right } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.binary(left, op, right)
+            }
+            ast::Expression::Cast { value, to, here } => {
+                cast(self.eval(value)?, to).ok_or_else(|| self.not_constant(*here))
+            }
+            ast::Expression::Array { elements, .. } => Ok(Value::Array(
+                elements
+                    .iter()
+                    .map(|e| self.eval(e))
+                    .collect::<Result<_, _>>()?,
+            )),
+            ast::Expression::Tuple { elements, .. } => Ok(Value::Struct(
+                elements
+                    .iter()
+                    .map(|e| self.eval(e))
+                    .collect::<Result<_, _>>()?,
+            )),
+            ast::Expression::StructLiteral { name, fields, .. } => {
+                let values = self.structs[name]
+                    .iter()
+                    .map(|field| {
+                        let init = fields.iter().find(|f| f.name == *field).unwrap();
+                        self.eval(&init.value)
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Struct(values))
+            }
+            e => Err(self.not_constant(e.here())),
+        }
+    }
+
+    fn binary(&self, left: Value, op: &Token, right: Value) -> Result<Value, ConstError> {
+        let (file, line_number, column_number) = self.position(op.here());
+        let overflow = || ConstError::Overflow {
+            file: file.clone(),
+            line_number,
+            column_number,
+            op: op.clone(),
+        };
+        if matches!(op, Token::Slash { .. })
+            && matches!(right, Value::I32(0) | Value::I64(0) | Value::U8(0))
+        {
+            return Err(ConstError::DivisionByZero {
+                file: file.clone(),
+                line_number,
+                column_number,
+            });
+        }
+        match (left, right) {
+            (Value::I32(l), Value::I32(r)) => match op {
+                Token::Plus { .. } => l.checked_add(r),
+                Token::Minus { .. } => l.checked_sub(r),
+                Token::Star { .. } => l.checked_mul(r),
+                Token::Slash { .. } => l.checked_div(r),
+                _ => unreachable!(),
+            }
+            .map(Value::I32)
+            .ok_or_else(overflow),
+            (Value::I64(l), Value::I64(r)) => match op {
+                Token::Plus { .. } => l.checked_add(r),
+                Token::Minus { .. } => l.checked_sub(r),
+                Token::Star { .. } => l.checked_mul(r),
+                Token::Slash { .. } => l.checked_div(r),
+                _ => unreachable!(),
+            }
+            .map(Value::I64)
+            .ok_or_else(overflow),
+            (Value::U8(l), Value::U8(r)) => match op {
+                Token::Plus { .. } => l.checked_add(r),
+                Token::Minus { .. } => l.checked_sub(r),
+                Token::Star { .. } => l.checked_mul(r),
+                Token::Slash { .. } => l.checked_div(r),
+                _ => unreachable!(),
+            }
+            .map(Value::U8)
+            .ok_or_else(overflow),
+            (Value::F32(l), Value::F32(r)) => Ok(Value::F32(match op {
+                Token::Plus { .. } => l + r,
+                Token::Minus { .. } => l - r,
+                Token::Star { .. } => l * r,
+                Token::Slash { .. } => l / r,
+                _ => unreachable!(),
+            })),
+            (Value::F64(l), Value::F64(r)) => Ok(Value::F64(match op {
+                Token::Plus { .. } => l + r,
+                Token::Minus { .. } => l - r,
+                Token::Star { .. } => l * r,
+                Token::Slash { .. } => l / r,
+                _ => unreachable!(),
+            })),
+            // Pointer arithmetic depends on where things end up in memory
+            _ => Err(self.not_constant(op.here())),
+        }
+    }
+}
+
+/// Float to int casts saturate and turn NaN into 0, like `llvm.fptosi.sat` in the backend
+fn cast(value: Value, to: &ast::Type) -> Option<Value> {
+    Some(match (value, to) {
+        (Value::I32(v), ast::Type::I32) => Value::I32(v),
+        (Value::I64(v), ast::Type::I32) => Value::I32(v as i32),
+        (Value::F32(v), ast::Type::I32) => Value::I32(v as i32),
+        (Value::F64(v), ast::Type::I32) => Value::I32(v as i32),
+        (Value::I32(v), ast::Type::I64) => Value::I64(v as i64),
+        (Value::I64(v), ast::Type::I64) => Value::I64(v),
+        (Value::F32(v), ast::Type::I64) => Value::I64(v as i64),
+        (Value::F64(v), ast::Type::I64) => Value::I64(v as i64),
+        (Value::I32(v), ast::Type::F32) => Value::F32(v as f32),
+        (Value::I64(v), ast::Type::F32) => Value::F32(v as f32),
+        (Value::F32(v), ast::Type::F32) => Value::F32(v),
+        (Value::F64(v), ast::Type::F32) => Value::F32(v as f32),
+        (Value::I32(v), ast::Type::F64) => Value::F64(v as f64),
+        (Value::I64(v), ast::Type::F64) => Value::F64(v as f64),
+        (Value::F32(v), ast::Type::F64) => Value::F64(v as f64),
+        (Value::F64(v), ast::Type::F64) => Value::F64(v),
+        (Value::U8(v), ast::Type::I32) => Value::I32(i32::from(v)),
+        (Value::U8(v), ast::Type::I64) => Value::I64(i64::from(v)),
+        (Value::U8(v), ast::Type::F32) => Value::F32(f32::from(v)),
+        (Value::U8(v), ast::Type::F64) => Value::F64(f64::from(v)),
+        (Value::I32(v), ast::Type::U8) => Value::U8(v as u8),
+        (Value::I64(v), ast::Type::U8) => Value::U8(v as u8),
+        (Value::F32(v), ast::Type::U8) => Value::U8(v as u8),
+        (Value::F64(v), ast::Type::U8) => Value::U8(v as u8),
+        (Value::U8(v), ast::Type::U8) => Value::U8(v),
+        (Value::Char(c), ast::Type::I32) => Value::I32(c as i32),
+        (Value::Char(c), ast::Type::I64) => Value::I64(i64::from(u32::from(c))),
+        (Value::Char(c), ast::Type::U8) => Value::U8(c as u8),
+        (Value::U8(v), ast::Type::Char) => Value::Char(char::from(v)),
+        (Value::Char(c), ast::Type::Char) => Value::Char(c),
+        (Value::Null, ast::Type::Pointer(_)) => Value::Null,
+        (Value::Str(s), ast::Type::Str | ast::Type::Slice(_)) => Value::Str(s),
+        _ => return None,
+    })
+}
+
+/// Evaluates the initializer of every `const` and `static`. Constants can only refer to
+/// constants defined above them
+pub fn evaluate(
+    program: &[ast::Statement],
+    src: &source::SourceMap,
+) -> Result<std::collections::HashMap<String, Value>, ConstError> {
+    let mut evaluator = Evaluator {
+        src,
+        structs: std::collections::HashMap::new(),
+        consts: std::collections::HashMap::new(),
+    };
+    for st in program {
+        if let ast::Statement::Struct { name, fields, .. } = st {
+            let names = fields.iter().map(|f| f.name.to_string()).collect();
+            evaluator.structs.insert(name.to_string(), names);
+        }
+    }
+
+    let mut globals = std::collections::HashMap::new();
+    for st in program {
+        match st {
+            ast::Statement::Const { name, value, .. } => {
+                let value = evaluator.eval(value)?;
+                evaluator.consts.insert(name.to_string(), value.clone());
+                globals.insert(name.to_string(), value);
+            }
+            ast::Statement::Static { name, value, .. } => {
+                globals.insert(name.to_string(), evaluator.eval(value)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(globals)
+}
+
+impl std::fmt::Display for ConstError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotConstant {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Cannot be evaluated at compile time",
+            ),
+            Self::Overflow {
+                file,
+                line_number,
+                column_number,
+                op,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{op} overflows in a constant expression").as_str(),
+            ),
+            Self::DivisionByZero {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Division by zero in a constant expression",
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+
+    fn evaluate_str(s: &str) -> Result<std::collections::HashMap<String, Value>, ConstError> {
+        let src = source::Source::new(s);
+        let tokens = lexer::lex_file(src.clone()).unwrap();
+        let program = ast::parse(&tokens).unwrap();
+        evaluate(&program, &src.into())
+    }
+
+    #[test]
+    fn constants() {
+        let globals = evaluate_str(
+            "struct P { x: i64, y: f32 }\nconst N: i64 = 2 * 3;\nstatic ORIGIN: P = P { y: 1.5 as f32, x: N - 1 };",
+        )
+        .unwrap();
+        assert_eq!(globals["N"], Value::I64(6));
+        assert_eq!(
+            globals["ORIGIN"],
+            Value::Struct(vec![Value::I64(5), Value::F32(1.5)])
+        );
+    }
+
+    #[test]
+    fn not_constant() {
+        assert_eq!(
+            evaluate_str("static A: i64 = 1;\nconst B: i64 = A + 1;"),
+            Err(ConstError::NotConstant {
+                file: None,
+                line_number: 2,
+                column_number: 16,
+            })
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(
+            evaluate_str("const A: i64 = 9223372036854775807 + 1;"),
+            Err(ConstError::Overflow {
+                file: None,
+                line_number: 1,
+                column_number: 36,
+                op: Token::Plus { here: 35 },
+            })
+        );
+    }
+}
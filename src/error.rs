@@ -4,9 +4,10 @@ pub fn display_error(
     pos: (usize, usize),
     message: &str,
 ) -> Result<(), std::fmt::Error> {
-    if let Some(n) = input_name {
-        writeln!(f, "./{n}:{}:{} \n{message}", pos.0, pos.1)
-    } else {
-        writeln!(f, "./<input>:{}:{} \n{message}", pos.0, pos.1)
+    match input_name {
+        // Bundled modules are named like `<std::io>`, they aren't files
+        Some(n) if n.starts_with('<') => writeln!(f, "{n}:{}:{} \n{message}", pos.0, pos.1),
+        Some(n) => writeln!(f, "./{n}:{}:{} \n{message}", pos.0, pos.1),
+        None => writeln!(f, "./<input>:{}:{} \n{message}", pos.0, pos.1),
     }
 }
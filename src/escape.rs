@@ -0,0 +1,230 @@
+// Closure environments go on the heap since a closure can outlive the function creating it.
+// One that can't, because it's only ever called or passed to a parameter that can't outlive
+// the call either, keeps its environment on the stack instead. Anything else done with a
+// closure, like storing it, returning it or capturing it in another closure, counts as
+// escaping.
+use crate::ast;
+use std::collections::{HashMap, HashSet};
+
+/// Whether a closure passed to each parameter of a function can outlive the call, by function
+/// name
+pub type Params = HashMap<String, Vec<bool>>;
+
+pub fn escaping_params(program: &[ast::Statement]) -> Params {
+    let functions: Vec<_> = program
+        .iter()
+        .filter_map(|st| match st {
+            ast::Statement::Function {
+                name, params, body, ..
+            } => Some((name, params, body)),
+            _ => None,
+        })
+        .collect();
+    // Nothing escapes until shown otherwise, so passing a parameter along in a recursive call
+    // doesn't make it escape
+    let mut escaping: Params = functions
+        .iter()
+        .map(|(name, params, _)| (name.to_string(), vec![false; params.len()]))
+        .collect();
+    loop {
+        let mut changed = false;
+        for (name, params, body) in &functions {
+            let body = Body::new(body, params.iter().map(|p| p.name.clone()), &escaping);
+            let now: Vec<_> = params.iter().map(|p| !body.keeps(&p.name)).collect();
+            if now != escaping[name.as_str()] {
+                escaping.insert(name.to_string(), now);
+                changed = true;
+            }
+        }
+        if !changed {
+            return escaping;
+        }
+    }
+}
+
+/// The closures written in `body` that can't outlive it, by offset. `bound` are the variables
+/// in scope before it, like parameters
+pub fn local_closures(
+    body: &[ast::Statement],
+    bound: impl IntoIterator<Item = String>,
+    params: &Params,
+) -> HashSet<usize> {
+    let body = Body::new(body, bound, params);
+    let mut found = HashSet::new();
+    for expr in &body.expressions {
+        if let ast::Expression::Call { name, args, .. } = expr {
+            for (i, arg) in args.iter().enumerate() {
+                if let ast::Expression::Closure { here, .. } = arg
+                    && body.stays_in_call(name, i)
+                {
+                    found.insert(*here);
+                }
+            }
+        }
+    }
+    for st in &body.statements {
+        if let ast::Statement::DefineVar {
+            name,
+            value: ast::Expression::Closure { here, .. },
+            ..
+        } = st
+            && body.keeps(name)
+        {
+            found.insert(*here);
+        }
+    }
+    found
+}
+
+struct Body<'a> {
+    statements: Vec<&'a ast::Statement>,
+    /// Every expression outside of closure bodies, those are functions of their own
+    expressions: Vec<&'a ast::Expression>,
+    /// Every variable anywhere in the body, they shadow functions of the same name
+    bound: HashSet<String>,
+    params: &'a Params,
+}
+
+impl<'a> Body<'a> {
+    fn new(
+        body: &'a [ast::Statement],
+        bound: impl IntoIterator<Item = String>,
+        params: &'a Params,
+    ) -> Self {
+        let (statements, expressions) = ast::flatten(body);
+        let mut bound: HashSet<_> = bound.into_iter().collect();
+        for st in &statements {
+            match st {
+                ast::Statement::DefineVar { name, .. } | ast::Statement::For { var: name, .. } => {
+                    bound.insert(name.to_string());
+                }
+                ast::Statement::DefineTuple { names, .. } => {
+                    bound.extend(names.iter().map(|b| b.name.clone()));
+                }
+                _ => {}
+            }
+        }
+        for expr in &expressions {
+            if let ast::Expression::Match { arms, .. } = expr {
+                for arm in arms {
+                    if let ast::Pattern::Variant { bindings, .. } = &arm.pattern {
+                        bound.extend(bindings.iter().map(|(name, _)| name.clone()));
+                    }
+                }
+            }
+        }
+        Self {
+            statements,
+            expressions,
+            bound,
+            params,
+        }
+    }
+
+    /// Whether a closure passed as argument `i` to `callee` can't outlive the call
+    fn stays_in_call(&self, callee: &str, i: usize) -> bool {
+        !self.bound.contains(callee)
+            && self.params.get(callee).and_then(|params| params.get(i)) == Some(&false)
+    }
+
+    /// Whether a closure held by the variable `name` can't outlive the body, which it can't
+    /// when it's only called or passed where it stays in the call
+    fn keeps(&self, name: &str) -> bool {
+        let mut uses = 0;
+        let mut kept = 0;
+        for expr in &self.expressions {
+            match expr {
+                ast::Expression::Variable { name: n, .. } if n == name => uses += 1,
+                ast::Expression::Call {
+                    name: callee, args, ..
+                } => {
+                    kept += args
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, arg)| {
+                            matches!(arg, ast::Expression::Variable { name: n, .. } if n == name)
+                                && self.stays_in_call(callee, *i)
+                        })
+                        .count();
+                }
+                // Captured by a closure which might outlive the body itself
+                ast::Expression::Closure { body, .. } if mentions(body, name) => return false,
+                _ => {}
+            }
+        }
+        uses == kept
+    }
+}
+
+/// Whether `body` uses the variable or function `name` anywhere, closures inside it included
+fn mentions(body: &[ast::Statement], name: &str) -> bool {
+    ast::flatten(body).1.iter().any(|expr| match expr {
+        ast::Expression::Variable { name: n, .. } | ast::Expression::Call { name: n, .. } => {
+            n == name
+        }
+        ast::Expression::Closure { body, .. } => mentions(body, name),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, source};
+
+    /// The offsets of the top level closures that stay on the stack
+    fn local(s: &str) -> Vec<usize> {
+        let tokens = lexer::lex_file(source::Source::new(s)).unwrap();
+        let program = ast::parse(&tokens).unwrap();
+        let params = escaping_params(&program);
+        let mut found: Vec<_> = local_closures(&program, [], &params).into_iter().collect();
+        found.sort();
+        found
+    }
+
+    fn offsets(s: &str, pattern: &str) -> Vec<usize> {
+        s.match_indices(pattern).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn arguments() {
+        let apply = "fn apply(f: fn(i64) -> i64, x: i64) -> i64 { return f(x); }\n";
+        let s = format!("{apply}let c = 2;\nreturn apply(|x| x * c, 3);");
+        assert_eq!(local(&s), offsets(&s, "|x|"));
+
+        let s = "fn keep(f: fn(i64) -> i64) -> fn(i64) -> i64 { return f; }\nlet c = 2;\nlet g = keep(|x| x * c);\nreturn g(1);";
+        assert_eq!(local(s), vec![]);
+
+        let s = "fn twice(f: fn(i64) -> i64, n: i64) -> i64 { return match n { 0 => 0, _ => f(twice(f, n - 1)) }; }\nlet c = 2;\nreturn twice(|x| x + c, 3);";
+        assert_eq!(local(s), offsets(s, "|x|"));
+
+        let s = format!(
+            "{apply}fn pass(f: fn(i64) -> i64) -> i64 {{ return apply(f, 1); }}\nlet c = 2;\nreturn pass(|x| x * c);"
+        );
+        assert_eq!(local(&s), offsets(&s, "|x|"));
+
+        let s = "struct Holder { f: fn(i64) -> i64 }\nfn hold(f: fn(i64) -> i64) -> Holder { return Holder { f: f }; }\nlet c = 2;\nreturn hold(|x| x * c).f(1);";
+        assert_eq!(local(s), vec![]);
+    }
+
+    #[test]
+    fn bindings() {
+        let s = "let c = 2;\nlet f = |x| x * c;\nreturn f(f(1));";
+        assert_eq!(local(s), offsets(s, "|x|"));
+
+        let s = "let c = 2;\nlet f = |x| x * c;\nvar g = f;\nreturn g(1);";
+        assert_eq!(local(s), vec![]);
+
+        let s = "let c = 2;\nlet f = |x| x * c;\nlet g = |y| f(y);\nreturn g(1);";
+        assert_eq!(local(s), offsets(s, "|y|"));
+    }
+
+    #[test]
+    fn shadowed_functions() {
+        let s = "fn apply(f: fn(i64) -> i64, x: i64) -> i64 { return f(x); }\nlet c = 2;\nreturn apply(|x| x * c, 3);";
+        let tokens = lexer::lex_file(source::Source::new(s)).unwrap();
+        let program = ast::parse(&tokens).unwrap();
+        let params = escaping_params(&program);
+        assert!(local_closures(&program, [String::from("apply")], &params).is_empty());
+    }
+}
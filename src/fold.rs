@@ -0,0 +1,595 @@
+// Only literal-on-literal float operations are folded. Identities like `x * 0.0` or
+// reassociation are not IEEE-safe (NaN, signed zeros), and f32 is left to LLVM since
+// folding it in f64 would round twice. Integer literals fold with the same wrapping
+// semantics LLVM uses, but overflow gets reported since it's almost never intended. With
+// overflow checks on, an overflowing operation is left alone so it panics like any other.
+use crate::{ast, error, lexer::Token, source};
+
+#[derive(Debug, PartialEq)]
+pub enum Warning {
+    Overflow {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        op: Token,
+        value: i64,
+    },
+    OverflowPanics {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        op: Token,
+    },
+}
+
+struct Folder<'a> {
+    src: &'a source::SourceMap,
+    warnings: Vec<Warning>,
+    overflow_checks: bool,
+}
+
+pub fn fold_program(
+    program: Vec<ast::Statement>,
+    src: &source::SourceMap,
+    overflow_checks: bool,
+) -> (Vec<ast::Statement>, Vec<Warning>) {
+    let mut folder = Folder {
+        src,
+        warnings: vec![],
+        overflow_checks,
+    };
+    let program = program
+        .into_iter()
+        .map(|st| folder.fold_statement(st))
+        .collect();
+    (program, folder.warnings)
+}
+
+impl Folder<'_> {
+    fn fold_statement(&mut self, st: ast::Statement) -> ast::Statement {
+        match st {
+            ast::Statement::Return(value) => ast::Statement::Return(self.fold(value)),
+            ast::Statement::DefineVar {
+                name,
+                mutable,
+                ty,
+                value,
+                here,
+            } => ast::Statement::DefineVar {
+                name,
+                mutable,
+                ty,
+                value: self.fold(value),
+                here,
+            },
+            ast::Statement::DefineTuple { names, ty, value } => ast::Statement::DefineTuple {
+                names,
+                ty,
+                value: self.fold(value),
+            },
+            ast::Statement::Assign { target, value } => ast::Statement::Assign {
+                target: self.fold(target),
+                value: self.fold(value),
+            },
+            ast::Statement::Function {
+                name,
+                generics,
+                params,
+                ret,
+                body,
+                here,
+            } => ast::Statement::Function {
+                name,
+                generics,
+                params,
+                ret,
+                body: body.into_iter().map(|st| self.fold_statement(st)).collect(),
+                here,
+            },
+            ast::Statement::Expression(value) => ast::Statement::Expression(self.fold(value)),
+            ast::Statement::For {
+                var,
+                start,
+                end,
+                inclusive,
+                body,
+                here,
+            } => ast::Statement::For {
+                var,
+                start: self.fold(start),
+                end: self.fold(end),
+                inclusive,
+                body: body.into_iter().map(|st| self.fold_statement(st)).collect(),
+                here,
+            },
+            ast::Statement::Defer { stmt, here } => ast::Statement::Defer {
+                stmt: Box::new(self.fold_statement(*stmt)),
+                here,
+            },
+            // Initializers of globals are evaluated by `consteval`, which rejects overflow
+            st @ (ast::Statement::Struct { .. }
+            | ast::Statement::Enum { .. }
+            | ast::Statement::Trait { .. }
+            | ast::Statement::Impl { .. }
+            | ast::Statement::Extern { .. }
+            | ast::Statement::Const { .. }
+            | ast::Statement::Static { .. }
+            | ast::Statement::Mod { .. }
+            | ast::Statement::Break { .. }
+            | ast::Statement::Continue { .. }) => st,
+        }
+    }
+
+    fn fold(&mut self, expr: ast::Expression) -> ast::Expression {
+        match expr {
+            ast::Expression::Binary { left, op, right } => {
+                let left = self.fold(*left);
+                let right = self.fold(*right);
+                if let (Some((l, ty)), Some((r, r_ty))) = (int_literal(&left), int_literal(&right))
+                    && ty == r_ty
+                    && let Some(value) = self.fold_int(l, &op, r, &ty)
+                {
+                    return int_expression(value, ty, left.here());
+                }
+                match (&left, &right) {
+                    (
+                        ast::Expression::Float { value: l, here, .. },
+                        ast::Expression::Float { value: r, .. },
+                    ) => {
+                        let value = match op {
+                            Token::Plus { .. } => l + r,
+                            Token::Minus { .. } => l - r,
+                            Token::Star { .. } => l * r,
+                            Token::Slash { .. } => l / r,
+                            _ => unreachable!(),
+                        };
+                        ast::Expression::Float {
+                            value,
+                            here: *here,
+                            len: 0,
+                        }
+                    }
+                    _ => ast::Expression::Binary {
+                        left: Box::new(left),
+                        op,
+                        right: Box::new(right),
+                    },
+                }
+            }
+            ast::Expression::Cast { value, to, here } => match (self.fold(*value), to) {
+                // Matches `sitofp`, literals are signed 64-bit integers
+                (ast::Expression::Number { value, here, .. }, ast::Type::F64) => {
+                    ast::Expression::Float {
+                        value: value as i64 as f64,
+                        here,
+                        len: 0,
+                    }
+                }
+                (value @ ast::Expression::Float { .. }, ast::Type::F64) => value,
+                (value, to) => ast::Expression::Cast {
+                    value: Box::new(value),
+                    to,
+                    here,
+                },
+            },
+            ast::Expression::Array { elements, here } => ast::Expression::Array {
+                elements: elements.into_iter().map(|e| self.fold(e)).collect(),
+                here,
+            },
+            ast::Expression::Tuple { elements, here } => ast::Expression::Tuple {
+                elements: elements.into_iter().map(|e| self.fold(e)).collect(),
+                here,
+            },
+            ast::Expression::Index { array, index, here } => ast::Expression::Index {
+                array: Box::new(self.fold(*array)),
+                index: Box::new(self.fold(*index)),
+                here,
+            },
+            ast::Expression::Slice {
+                value,
+                start,
+                end,
+                inclusive,
+                here,
+            } => ast::Expression::Slice {
+                value: Box::new(self.fold(*value)),
+                start: start.map(|e| Box::new(self.fold(*e))),
+                end: end.map(|e| Box::new(self.fold(*e))),
+                inclusive,
+                here,
+            },
+            ast::Expression::StructLiteral { name, fields, here } => {
+                ast::Expression::StructLiteral {
+                    name,
+                    fields: fields
+                        .into_iter()
+                        .map(|f| ast::FieldInit {
+                            value: self.fold(f.value),
+                            ..f
+                        })
+                        .collect(),
+                    here,
+                }
+            }
+            ast::Expression::Field { value, field, here } => ast::Expression::Field {
+                value: Box::new(self.fold(*value)),
+                field,
+                here,
+            },
+            ast::Expression::Variant {
+                ty,
+                variant,
+                args,
+                here,
+            } => ast::Expression::Variant {
+                ty,
+                variant,
+                args: args.into_iter().map(|e| self.fold(e)).collect(),
+                here,
+            },
+            ast::Expression::Match { value, arms, here } => ast::Expression::Match {
+                value: Box::new(self.fold(*value)),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| ast::MatchArm {
+                        value: self.fold(arm.value),
+                        ..arm
+                    })
+                    .collect(),
+                here,
+            },
+            ast::Expression::AddressOf { value, here } => ast::Expression::AddressOf {
+                value: Box::new(self.fold(*value)),
+                here,
+            },
+            ast::Expression::Deref { value, here } => ast::Expression::Deref {
+                value: Box::new(self.fold(*value)),
+                here,
+            },
+            ast::Expression::Call { name, args, here } => ast::Expression::Call {
+                name,
+                args: args.into_iter().map(|e| self.fold(e)).collect(),
+                here,
+            },
+            ast::Expression::Closure {
+                params,
+                ret,
+                body,
+                here,
+            } => ast::Expression::Closure {
+                params,
+                ret,
+                body: body.into_iter().map(|st| self.fold_statement(st)).collect(),
+                here,
+            },
+            ast::Expression::MethodCall {
+                value,
+                method,
+                args,
+                here,
+            } => ast::Expression::MethodCall {
+                value: Box::new(self.fold(*value)),
+                method,
+                args: args.into_iter().map(|e| self.fold(e)).collect(),
+                here,
+            },
+            ast::Expression::Try { value, here } => ast::Expression::Try {
+                value: Box::new(self.fold(*value)),
+                here,
+            },
+            e => e,
+        }
+    }
+
+    /// Folds `l op r` on integer literals of type `ty`, wrapping like the code LLVM would
+    /// generate unless overflow checks are on
+    fn fold_int(&mut self, l: u64, op: &Token, r: u64, ty: &ast::Type) -> Option<i64> {
+        // Exact in i128, then wrapped to `ty`
+        let (l, r) = (l as i64 as i128, r as i64 as i128);
+        let exact = match op {
+            Token::Plus { .. } => l + r,
+            Token::Minus { .. } => l - r,
+            Token::Star { .. } => l * r,
+            // Division by zero is left for the program to trip over at runtime
+            Token::Slash { .. } if r == 0 => return None,
+            // `i64::MIN / -1` is exact here too, so it's caught as overflow below
+            Token::Slash { .. } => l / r,
+            _ => unreachable!(),
+        };
+        let value = match ty {
+            ast::Type::I32 => exact as i32 as i64,
+            ast::Type::U8 => exact as u8 as i64,
+            _ => exact as i64,
+        };
+        let overflowed = value as i128 != exact;
+        if overflowed && self.overflow_checks {
+            let (file, line_number, column_number) = self.src.locate(op.here());
+            self.warnings.push(Warning::OverflowPanics {
+                file,
+                line_number,
+                column_number,
+                op: op.clone(),
+            });
+            return None;
+        }
+        if overflowed {
+            let (file, line_number, column_number) = self.src.locate(op.here());
+            self.warnings.push(Warning::Overflow {
+                file,
+                line_number,
+                column_number,
+                op: op.clone(),
+                value,
+            });
+        }
+        Some(value)
+    }
+}
+
+/// The value and type of an integer literal, which `infer` wraps in a cast unless it's an i64
+fn int_literal(expr: &ast::Expression) -> Option<(u64, ast::Type)> {
+    match expr {
+        ast::Expression::Number { value, .. } => Some((*value, ast::Type::I64)),
+        ast::Expression::Cast {
+            value,
+            to: to @ (ast::Type::I32 | ast::Type::U8),
+            ..
+        } => match **value {
+            ast::Expression::Number { value, .. } => Some((value, to.clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The literal `value` of type `ty`, the inverse of `int_literal`
+fn int_expression(value: i64, ty: ast::Type, here: usize) -> ast::Expression {
+    let literal = ast::Expression::Number {
+        value: value as u64,
+        here,
+        len: 0,
+    };
+    match ty {
+        ast::Type::I64 => literal,
+        to => ast::Expression::Cast {
+            value: Box::new(literal),
+            to,
+            here,
+        },
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overflow {
+                file,
+                line_number,
+                column_number,
+                op,
+                value,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{op} overflows, the result wraps around to {value}").as_str(),
+            ),
+            Self::OverflowPanics {
+                file,
+                line_number,
+                column_number,
+                op,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{op} always overflows, this panics at runtime").as_str(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fold(expr: ast::Expression) -> ast::Expression {
+        fold_with_warnings(expr).0
+    }
+
+    fn fold_with_warnings(expr: ast::Expression) -> (ast::Expression, Vec<Warning>) {
+        fold_checked(expr, false)
+    }
+
+    fn fold_checked(
+        expr: ast::Expression,
+        overflow_checks: bool,
+    ) -> (ast::Expression, Vec<Warning>) {
+        let src = source::SourceMap::from(source::Source::new(""));
+        let mut folder = Folder {
+            src: &src,
+            warnings: vec![],
+            overflow_checks,
+        };
+        let expr = folder.fold(expr);
+        (expr, folder.warnings)
+    }
+
+    fn float(value: f64) -> ast::Expression {
+        ast::Expression::Float {
+            value,
+            here: 0,
+            len: 0,
+        }
+    }
+
+    fn binary(left: ast::Expression, op: Token, right: ast::Expression) -> ast::Expression {
+        ast::Expression::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        }
+    }
+
+    #[test]
+    fn float_literals() {
+        assert_eq!(
+            fold(binary(float(0.1), Token::Plus { here: 0 }, float(0.2))),
+            float(0.1 + 0.2)
+        );
+        let nan = fold(binary(float(0.0), Token::Slash { here: 0 }, float(0.0)));
+        assert!(matches!(nan, ast::Expression::Float { value, .. } if value.is_nan()));
+    }
+
+    #[test]
+    fn keeps_non_literals() {
+        let x = ast::Expression::Variable {
+            name: String::from("x"),
+            here: 0,
+        };
+        let expr = binary(x, Token::Star { here: 0 }, float(0.0));
+        assert_eq!(fold(expr.clone()), expr);
+    }
+
+    #[test]
+    fn f32_is_not_folded() {
+        let expr = binary(
+            ast::Expression::Cast {
+                value: Box::new(float(0.1)),
+                to: ast::Type::F32,
+                here: 0,
+            },
+            Token::Plus { here: 0 },
+            ast::Expression::Cast {
+                value: Box::new(float(0.2)),
+                to: ast::Type::F32,
+                here: 0,
+            },
+        );
+        assert_eq!(fold(expr.clone()), expr);
+    }
+
+    fn number(value: u64) -> ast::Expression {
+        ast::Expression::Number {
+            value,
+            here: 0,
+            len: 0,
+        }
+    }
+
+    #[test]
+    fn int_literals() {
+        let expr = binary(
+            number(1),
+            Token::Plus { here: 0 },
+            binary(number(2), Token::Star { here: 0 }, number(3)),
+        );
+        assert_eq!(fold_with_warnings(expr), (number(7), vec![]));
+
+        let div_by_zero = binary(number(1), Token::Slash { here: 0 }, number(0));
+        assert_eq!(fold(div_by_zero.clone()), div_by_zero);
+    }
+
+    #[test]
+    fn int_overflow_warns() {
+        let expr = binary(number(i64::MAX as u64), Token::Plus { here: 0 }, number(1));
+        assert_eq!(
+            fold_with_warnings(expr),
+            (
+                number(i64::MIN as u64),
+                vec![Warning::Overflow {
+                    file: None,
+                    line_number: 1,
+                    column_number: 1,
+                    op: Token::Plus { here: 0 },
+                    value: i64::MIN,
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn int_division_overflow() {
+        let minus_one = || binary(number(0), Token::Minus { here: 0 }, number(1));
+        let expr = binary(
+            number(i64::MIN as u64),
+            Token::Slash { here: 0 },
+            minus_one(),
+        );
+        assert_eq!(
+            fold_with_warnings(expr.clone()),
+            (
+                number(i64::MIN as u64),
+                vec![Warning::Overflow {
+                    file: None,
+                    line_number: 1,
+                    column_number: 1,
+                    op: Token::Slash { here: 0 },
+                    value: i64::MIN,
+                }]
+            )
+        );
+        assert_eq!(
+            fold_checked(expr, true),
+            (
+                binary(
+                    number(i64::MIN as u64),
+                    Token::Slash { here: 0 },
+                    number(-1i64 as u64)
+                ),
+                vec![Warning::OverflowPanics {
+                    file: None,
+                    line_number: 1,
+                    column_number: 1,
+                    op: Token::Slash { here: 0 },
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn int_overflow_with_checks_is_kept() {
+        let expr = binary(number(i64::MAX as u64), Token::Plus { here: 0 }, number(1));
+        assert_eq!(
+            fold_checked(expr.clone(), true),
+            (
+                expr,
+                vec![Warning::OverflowPanics {
+                    file: None,
+                    line_number: 1,
+                    column_number: 1,
+                    op: Token::Plus { here: 0 },
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn narrow_int_literals() {
+        let i32_literal = |value: u64| ast::Expression::Cast {
+            value: Box::new(number(value)),
+            to: ast::Type::I32,
+            here: 0,
+        };
+        let expr = binary(
+            i32_literal(i32::MAX as u64),
+            Token::Plus { here: 0 },
+            i32_literal(1),
+        );
+        assert_eq!(
+            fold_with_warnings(expr),
+            (
+                i32_literal(i32::MIN as i64 as u64),
+                vec![Warning::Overflow {
+                    file: None,
+                    line_number: 1,
+                    column_number: 1,
+                    op: Token::Plus { here: 0 },
+                    value: i32::MIN as i64,
+                }]
+            )
+        );
+        let expr = binary(i32_literal(2), Token::Star { here: 0 }, i32_literal(3));
+        assert_eq!(fold_with_warnings(expr), (i32_literal(6), vec![]));
+    }
+}
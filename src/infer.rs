@@ -0,0 +1,1566 @@
+// Local type inference, run before `typeck`. Literals take whatever type their uses ask for,
+// falling back to i64 and f64, and unannotated variables get the type of their initializer
+// as refined by later uses, so `var i = 0; f(i)` works for `fn f(x: i32)`. Afterwards every
+// inferred variable is annotated and every narrowed literal is wrapped in a cast, so the
+// passes after this one only ever see concrete types. Anything this pass can't make sense of
+// is left alone for `typeck` to report.
+use crate::{ast, error, runtime, source};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InferError {
+    Conflict {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        inferred: Box<ast::Type>,
+        got: ast::Type,
+        previous: Box<(Option<String>, usize, usize)>,
+    },
+    Ambiguous {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    UninferredParameter {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        param: String,
+    },
+    LiteralOutOfRange {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        value: u64,
+        ty: ast::Type,
+    },
+}
+
+/// A type that may still contain unknowns
+#[derive(Debug, Clone)]
+enum Ty {
+    Known(ast::Type),
+    Var(usize),
+    Array(Box<Ty>, u64),
+    Pointer(Box<Ty>),
+    Slice(Box<Ty>),
+    Tuple(Vec<Ty>),
+    Generic(String, Vec<Ty>),
+    Function(Vec<Ty>, Box<Ty>),
+    /// Something this pass doesn't understand
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// The type of an integer literal, i64 unless something says otherwise
+    Integer,
+    /// The type of a float literal, f64 unless something says otherwise
+    Float,
+    /// What `null` points to, which has to be inferred
+    Any,
+}
+
+struct Var {
+    kind: Kind,
+    /// What the variable turned out to be and the expression that decided it
+    bound: Option<(Ty, usize)>,
+    /// The program variable this is the type of, conflicts are only reported for those
+    name: Option<String>,
+}
+
+/// An unannotated `var` or `let`
+struct Definition {
+    name: String,
+    ty: Ty,
+    here: usize,
+}
+
+struct FunctionInfo {
+    generics: Vec<String>,
+    params: Vec<ast::Type>,
+    ret: ast::Type,
+}
+
+/// A call to a generic function, a literal of a generic struct or a variant of a generic enum
+struct Use {
+    name: String,
+    generics: Vec<String>,
+    args: Vec<Ty>,
+    here: usize,
+}
+
+/// Type arguments of every use of a generic function, struct or enum, by offset
+pub type Instantiations = HashMap<usize, Vec<ast::Type>>;
+
+/// Types of the receivers of method calls and of the values cast to `*dyn Trait`, by offset.
+/// Inside generic functions they're in terms of the type parameters
+pub type Receivers = HashMap<usize, ast::Type>;
+
+struct Inferer<'a> {
+    src: &'a source::SourceMap,
+    vars: Vec<Var>,
+    variables: HashMap<String, Ty>,
+    functions: HashMap<String, FunctionInfo>,
+    globals: HashMap<String, ast::Type>,
+    /// Fields of every struct along with its type parameters
+    structs: HashMap<String, (Vec<String>, Vec<ast::FieldDecl>)>,
+    /// Variants of every enum along with its type parameters
+    enums: HashMap<String, (Vec<String>, Vec<ast::VariantDecl>)>,
+    traits: HashMap<String, Vec<ast::TraitMethod>>,
+    /// Return type of the function or closure being inferred
+    ret: Ty,
+    /// Type parameters of the function being inferred
+    generics: Vec<ast::Generic>,
+    uses: Vec<Use>,
+    receivers: HashMap<usize, Ty>,
+    /// Type of every literal, by offset
+    literals: HashMap<usize, Ty>,
+    definitions: Vec<Definition>,
+}
+
+impl Inferer<'_> {
+    fn declare(&mut self, program: &[ast::Statement]) {
+        for builtin in runtime::builtins() {
+            self.functions.insert(
+                builtin.name.to_string(),
+                FunctionInfo {
+                    generics: vec![],
+                    params: builtin.params,
+                    ret: builtin.ret,
+                },
+            );
+        }
+        for st in program {
+            match st {
+                ast::Statement::Function {
+                    name,
+                    generics,
+                    params,
+                    ret,
+                    ..
+                } => {
+                    self.functions.insert(
+                        name.to_string(),
+                        FunctionInfo {
+                            generics: generics.iter().map(|g| g.name.to_string()).collect(),
+                            params: params.iter().map(|p| p.ty.clone()).collect(),
+                            ret: ret.clone(),
+                        },
+                    );
+                }
+                ast::Statement::Impl {
+                    generics: impl_generics,
+                    ty,
+                    methods,
+                    ..
+                } => {
+                    let subst = HashMap::from([(String::from("Self"), ty.clone())]);
+                    for method in methods {
+                        let ast::Statement::Function {
+                            name,
+                            generics,
+                            params,
+                            ret,
+                            ..
+                        } = method
+                        else {
+                            continue;
+                        };
+                        let Some(path) = ty.method_path(name) else {
+                            continue;
+                        };
+                        self.functions.insert(
+                            path,
+                            FunctionInfo {
+                                generics: impl_generics
+                                    .iter()
+                                    .chain(generics)
+                                    .map(|g| g.name.to_string())
+                                    .collect(),
+                                params: params.iter().map(|p| p.ty.substitute(&subst)).collect(),
+                                ret: ret.substitute(&subst),
+                            },
+                        );
+                    }
+                }
+                ast::Statement::Trait { name, methods, .. } => {
+                    self.traits.insert(name.to_string(), methods.clone());
+                }
+                ast::Statement::Extern {
+                    name, params, ret, ..
+                } => {
+                    self.functions.insert(
+                        name.to_string(),
+                        FunctionInfo {
+                            generics: vec![],
+                            params: params.iter().map(|p| p.ty.clone()).collect(),
+                            ret: ret.clone(),
+                        },
+                    );
+                }
+                ast::Statement::Const { name, ty, .. }
+                | ast::Statement::Static { name, ty, .. } => {
+                    self.globals.insert(name.to_string(), ty.clone());
+                }
+                ast::Statement::Struct {
+                    name,
+                    generics,
+                    fields,
+                    ..
+                } => {
+                    self.structs.insert(
+                        name.to_string(),
+                        (
+                            generics.iter().map(|g| g.name.to_string()).collect(),
+                            fields.clone(),
+                        ),
+                    );
+                }
+                ast::Statement::Enum {
+                    name,
+                    generics,
+                    variants,
+                    ..
+                } => {
+                    self.enums.insert(
+                        name.to_string(),
+                        (
+                            generics.iter().map(|g| g.name.to_string()).collect(),
+                            variants.clone(),
+                        ),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn fresh(&mut self, kind: Kind) -> Ty {
+        self.vars.push(Var {
+            kind,
+            bound: None,
+            name: None,
+        });
+        Ty::Var(self.vars.len() - 1)
+    }
+
+    /// Gives the type parameters of a use of the generic `name` fresh variables, returning
+    /// what to replace them with
+    fn instantiate(&mut self, name: &str, generics: &[String], here: usize) -> HashMap<String, Ty> {
+        let args: Vec<_> = generics.iter().map(|_| self.fresh(Kind::Any)).collect();
+        self.uses.push(Use {
+            name: name.to_string(),
+            generics: generics.to_vec(),
+            args: args.clone(),
+            here,
+        });
+        generics.iter().cloned().zip(args).collect()
+    }
+
+    /// Follows variables bound to other variables
+    fn root(&self, mut var: usize) -> usize {
+        while let Some((Ty::Var(next), _)) = &self.vars[var].bound {
+            var = *next;
+        }
+        var
+    }
+
+    /// `ty` with bound variables replaced and the outermost layer of known types spelled out
+    fn shape(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(var) => {
+                let var = self.root(*var);
+                match &self.vars[var].bound {
+                    Some((ty, _)) => self.shape(ty),
+                    None => Ty::Var(var),
+                }
+            }
+            Ty::Known(ast::Type::Array(elem, len)) => {
+                Ty::Array(Box::new(Ty::Known(*elem.clone())), *len)
+            }
+            Ty::Known(ast::Type::Pointer(inner)) => {
+                Ty::Pointer(Box::new(Ty::Known(*inner.clone())))
+            }
+            Ty::Known(ast::Type::Slice(elem)) => Ty::Slice(Box::new(Ty::Known(*elem.clone()))),
+            Ty::Known(ast::Type::Tuple(elems)) => {
+                Ty::Tuple(elems.iter().cloned().map(Ty::Known).collect())
+            }
+            Ty::Known(ast::Type::Generic(name, args)) => Ty::Generic(
+                name.to_string(),
+                args.iter().cloned().map(Ty::Known).collect(),
+            ),
+            Ty::Known(ast::Type::Function(params, ret)) => Ty::Function(
+                params.iter().cloned().map(Ty::Known).collect(),
+                Box::new(Ty::Known(*ret.clone())),
+            ),
+            ty => ty.clone(),
+        }
+    }
+
+    /// The concrete type `ty` stands for, if everything in it is known
+    fn resolve(&self, ty: &Ty) -> Option<ast::Type> {
+        match self.shape(ty) {
+            Ty::Known(ty) => Some(ty),
+            Ty::Array(elem, len) => Some(ast::Type::Array(Box::new(self.resolve(&elem)?), len)),
+            Ty::Pointer(inner) => Some(ast::Type::Pointer(Box::new(self.resolve(&inner)?))),
+            Ty::Slice(elem) => Some(ast::Type::Slice(Box::new(self.resolve(&elem)?))),
+            Ty::Tuple(elems) => Some(ast::Type::Tuple(
+                elems
+                    .iter()
+                    .map(|e| self.resolve(e))
+                    .collect::<Option<_>>()?,
+            )),
+            Ty::Generic(name, args) => Some(ast::Type::Generic(
+                name,
+                args.iter()
+                    .map(|a| self.resolve(a))
+                    .collect::<Option<_>>()?,
+            )),
+            Ty::Function(params, ret) => Some(ast::Type::Function(
+                params
+                    .iter()
+                    .map(|p| self.resolve(p))
+                    .collect::<Option<_>>()?,
+                Box::new(self.resolve(&ret)?),
+            )),
+            Ty::Var(_) | Ty::Unknown => None,
+        }
+    }
+
+    /// Whether `ty` still has parts nothing decided
+    fn is_ambiguous(&self, ty: &Ty) -> bool {
+        match self.shape(ty) {
+            Ty::Var(_) => true,
+            Ty::Array(inner, _) | Ty::Pointer(inner) | Ty::Slice(inner) => {
+                self.is_ambiguous(&inner)
+            }
+            Ty::Tuple(elems) | Ty::Generic(_, elems) => elems.iter().any(|e| self.is_ambiguous(e)),
+            Ty::Function(params, ret) => {
+                params.iter().any(|p| self.is_ambiguous(p)) || self.is_ambiguous(&ret)
+            }
+            Ty::Known(_) | Ty::Unknown => false,
+        }
+    }
+
+    fn occurs(&self, var: usize, ty: &Ty) -> bool {
+        match self.shape(ty) {
+            Ty::Var(other) => other == var,
+            Ty::Array(inner, _) | Ty::Pointer(inner) | Ty::Slice(inner) => self.occurs(var, &inner),
+            Ty::Tuple(elems) | Ty::Generic(_, elems) => elems.iter().any(|e| self.occurs(var, e)),
+            Ty::Function(params, ret) => {
+                params.iter().any(|p| self.occurs(var, p)) || self.occurs(var, &ret)
+            }
+            Ty::Known(_) | Ty::Unknown => false,
+        }
+    }
+
+    /// Makes `a` and `b` the same type, `here` being the expression that requires it
+    fn unify(&mut self, a: &Ty, b: &Ty, here: usize) -> Result<(), InferError> {
+        for (var, other) in [(a, b), (b, a)] {
+            let Ty::Var(var) = var else {
+                continue;
+            };
+            let var = &self.vars[self.root(*var)];
+            if let (Some((bound, at)), Some(name)) = (&var.bound, &var.name)
+                && let (Some(inferred), Some(got)) = (self.resolve(bound), self.resolve(other))
+                && !converts(&inferred, &got)
+            {
+                let (file, line_number, column_number) = self.src.locate(here);
+                return Err(InferError::Conflict {
+                    file,
+                    line_number,
+                    column_number,
+                    name: name.to_string(),
+                    inferred: Box::new(inferred),
+                    got,
+                    previous: Box::new(self.src.locate(*at)),
+                });
+            }
+        }
+        match (self.shape(a), self.shape(b)) {
+            (Ty::Var(x), Ty::Var(y)) if x == y => {}
+            (Ty::Var(var), other) | (other, Ty::Var(var)) => self.bind(var, other, here),
+            (Ty::Array(x, n), Ty::Array(y, m)) if n == m => self.unify(&x, &y, here)?,
+            (Ty::Slice(x), Ty::Slice(y)) => self.unify(&x, &y, here)?,
+            // Like `void *`, `*()` converts to any other pointer so it says nothing about them
+            (Ty::Pointer(x), Ty::Pointer(y)) => {
+                let untyped = |ty: &Ty| matches!(ty, Ty::Known(ast::Type::Unit));
+                if !untyped(&x) && !untyped(&y) {
+                    self.unify(&x, &y, here)?;
+                }
+            }
+            (Ty::Tuple(xs), Ty::Tuple(ys)) if xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(&ys) {
+                    self.unify(x, y, here)?;
+                }
+            }
+            (Ty::Generic(a, xs), Ty::Generic(b, ys)) if a == b && xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(&ys) {
+                    self.unify(x, y, here)?;
+                }
+            }
+            (Ty::Function(xs, x), Ty::Function(ys, y)) if xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(&ys) {
+                    self.unify(x, y, here)?;
+                }
+                self.unify(&x, &y, here)?;
+            }
+            // A plain mismatch, which `typeck` reports
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Binds the unbound `var` to `ty` unless a literal can't have that type, in which case
+    /// `typeck` reports the mismatch
+    fn bind(&mut self, var: usize, ty: Ty, here: usize) {
+        let kind = self.vars[var].kind;
+        match &ty {
+            Ty::Var(other) => {
+                self.vars[*other].kind = match (kind, self.vars[*other].kind) {
+                    (a, b) if a == b => a,
+                    (Kind::Any, kind) | (kind, Kind::Any) => kind,
+                    _ => return,
+                };
+                if self.vars[*other].name.is_none() {
+                    self.vars[*other].name = self.vars[var].name.clone();
+                }
+            }
+            Ty::Unknown => return,
+            ty => {
+                // Inside a generic function a literal can have the type of a type parameter,
+                // whether the instances can hold it is up to `typeck`
+                let param = matches!(ty, Ty::Known(ast::Type::Named(name))
+                    if self.generics.iter().any(|g| g.name == *name));
+                let fits = match kind {
+                    Kind::Integer => {
+                        param
+                            || matches!(
+                                ty,
+                                Ty::Known(ast::Type::I32 | ast::Type::I64 | ast::Type::U8)
+                            )
+                    }
+                    Kind::Float => {
+                        param || matches!(ty, Ty::Known(ast::Type::F32 | ast::Type::F64))
+                    }
+                    Kind::Any => !self.occurs(var, ty),
+                };
+                if !fits {
+                    return;
+                }
+            }
+        }
+        self.vars[var].bound = Some((ty, here));
+    }
+
+    /// Names the variable `ty` is, if it is one, after the program variable `name`
+    fn name(&mut self, ty: &Ty, name: &str) {
+        if let Ty::Var(var) = self.shape(ty) {
+            self.vars[var].name.get_or_insert_with(|| name.to_string());
+        }
+    }
+
+    /// Runs `f` with `bindings` in scope, dropping them and anything `f` defines afterwards
+    fn scoped<T>(&mut self, bindings: Vec<(String, Ty)>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = self.variables.clone();
+        self.variables.extend(bindings);
+        let result = f(self);
+        self.variables = outer;
+        result
+    }
+
+    /// The parameters, `self` included, and the return type of `method` called on a value of
+    /// type `receiver`, or a pointer to one
+    fn method(&mut self, receiver: &Ty, method: &str, here: usize) -> Option<(Vec<Ty>, Ty)> {
+        let ty = match self.shape(receiver) {
+            Ty::Pointer(inner) => self.shape(&inner),
+            ty => ty,
+        };
+        // Type parameters and trait objects have the methods of their traits
+        let bounds = match &ty {
+            Ty::Known(ast::Type::Named(name)) => self
+                .generics
+                .iter()
+                .find(|g| g.name == *name)
+                .map(|g| g.bounds.clone()),
+            Ty::Known(ast::Type::Dyn(name)) => Some(vec![name.to_string()]),
+            _ => None,
+        };
+        if let Some(bounds) = bounds {
+            let decl = bounds
+                .iter()
+                .filter_map(|b| self.traits.get(b))
+                .flatten()
+                .find(|m| m.name == method)?;
+            let subst = HashMap::from([(String::from("Self"), ty.clone())]);
+            let params = decl.params.iter().map(|p| substitute(&p.ty, &subst));
+            return Some((params.collect(), substitute(&decl.ret, &subst)));
+        }
+        let path = match &ty {
+            Ty::Known(ty) => ty.method_path(method)?,
+            Ty::Generic(name, _) => format!("{name}::{method}"),
+            _ => return None,
+        };
+        let info = self.functions.get(&path)?;
+        let (generics, params, ret) =
+            (info.generics.clone(), info.params.clone(), info.ret.clone());
+        let subst = if generics.is_empty() {
+            HashMap::new()
+        } else {
+            self.instantiate(&path, &generics, here)
+        };
+        let params = params.iter().map(|p| substitute(p, &subst));
+        Some((params.collect(), substitute(&ret, &subst)))
+    }
+
+    fn type_of(&mut self, expr: &ast::Expression) -> Result<Ty, InferError> {
+        Ok(match expr {
+            ast::Expression::Number { here, .. } => {
+                let ty = self.fresh(Kind::Integer);
+                self.literals.insert(*here, ty.clone());
+                ty
+            }
+            ast::Expression::Float { here, .. } => {
+                let ty = self.fresh(Kind::Float);
+                self.literals.insert(*here, ty.clone());
+                ty
+            }
+            ast::Expression::Char { .. } => Ty::Known(ast::Type::Char),
+            ast::Expression::Str { .. } => Ty::Known(ast::Type::Str),
+            ast::Expression::Variable { name, .. } => self
+                .variables
+                .get(name)
+                .cloned()
+                .or_else(|| self.globals.get(name).cloned().map(Ty::Known))
+                .or_else(|| {
+                    let info = self.functions.get(name)?;
+                    info.generics.is_empty().then(|| {
+                        Ty::Known(ast::Type::Function(
+                            info.params.clone().into(),
+                            Box::new(info.ret.clone()),
+                        ))
+                    })
+                })
+                .unwrap_or(Ty::Unknown),
+            ast::Expression::Binary { left, op, right } => {
+                let left = self.type_of(left)?;
+                let right = self.type_of(right)?;
+                if !matches!(self.shape(&left), Ty::Pointer(_)) {
+                    self.unify(&left, &right, op.here())?;
+                    left
+                } else if matches!(self.shape(&right), Ty::Pointer(_)) {
+                    Ty::Known(ast::Type::I64)
+                } else {
+                    self.unify(&right, &Ty::Known(ast::Type::I64), op.here())?;
+                    left
+                }
+            }
+            ast::Expression::Cast { value, to, here } => {
+                let got = self.type_of(value)?;
+                if let ast::Type::Pointer(inner) = to
+                    && let ast::Type::Dyn(_) = **inner
+                {
+                    self.receivers.insert(*here, got);
+                }
+                Ty::Known(to.clone())
+            }
+            ast::Expression::Array { elements, .. } => {
+                let Some((first, rest)) = elements.split_first() else {
+                    return Ok(Ty::Unknown);
+                };
+                let elem = self.type_of(first)?;
+                for e in rest {
+                    let got = self.type_of(e)?;
+                    self.unify(&elem, &got, e.here())?;
+                }
+                Ty::Array(Box::new(elem), elements.len() as u64)
+            }
+            ast::Expression::Tuple { elements, .. } => Ty::Tuple(
+                elements
+                    .iter()
+                    .map(|e| self.type_of(e))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ast::Expression::Index { array, index, .. } => {
+                let array = self.type_of(array)?;
+                let got = self.type_of(index)?;
+                self.unify(&got, &Ty::Known(ast::Type::I64), index.here())?;
+                match self.shape(&array) {
+                    Ty::Array(elem, _) | Ty::Pointer(elem) | Ty::Slice(elem) => *elem,
+                    Ty::Known(ast::Type::Str) => Ty::Known(ast::Type::U8),
+                    _ => Ty::Unknown,
+                }
+            }
+            ast::Expression::Slice {
+                value, start, end, ..
+            } => {
+                let ty = self.type_of(value)?;
+                for bound in start.iter().chain(end) {
+                    let got = self.type_of(bound)?;
+                    self.unify(&got, &Ty::Known(ast::Type::I64), bound.here())?;
+                }
+                match self.shape(&ty) {
+                    Ty::Array(elem, _) | Ty::Pointer(elem) | Ty::Slice(elem) => Ty::Slice(elem),
+                    Ty::Known(ast::Type::Str) => Ty::Known(ast::Type::Str),
+                    _ => Ty::Unknown,
+                }
+            }
+            ast::Expression::StructLiteral { name, fields, here } => {
+                let (generics, decls) = self.structs.get(name).cloned().unwrap_or_default();
+                let subst = if generics.is_empty() {
+                    HashMap::new()
+                } else {
+                    self.instantiate(name, &generics, *here)
+                };
+                for init in fields {
+                    let got = self.type_of(&init.value)?;
+                    if let Some(decl) = decls.iter().find(|f| f.name == init.name) {
+                        let expected = substitute(&decl.ty, &subst);
+                        self.unify(&got, &expected, init.value.here())?;
+                    }
+                }
+                if generics.is_empty() {
+                    Ty::Known(ast::Type::Named(name.to_string()))
+                } else {
+                    Ty::Generic(
+                        name.to_string(),
+                        generics.iter().map(|g| subst[g].clone()).collect(),
+                    )
+                }
+            }
+            ast::Expression::Field { value, field, .. } => {
+                let ty = self.type_of(value)?;
+                let ty = match self.shape(&ty) {
+                    Ty::Pointer(inner) => self.shape(&inner),
+                    ty => ty,
+                };
+                let (name, args) = match ty {
+                    Ty::Array(..) | Ty::Slice(_) | Ty::Known(ast::Type::Str) if field == "len" => {
+                        return Ok(Ty::Known(ast::Type::I64));
+                    }
+                    Ty::Tuple(elems) => {
+                        return Ok(field
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|i| elems.get(i).cloned())
+                            .unwrap_or(Ty::Unknown));
+                    }
+                    Ty::Known(ast::Type::Named(name)) => (name, vec![]),
+                    Ty::Generic(name, args) => (name, args),
+                    _ => return Ok(Ty::Unknown),
+                };
+                let Some((generics, fields)) = self.structs.get(&name) else {
+                    return Ok(Ty::Unknown);
+                };
+                let subst = generics.iter().cloned().zip(args).collect();
+                fields
+                    .iter()
+                    .find(|f| f.name == *field)
+                    .map_or(Ty::Unknown, |f| substitute(&f.ty, &subst))
+            }
+            ast::Expression::Variant {
+                ty,
+                variant,
+                args,
+                here,
+            } => {
+                let (generics, payload, subst) = self.variant(ty, variant, *here);
+                for (i, arg) in args.iter().enumerate() {
+                    let got = self.type_of(arg)?;
+                    if let Some(expected) = payload.get(i) {
+                        self.unify(&got, &substitute(expected, &subst), arg.here())?;
+                    }
+                }
+                enum_type(ty, &generics, &subst)
+            }
+            ast::Expression::Match { value, arms, .. } => {
+                let value_type = self.type_of(value)?;
+                let mut result: Option<Ty> = None;
+                for arm in arms {
+                    let bindings = match &arm.pattern {
+                        ast::Pattern::Variant {
+                            ty,
+                            variant,
+                            bindings,
+                            here,
+                        } => {
+                            let (generics, payload, subst) = self.variant(ty, variant, *here);
+                            if !generics.is_empty() {
+                                let expected = enum_type(ty, &generics, &subst);
+                                self.unify(&value_type, &expected, *here)?;
+                            }
+                            bindings
+                                .iter()
+                                .zip(payload)
+                                .map(|((name, _), ty)| (name.to_string(), substitute(&ty, &subst)))
+                                .collect()
+                        }
+                        _ => vec![],
+                    };
+                    let got = self.scoped(bindings, |inferer| inferer.type_of(&arm.value))?;
+                    match &result {
+                        Some(expected) => self.unify(expected, &got, arm.value.here())?,
+                        None => result = Some(got),
+                    }
+                }
+                result.unwrap_or(Ty::Unknown)
+            }
+            ast::Expression::Null { .. } => Ty::Pointer(Box::new(self.fresh(Kind::Any))),
+            ast::Expression::AddressOf { value, .. } => Ty::Pointer(Box::new(self.type_of(value)?)),
+            ast::Expression::Deref { value, .. } => {
+                let ty = self.type_of(value)?;
+                match self.shape(&ty) {
+                    Ty::Pointer(inner) => *inner,
+                    _ => Ty::Unknown,
+                }
+            }
+            // Variables holding function values shadow functions
+            ast::Expression::Call { name, args, .. } if self.variables.contains_key(name) => {
+                let callee = self.variables[name].clone();
+                let (params, ret) = match self.shape(&callee) {
+                    Ty::Function(params, ret) => (params, *ret),
+                    _ => (vec![], Ty::Unknown),
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    let got = self.type_of(arg)?;
+                    if let Some(expected) = params.get(i) {
+                        self.unify(&got, expected, arg.here())?;
+                    }
+                }
+                ret
+            }
+            ast::Expression::Closure {
+                params,
+                ret,
+                body,
+                here,
+            } => {
+                let mut bindings = vec![];
+                for p in params {
+                    let ty = match &p.ty {
+                        Some(ty) => Ty::Known(ty.clone()),
+                        None => {
+                            let ty = self.fresh(Kind::Any);
+                            self.name(&ty, &p.name);
+                            self.definitions.push(Definition {
+                                name: p.name.to_string(),
+                                ty: ty.clone(),
+                                here: p.here,
+                            });
+                            ty
+                        }
+                    };
+                    bindings.push((p.name.to_string(), ty));
+                }
+                let ret = match ret {
+                    Some(ret) => Ty::Known(ret.clone()),
+                    None => {
+                        let ty = self.fresh(Kind::Any);
+                        self.definitions.push(Definition {
+                            name: String::from("the closure's result"),
+                            ty: ty.clone(),
+                            here: *here,
+                        });
+                        ty
+                    }
+                };
+                let params = bindings.iter().map(|(_, ty)| ty.clone()).collect();
+                let outer_ret = std::mem::replace(&mut self.ret, ret.clone());
+                let result = self.scoped(bindings, |inferer| {
+                    body.iter().try_for_each(|st| inferer.infer_statement(st))
+                });
+                self.ret = outer_ret;
+                result?;
+                Ty::Function(params, Box::new(ret))
+            }
+            ast::Expression::Call { name, args, here } => {
+                let Some(info) = self.functions.get(name) else {
+                    for arg in args {
+                        self.type_of(arg)?;
+                    }
+                    return Ok(Ty::Unknown);
+                };
+                let (generics, params, ret) =
+                    (info.generics.clone(), info.params.clone(), info.ret.clone());
+                let subst = if generics.is_empty() {
+                    HashMap::new()
+                } else {
+                    self.instantiate(name, &generics, *here)
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    let got = self.type_of(arg)?;
+                    if let Some(expected) = params.get(i) {
+                        self.unify(&got, &substitute(expected, &subst), arg.here())?;
+                    }
+                }
+                substitute(&ret, &subst)
+            }
+            ast::Expression::MethodCall {
+                value,
+                method,
+                args,
+                here,
+            } => {
+                let receiver = self.type_of(value)?;
+                self.receivers.insert(*here, receiver.clone());
+                let Some((params, ret)) = self.method(&receiver, method, *here) else {
+                    for arg in args {
+                        self.type_of(arg)?;
+                    }
+                    return Ok(Ty::Unknown);
+                };
+                // The receiver is passed by address or by value, whichever `self` takes
+                if let Some(expected) = params.first() {
+                    match (self.shape(expected), self.shape(&receiver)) {
+                        (Ty::Pointer(_), Ty::Pointer(_)) => {
+                            self.unify(&receiver, expected, value.here())?
+                        }
+                        (Ty::Pointer(_), _) => self.unify(
+                            &Ty::Pointer(Box::new(receiver.clone())),
+                            expected,
+                            value.here(),
+                        )?,
+                        (_, Ty::Pointer(inner)) => self.unify(&inner, expected, value.here())?,
+                        _ => self.unify(&receiver, expected, value.here())?,
+                    }
+                }
+                for (i, arg) in args.iter().enumerate() {
+                    let got = self.type_of(arg)?;
+                    if let Some(expected) = params.get(i + 1) {
+                        self.unify(&got, expected, arg.here())?;
+                    }
+                }
+                ret
+            }
+            // The enclosing function returns the same kind of enum, with the same error
+            ast::Expression::Try { value, here } => {
+                let ty = self.type_of(value)?;
+                let (payload, ret) = match self.shape(&ty) {
+                    Ty::Generic(name, args) if name == "Option" && args.len() == 1 => {
+                        let ret = Ty::Generic(name, vec![self.fresh(Kind::Any)]);
+                        (args[0].clone(), ret)
+                    }
+                    Ty::Generic(name, args) if name == "Result" && args.len() == 2 => {
+                        let ret = Ty::Generic(name, vec![self.fresh(Kind::Any), args[1].clone()]);
+                        (args[0].clone(), ret)
+                    }
+                    _ => return Ok(Ty::Unknown),
+                };
+                let expected = self.ret.clone();
+                self.unify(&ret, &expected, *here)?;
+                payload
+            }
+        })
+    }
+
+    /// The type parameters of the enum `ty` and the payload of its `variant`, along with
+    /// fresh variables for the parameters of the use at `here`
+    fn variant(
+        &mut self,
+        ty: &str,
+        variant: &str,
+        here: usize,
+    ) -> (Vec<String>, Vec<ast::Type>, HashMap<String, Ty>) {
+        let Some((generics, variants)) = self.enums.get(ty).cloned() else {
+            return (vec![], vec![], HashMap::new());
+        };
+        let payload = variants
+            .into_iter()
+            .find(|v| v.name == variant)
+            .map(|v| v.payload)
+            .unwrap_or_default();
+        let subst = if generics.is_empty() {
+            HashMap::new()
+        } else {
+            self.instantiate(ty, &generics, here)
+        };
+        (generics, payload, subst)
+    }
+
+    /// Infers the body of a function or method with `params` in scope
+    fn infer_function(
+        &mut self,
+        generics: Vec<ast::Generic>,
+        params: &[ast::Param],
+        ret: ast::Type,
+        body: &[ast::Statement],
+    ) -> Result<(), InferError> {
+        let params = params
+            .iter()
+            .map(|p| (p.name.to_string(), Ty::Known(p.ty.clone())))
+            .collect();
+        let outer_variables = std::mem::replace(&mut self.variables, params);
+        let outer_ret = std::mem::replace(&mut self.ret, Ty::Known(ret));
+        let outer_generics = std::mem::replace(&mut self.generics, generics);
+        let result = body.iter().try_for_each(|st| self.infer_statement(st));
+        self.variables = outer_variables;
+        self.ret = outer_ret;
+        self.generics = outer_generics;
+        result
+    }
+
+    fn infer_statement(&mut self, st: &ast::Statement) -> Result<(), InferError> {
+        match st {
+            ast::Statement::Return(value) => {
+                let got = self.type_of(value)?;
+                let expected = self.ret.clone();
+                self.unify(&got, &expected, value.here())?;
+            }
+            ast::Statement::DefineVar {
+                name,
+                ty,
+                value,
+                here,
+                ..
+            } => {
+                let got = self.type_of(value)?;
+                let ty = match ty {
+                    Some(ty) => {
+                        let ty = Ty::Known(ty.clone());
+                        self.unify(&got, &ty, value.here())?;
+                        ty
+                    }
+                    None => {
+                        self.name(&got, name);
+                        self.definitions.push(Definition {
+                            name: name.to_string(),
+                            ty: got.clone(),
+                            here: *here,
+                        });
+                        got
+                    }
+                };
+                self.variables.insert(name.to_string(), ty);
+            }
+            ast::Statement::DefineTuple { names, ty, value } => {
+                let got = self.type_of(value)?;
+                if let Some(ty) = ty {
+                    self.unify(&got, &Ty::Known(ty.clone()), value.here())?;
+                }
+                let elems = match self.shape(&ty.clone().map_or(got, Ty::Known)) {
+                    Ty::Tuple(elems) if elems.len() == names.len() => elems,
+                    _ => vec![Ty::Unknown; names.len()],
+                };
+                for (binding, elem) in names.iter().zip(elems) {
+                    if ty.is_none() {
+                        self.name(&elem, &binding.name);
+                        self.definitions.push(Definition {
+                            name: binding.name.to_string(),
+                            ty: elem.clone(),
+                            here: binding.here,
+                        });
+                    }
+                    self.variables.insert(binding.name.to_string(), elem);
+                }
+            }
+            ast::Statement::Assign { target, value } => {
+                let expected = self.type_of(target)?;
+                let got = self.type_of(value)?;
+                self.unify(&got, &expected, value.here())?;
+            }
+            ast::Statement::Expression(value) => {
+                self.type_of(value)?;
+            }
+            ast::Statement::Function {
+                generics,
+                params,
+                ret,
+                body,
+                ..
+            } => self.infer_function(generics.clone(), params, ret.clone(), body)?,
+            ast::Statement::Impl {
+                generics: impl_generics,
+                ty,
+                methods,
+                ..
+            } => {
+                let subst = HashMap::from([(String::from("Self"), ty.clone())]);
+                for method in methods {
+                    let ast::Statement::Function {
+                        generics,
+                        params,
+                        ret,
+                        body,
+                        ..
+                    } = method
+                    else {
+                        continue;
+                    };
+                    let params: Vec<_> = params
+                        .iter()
+                        .map(|p| ast::Param {
+                            ty: p.ty.substitute(&subst),
+                            ..p.clone()
+                        })
+                        .collect();
+                    let generics = impl_generics.iter().chain(generics).cloned().collect();
+                    self.infer_function(generics, &params, ret.substitute(&subst), body)?;
+                }
+            }
+            ast::Statement::For {
+                var,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                let ty = self.type_of(start)?;
+                let got = self.type_of(end)?;
+                self.unify(&ty, &got, end.here())?;
+                self.scoped(vec![(var.to_string(), ty)], |inferer| {
+                    body.iter().try_for_each(|st| inferer.infer_statement(st))
+                })?;
+            }
+            ast::Statement::Defer { stmt, .. } => {
+                self.scoped(vec![], |inferer| inferer.infer_statement(stmt))?;
+            }
+            // Globals are inferred up front, everything else has nothing to infer
+            ast::Statement::Struct { .. }
+            | ast::Statement::Enum { .. }
+            | ast::Statement::Trait { .. }
+            | ast::Statement::Extern { .. }
+            | ast::Statement::Const { .. }
+            | ast::Statement::Static { .. }
+            | ast::Statement::Mod { .. }
+            | ast::Statement::Break { .. }
+            | ast::Statement::Continue { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn literal_type(&self, here: usize) -> Option<ast::Type> {
+        self.resolve(self.literals.get(&here)?)
+    }
+
+    fn annotate_statement(
+        &self,
+        st: &mut ast::Statement,
+        types: &HashMap<usize, ast::Type>,
+    ) -> Result<(), InferError> {
+        match st {
+            ast::Statement::DefineVar {
+                ty, value, here, ..
+            } => {
+                if ty.is_none() {
+                    *ty = types.get(here).cloned();
+                }
+                self.annotate_expression(value, types)
+            }
+            ast::Statement::DefineTuple { names, ty, value } => {
+                if ty.is_none() {
+                    *ty = names
+                        .iter()
+                        .map(|b| types.get(&b.here).cloned())
+                        .collect::<Option<_>>()
+                        .map(ast::Type::Tuple);
+                }
+                self.annotate_expression(value, types)
+            }
+            ast::Statement::Return(value)
+            | ast::Statement::Expression(value)
+            | ast::Statement::Const { value, .. }
+            | ast::Statement::Static { value, .. } => self.annotate_expression(value, types),
+            ast::Statement::Assign { target, value } => {
+                self.annotate_expression(target, types)?;
+                self.annotate_expression(value, types)
+            }
+            ast::Statement::Function { body, .. } => body
+                .iter_mut()
+                .try_for_each(|st| self.annotate_statement(st, types)),
+            ast::Statement::Impl { methods, .. } => methods
+                .iter_mut()
+                .try_for_each(|st| self.annotate_statement(st, types)),
+            ast::Statement::For {
+                start, end, body, ..
+            } => {
+                self.annotate_expression(start, types)?;
+                self.annotate_expression(end, types)?;
+                body.iter_mut()
+                    .try_for_each(|st| self.annotate_statement(st, types))
+            }
+            ast::Statement::Defer { stmt, .. } => self.annotate_statement(stmt, types),
+            ast::Statement::Struct { .. }
+            | ast::Statement::Enum { .. }
+            | ast::Statement::Trait { .. }
+            | ast::Statement::Extern { .. }
+            | ast::Statement::Mod { .. }
+            | ast::Statement::Break { .. }
+            | ast::Statement::Continue { .. } => Ok(()),
+        }
+    }
+
+    /// The inferred type of what's defined at `here`. Unlike variables, closures have nowhere
+    /// to fall back to their value's type, so anything unresolved is ambiguous
+    fn annotation(
+        &self,
+        types: &HashMap<usize, ast::Type>,
+        name: &str,
+        here: usize,
+    ) -> Result<ast::Type, InferError> {
+        types.get(&here).cloned().ok_or_else(|| {
+            let (file, line_number, column_number) = self.src.locate(here);
+            InferError::Ambiguous {
+                file,
+                line_number,
+                column_number,
+                name: name.to_string(),
+            }
+        })
+    }
+
+    /// Wraps every literal that isn't an i64 or f64 in a cast to its type
+    fn annotate_expression(
+        &self,
+        expr: &mut ast::Expression,
+        types: &HashMap<usize, ast::Type>,
+    ) -> Result<(), InferError> {
+        match expr {
+            ast::Expression::Number { value, here, .. } => {
+                let (value, here) = (*value, *here);
+                let (ty, max) = match self.literal_type(here) {
+                    Some(ty @ ast::Type::I32) => (ty, i32::MAX as u64),
+                    Some(ty @ ast::Type::U8) => (ty, u8::MAX as u64),
+                    // A type parameter, the cast is checked in every instance
+                    Some(ty @ ast::Type::Named(_)) => (ty, u64::MAX),
+                    // There's no unary minus, so i64::MIN can't be written as a literal either
+                    Some(ast::Type::I64) | None => (ast::Type::I64, i64::MAX as u64),
+                    _ => return Ok(()),
+                };
+                if value > max {
+                    let (file, line_number, column_number) = self.src.locate(here);
+                    return Err(InferError::LiteralOutOfRange {
+                        file,
+                        line_number,
+                        column_number,
+                        value,
+                        ty,
+                    });
+                }
+                if ty != ast::Type::I64 {
+                    narrow(expr, ty);
+                }
+            }
+            ast::Expression::Float { here, .. } => {
+                if let Some(ty @ (ast::Type::F32 | ast::Type::Named(_))) = self.literal_type(*here)
+                {
+                    narrow(expr, ty);
+                }
+            }
+            ast::Expression::Binary { left, right, .. } => {
+                self.annotate_expression(left, types)?;
+                self.annotate_expression(right, types)?;
+            }
+            ast::Expression::Index { array, index, .. } => {
+                self.annotate_expression(array, types)?;
+                self.annotate_expression(index, types)?;
+            }
+            ast::Expression::Slice {
+                value, start, end, ..
+            } => {
+                self.annotate_expression(value, types)?;
+                for bound in start.iter_mut().chain(end) {
+                    self.annotate_expression(bound, types)?;
+                }
+            }
+            ast::Expression::MethodCall { value, args, .. } => {
+                self.annotate_expression(value, types)?;
+                for e in args {
+                    self.annotate_expression(e, types)?;
+                }
+            }
+            ast::Expression::Cast { value, .. }
+            | ast::Expression::Field { value, .. }
+            | ast::Expression::AddressOf { value, .. }
+            | ast::Expression::Deref { value, .. }
+            | ast::Expression::Try { value, .. } => self.annotate_expression(value, types)?,
+            ast::Expression::Array { elements, .. }
+            | ast::Expression::Tuple { elements, .. }
+            | ast::Expression::Variant { args: elements, .. }
+            | ast::Expression::Call { args: elements, .. } => {
+                for e in elements {
+                    self.annotate_expression(e, types)?;
+                }
+            }
+            ast::Expression::StructLiteral { fields, .. } => {
+                for f in fields {
+                    self.annotate_expression(&mut f.value, types)?;
+                }
+            }
+            ast::Expression::Match { value, arms, .. } => {
+                self.annotate_expression(value, types)?;
+                for arm in arms {
+                    self.annotate_expression(&mut arm.value, types)?;
+                }
+            }
+            ast::Expression::Closure {
+                params,
+                ret,
+                body,
+                here,
+            } => {
+                for p in params.iter_mut().filter(|p| p.ty.is_none()) {
+                    p.ty = Some(self.annotation(types, &p.name, p.here)?);
+                }
+                if ret.is_none() {
+                    *ret = Some(self.annotation(types, "the closure's result", *here)?);
+                }
+                for st in body {
+                    self.annotate_statement(st, types)?;
+                }
+            }
+            ast::Expression::Char { .. }
+            | ast::Expression::Str { .. }
+            | ast::Expression::Variable { .. }
+            | ast::Expression::Null { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+/// `ty` with the type parameters in `subst` replaced
+fn substitute(ty: &ast::Type, subst: &HashMap<String, Ty>) -> Ty {
+    match ty {
+        ast::Type::Named(name) if subst.contains_key(name) => subst[name].clone(),
+        ast::Type::Array(elem, len) => Ty::Array(Box::new(substitute(elem, subst)), *len),
+        ast::Type::Pointer(inner) => Ty::Pointer(Box::new(substitute(inner, subst))),
+        ast::Type::Slice(elem) => Ty::Slice(Box::new(substitute(elem, subst))),
+        ast::Type::Tuple(elems) => Ty::Tuple(elems.iter().map(|e| substitute(e, subst)).collect()),
+        ast::Type::Generic(name, args) => Ty::Generic(
+            name.to_string(),
+            args.iter().map(|a| substitute(a, subst)).collect(),
+        ),
+        ast::Type::Function(params, ret) => Ty::Function(
+            params.iter().map(|p| substitute(p, subst)).collect(),
+            Box::new(substitute(ret, subst)),
+        ),
+        ty => Ty::Known(ty.clone()),
+    }
+}
+
+/// The type of a value of the enum `name`, in terms of the variables in `subst` if it's generic
+fn enum_type(name: &str, generics: &[String], subst: &HashMap<String, Ty>) -> Ty {
+    if generics.is_empty() {
+        Ty::Known(ast::Type::Named(name.to_string()))
+    } else {
+        Ty::Generic(
+            name.to_string(),
+            generics.iter().map(|g| subst[g].clone()).collect(),
+        )
+    }
+}
+
+/// Whether a value of type `got` can be used where `inferred` was, see `typeck::check_value`
+fn converts(inferred: &ast::Type, got: &ast::Type) -> bool {
+    let untyped = ast::Type::Pointer(Box::new(ast::Type::Unit));
+    inferred == got
+        || matches!(
+            (inferred, got),
+            (ast::Type::Pointer(_), ast::Type::Pointer(_))
+        ) && (*inferred == untyped || *got == untyped)
+}
+
+fn narrow(literal: &mut ast::Expression, to: ast::Type) {
+    let here = literal.here();
+    let value = std::mem::replace(literal, ast::Expression::Null { here });
+    *literal = ast::Expression::Cast {
+        value: Box::new(value),
+        to,
+        here,
+    };
+}
+
+/// Infers the types of unannotated variables and literals, writing them into the program, and
+/// the type arguments of generic functions and structs and the types of method receivers
+pub fn infer(
+    program: &mut [ast::Statement],
+    src: &source::SourceMap,
+) -> Result<(Instantiations, Receivers), InferError> {
+    let mut inferer = Inferer {
+        src,
+        vars: vec![],
+        variables: HashMap::new(),
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        structs: HashMap::new(),
+        enums: HashMap::new(),
+        traits: HashMap::new(),
+        // `main` returns the process exit code
+        ret: Ty::Known(ast::Type::I64),
+        generics: vec![],
+        uses: vec![],
+        receivers: HashMap::new(),
+        literals: HashMap::new(),
+        definitions: vec![],
+    };
+    inferer.declare(program);
+    for st in program.iter() {
+        if let ast::Statement::Const { ty, value, .. } | ast::Statement::Static { ty, value, .. } =
+            st
+        {
+            let got = inferer.type_of(value)?;
+            inferer.unify(&got, &Ty::Known(ty.clone()), value.here())?;
+        }
+    }
+    for st in program.iter() {
+        inferer.infer_statement(st)?;
+    }
+
+    for var in &mut inferer.vars {
+        let default = match var.kind {
+            Kind::Integer => ast::Type::I64,
+            Kind::Float => ast::Type::F64,
+            Kind::Any => continue,
+        };
+        var.bound.get_or_insert((Ty::Known(default), 0));
+    }
+    let mut instantiations = HashMap::new();
+    for u in &inferer.uses {
+        let mut args = vec![];
+        for (param, arg) in u.generics.iter().zip(&u.args) {
+            let Some(arg) = inferer.resolve(arg) else {
+                let (file, line_number, column_number) = src.locate(u.here);
+                return Err(InferError::UninferredParameter {
+                    file,
+                    line_number,
+                    column_number,
+                    name: u.name.to_string(),
+                    param: param.to_string(),
+                });
+            };
+            args.push(arg);
+        }
+        instantiations.insert(u.here, args);
+    }
+    if let Some(definition) = inferer
+        .definitions
+        .iter()
+        .find(|d| inferer.is_ambiguous(&d.ty))
+    {
+        let (file, line_number, column_number) = src.locate(definition.here);
+        return Err(InferError::Ambiguous {
+            file,
+            line_number,
+            column_number,
+            name: definition.name.to_string(),
+        });
+    }
+    let types = inferer
+        .definitions
+        .iter()
+        .filter_map(|d| Some((d.here, inferer.resolve(&d.ty)?)))
+        .collect();
+    program
+        .iter_mut()
+        .try_for_each(|st| inferer.annotate_statement(st, &types))?;
+    let receivers = inferer
+        .receivers
+        .iter()
+        .filter_map(|(here, ty)| Some((*here, inferer.resolve(ty)?)))
+        .collect();
+    Ok((instantiations, receivers))
+}
+
+impl std::fmt::Display for InferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Conflict {
+                file,
+                line_number,
+                column_number,
+                name,
+                inferred,
+                got,
+                previous,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Conflicting types for {name}, used as {got} here").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    previous.0.as_deref(),
+                    (previous.1, previous.2),
+                    format!("{name} was inferred to be {inferred} here").as_str(),
+                )
+            }
+            Self::Ambiguous {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot infer the type of {name}, consider annotating it").as_str(),
+            ),
+            Self::UninferredParameter {
+                file,
+                line_number,
+                column_number,
+                name,
+                param,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot infer {param} for {name}, consider annotating the result").as_str(),
+            ),
+            Self::LiteralOutOfRange {
+                file,
+                line_number,
+                column_number,
+                value,
+                ty,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Literal {value} does not fit in {ty}").as_str(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+
+    fn infer_str(s: &str) -> Result<Vec<ast::Statement>, InferError> {
+        let src = source::Source::new(s);
+        let tokens = lexer::lex_file(src.clone()).unwrap();
+        let mut program = ast::parse(&tokens).unwrap();
+        infer(&mut program, &src.into())?;
+        Ok(program)
+    }
+
+    fn annotations(program: &[ast::Statement]) -> Vec<Option<ast::Type>> {
+        program
+            .iter()
+            .filter_map(|st| match st {
+                ast::Statement::DefineVar { ty, .. } | ast::Statement::DefineTuple { ty, .. } => {
+                    Some(ty.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn from_uses() {
+        let program = infer_str(
+            "fn f(x: f32) {}\nvar a = 1;\nlet b: u8 = a + 2;\nlet c = 1.5;\nf(c);\nvar p = null;\n*p = (a, c);",
+        )
+        .unwrap();
+        assert_eq!(
+            annotations(&program),
+            vec![
+                Some(ast::Type::U8),
+                Some(ast::Type::U8),
+                Some(ast::Type::F32),
+                Some(ast::Type::Pointer(Box::new(ast::Type::Tuple(vec![
+                    ast::Type::U8,
+                    ast::Type::F32
+                ])))),
+            ]
+        );
+        assert!(matches!(
+            &program[1],
+            ast::Statement::DefineVar {
+                value: ast::Expression::Cast {
+                    to: ast::Type::U8,
+                    ..
+                },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn defaults() {
+        let program = infer_str("var a = 1;\nvar (b, c) = (a * 2, 0.5);").unwrap();
+        assert_eq!(
+            annotations(&program),
+            vec![
+                Some(ast::Type::I64),
+                Some(ast::Type::Tuple(vec![ast::Type::I64, ast::Type::F64])),
+            ]
+        );
+        assert!(matches!(
+            &program[0],
+            ast::Statement::DefineVar {
+                value: ast::Expression::Number { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn closures() {
+        let program = infer_str("let f = |x| x + 1;\nlet a: u8 = f(2);").unwrap();
+        assert_eq!(
+            annotations(&program),
+            vec![
+                Some(ast::Type::Function(
+                    Box::new([ast::Type::U8]),
+                    Box::new(ast::Type::U8)
+                )),
+                Some(ast::Type::U8),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            infer_str("var x = 1;\nlet a: i32 = x;\nlet b: u8 = x;"),
+            Err(InferError::Conflict {
+                file: None,
+                line_number: 3,
+                column_number: 13,
+                name: String::from("x"),
+                inferred: Box::new(ast::Type::I32),
+                got: ast::Type::U8,
+                previous: Box::new((None, 2, 14)),
+            })
+        );
+        assert_eq!(
+            infer_str("var p = null;\nfree(p);"),
+            Err(InferError::Ambiguous {
+                file: None,
+                line_number: 1,
+                column_number: 5,
+                name: String::from("p"),
+            })
+        );
+        assert_eq!(
+            infer_str("let b: u8 = 256;"),
+            Err(InferError::LiteralOutOfRange {
+                file: None,
+                line_number: 1,
+                column_number: 13,
+                value: 256,
+                ty: ast::Type::U8,
+            })
+        );
+        assert_eq!(
+            infer_str("let a = 9223372036854775808;"),
+            Err(InferError::LiteralOutOfRange {
+                file: None,
+                line_number: 1,
+                column_number: 9,
+                value: 9223372036854775808,
+                ty: ast::Type::I64,
+            })
+        );
+    }
+}
@@ -4,20 +4,118 @@ use thiserror::Error;
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Number { value: u64, here: usize, len: usize },
+    Float { value: f64, here: usize, len: usize },
+    Char { value: char, here: usize },
+    Str { value: String, here: usize },
     Plus { here: usize },
     Minus { here: usize },
     Star { here: usize },
+    Slash { here: usize },
     OpenParen { here: usize },
     CloseParen { here: usize },
     OpenCurly { here: usize },
     CloseCurly { here: usize },
+    OpenBracket { here: usize },
+    CloseBracket { here: usize },
     Return { here: usize },
     Var { here: usize },
+    Let { here: usize },
+    Mut { here: usize },
+    As { here: usize },
+    Struct { here: usize },
+    Enum { here: usize },
+    Match { here: usize },
+    Fn { here: usize },
+    Extern { here: usize },
+    Null { here: usize },
+    Const { here: usize },
+    Static { here: usize },
+    For { here: usize },
+    In { here: usize },
+    Mod { here: usize },
+    Trait { here: usize },
+    Impl { here: usize },
+    Dyn { here: usize },
+    Defer { here: usize },
+    Break { here: usize },
+    Continue { here: usize },
     Semicolon { here: usize },
+    Colon { here: usize },
+    ColonColon { here: usize },
+    Comma { here: usize },
+    Dot { here: usize },
+    DotDot { here: usize },
+    DotDotEqual { here: usize },
     Equal { here: usize },
+    FatArrow { here: usize },
+    Arrow { here: usize },
+    Ampersand { here: usize },
+    Pipe { here: usize },
+    Question { here: usize },
+    Less { here: usize },
+    Greater { here: usize },
     Ident { value: String, here: usize },
 }
 
+impl Token {
+    pub fn here(&self) -> usize {
+        match self {
+            Self::Number { here, .. }
+            | Self::Float { here, .. }
+            | Self::Char { here, .. }
+            | Self::Str { here, .. }
+            | Self::Plus { here }
+            | Self::Minus { here }
+            | Self::Star { here }
+            | Self::Slash { here }
+            | Self::OpenParen { here }
+            | Self::CloseParen { here }
+            | Self::OpenCurly { here }
+            | Self::CloseCurly { here }
+            | Self::OpenBracket { here }
+            | Self::CloseBracket { here }
+            | Self::Return { here }
+            | Self::Var { here }
+            | Self::Let { here }
+            | Self::Mut { here }
+            | Self::As { here }
+            | Self::Struct { here }
+            | Self::Enum { here }
+            | Self::Match { here }
+            | Self::Fn { here }
+            | Self::Extern { here }
+            | Self::Null { here }
+            | Self::Const { here }
+            | Self::Static { here }
+            | Self::For { here }
+            | Self::In { here }
+            | Self::Mod { here }
+            | Self::Trait { here }
+            | Self::Impl { here }
+            | Self::Dyn { here }
+            | Self::Defer { here }
+            | Self::Break { here }
+            | Self::Continue { here }
+            | Self::Semicolon { here }
+            | Self::Colon { here }
+            | Self::ColonColon { here }
+            | Self::Comma { here }
+            | Self::Dot { here }
+            | Self::DotDot { here }
+            | Self::DotDotEqual { here }
+            | Self::Equal { here }
+            | Self::FatArrow { here }
+            | Self::Arrow { here }
+            | Self::Ampersand { here }
+            | Self::Pipe { here }
+            | Self::Question { here }
+            | Self::Less { here }
+            | Self::Greater { here }
+            | Self::Ident { here, .. } => *here,
+        }
+    }
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum NumberLexError {
     Letter {
@@ -25,6 +123,44 @@ pub enum NumberLexError {
         line_number: usize,
         column_number: usize,
     },
+    TooLarge {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CharLexError {
+    Unterminated {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    Empty {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    InvalidEscape {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum StringLexError {
+    Unterminated {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    InvalidEscape {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
 }
 
 #[derive(Debug, Error, PartialEq)]
@@ -41,6 +177,8 @@ pub enum LexerError {
         column_number: usize,
     },
     Number(#[from] NumberLexError),
+    Char(#[from] CharLexError),
+    String(#[from] StringLexError),
 }
 
 pub type LexerResult<T, E> = Result<T, E>;
@@ -52,21 +190,62 @@ pub fn lex_file(mut src: source::Source) -> LexerResult<Vec<Token>, LexerError>
         src.skip_whitespace();
         let (l, col) = src.get_position(src.offset());
         match src.peek() {
+            Some('\'') => {
+                tokens.push(lex_char(&mut src)?);
+            }
+            Some('"') => {
+                tokens.push(lex_string(&mut src)?);
+            }
             Some(c) if c.is_ascii_digit() => {
-                tokens.push(lex_number(&mut src)?);
+                let after_dot = matches!(tokens.last(), Some(Token::Dot { .. }));
+                tokens.push(lex_number(&mut src, after_dot)?);
             }
             Some('+') => {
                 tokens.push(Token::Plus { here: src.offset() });
                 src.next();
             }
+            Some('-') if src.peek_nth(1) == Some(&'>') => {
+                tokens.push(Token::Arrow { here: src.offset() });
+                src.next();
+                src.next();
+            }
             Some('-') => {
                 tokens.push(Token::Minus { here: src.offset() });
                 src.next();
             }
+            Some('&') => {
+                tokens.push(Token::Ampersand { here: src.offset() });
+                src.next();
+            }
+            Some('|') => {
+                tokens.push(Token::Pipe { here: src.offset() });
+                src.next();
+            }
+            Some('?') => {
+                tokens.push(Token::Question { here: src.offset() });
+                src.next();
+            }
+            Some('<') => {
+                tokens.push(Token::Less { here: src.offset() });
+                src.next();
+            }
+            Some('>') => {
+                tokens.push(Token::Greater { here: src.offset() });
+                src.next();
+            }
             Some('*') => {
                 tokens.push(Token::Star { here: src.offset() });
                 src.next();
             }
+            Some('/') => {
+                tokens.push(Token::Slash { here: src.offset() });
+                src.next();
+            }
+            Some('=') if src.peek_nth(1) == Some(&'>') => {
+                tokens.push(Token::FatArrow { here: src.offset() });
+                src.next();
+                src.next();
+            }
             Some('=') => {
                 tokens.push(Token::Equal { here: src.offset() });
                 src.next();
@@ -75,6 +254,34 @@ pub fn lex_file(mut src: source::Source) -> LexerResult<Vec<Token>, LexerError>
                 tokens.push(Token::Semicolon { here: src.offset() });
                 src.next();
             }
+            Some(':') if src.peek_nth(1) == Some(&':') => {
+                tokens.push(Token::ColonColon { here: src.offset() });
+                src.next();
+                src.next();
+            }
+            Some(':') => {
+                tokens.push(Token::Colon { here: src.offset() });
+                src.next();
+            }
+            Some(',') => {
+                tokens.push(Token::Comma { here: src.offset() });
+                src.next();
+            }
+            Some('.') if src.peek_nth(1) == Some(&'.') && src.peek_nth(2) == Some(&'=') => {
+                tokens.push(Token::DotDotEqual { here: src.offset() });
+                src.next();
+                src.next();
+                src.next();
+            }
+            Some('.') if src.peek_nth(1) == Some(&'.') => {
+                tokens.push(Token::DotDot { here: src.offset() });
+                src.next();
+                src.next();
+            }
+            Some('.') => {
+                tokens.push(Token::Dot { here: src.offset() });
+                src.next();
+            }
             Some('(') => {
                 tokens.push(Token::OpenParen { here: src.offset() });
                 src.next();
@@ -91,11 +298,39 @@ pub fn lex_file(mut src: source::Source) -> LexerResult<Vec<Token>, LexerError>
                 tokens.push(Token::CloseCurly { here: src.offset() });
                 src.next();
             }
+            Some('[') => {
+                tokens.push(Token::OpenBracket { here: src.offset() });
+                src.next();
+            }
+            Some(']') => {
+                tokens.push(Token::CloseBracket { here: src.offset() });
+                src.next();
+            }
             Some(c) if c.is_alphabetic() || *c == '_' => {
                 let (begin, _len, ident) = lex_ident(&mut src);
                 match ident.as_str() {
                     "return" => tokens.push(Token::Return { here: begin }),
                     "var" => tokens.push(Token::Var { here: begin }),
+                    "let" => tokens.push(Token::Let { here: begin }),
+                    "mut" => tokens.push(Token::Mut { here: begin }),
+                    "as" => tokens.push(Token::As { here: begin }),
+                    "struct" => tokens.push(Token::Struct { here: begin }),
+                    "enum" => tokens.push(Token::Enum { here: begin }),
+                    "match" => tokens.push(Token::Match { here: begin }),
+                    "fn" => tokens.push(Token::Fn { here: begin }),
+                    "extern" => tokens.push(Token::Extern { here: begin }),
+                    "null" => tokens.push(Token::Null { here: begin }),
+                    "const" => tokens.push(Token::Const { here: begin }),
+                    "static" => tokens.push(Token::Static { here: begin }),
+                    "for" => tokens.push(Token::For { here: begin }),
+                    "in" => tokens.push(Token::In { here: begin }),
+                    "mod" => tokens.push(Token::Mod { here: begin }),
+                    "trait" => tokens.push(Token::Trait { here: begin }),
+                    "impl" => tokens.push(Token::Impl { here: begin }),
+                    "dyn" => tokens.push(Token::Dyn { here: begin }),
+                    "defer" => tokens.push(Token::Defer { here: begin }),
+                    "break" => tokens.push(Token::Break { here: begin }),
+                    "continue" => tokens.push(Token::Continue { here: begin }),
                     _ => tokens.push(Token::Ident {
                         here: begin,
                         value: ident,
@@ -132,17 +367,42 @@ fn lex_ident(src: &mut source::Source) -> (usize, usize, String) {
     (
         begin,
         src.offset() - begin,
-        src.as_string().get(begin..src.offset()).unwrap().to_owned(),
+        src.slice(begin, src.offset()).iter().collect(),
     )
 }
 
-fn lex_number(src: &mut source::Source) -> LexerResult<Token, NumberLexError> {
+/// `after_dot` numbers are tuple fields, so `t.0.1` doesn't lex `0.1` as a float
+fn lex_number(src: &mut source::Source, after_dot: bool) -> LexerResult<Token, NumberLexError> {
     let begin = src.offset();
+    let mut is_float = false;
 
     while src.peek().is_some_and(char::is_ascii_digit) {
         src.next();
     }
 
+    // Only treat the dot as a fraction when a digit follows, so `1.` never eats into
+    // whatever comes after it
+    if !after_dot && src.peek() == Some(&'.') && src.peek_nth(1).is_some_and(char::is_ascii_digit) {
+        is_float = true;
+        src.next();
+        while src.peek().is_some_and(char::is_ascii_digit) {
+            src.next();
+        }
+    }
+
+    if !after_dot && matches!(src.peek(), Some('e' | 'E')) {
+        let sign = usize::from(matches!(src.peek_nth(1), Some('+' | '-')));
+        if src.peek_nth(1 + sign).is_some_and(char::is_ascii_digit) {
+            is_float = true;
+            for _ in 0..=sign {
+                src.next();
+            }
+            while src.peek().is_some_and(char::is_ascii_digit) {
+                src.next();
+            }
+        }
+    }
+
     match src.peek() {
         Some(c) if c.is_ascii_alphabetic() => {
             let (l, c) = src.get_position(begin);
@@ -153,18 +413,154 @@ fn lex_number(src: &mut source::Source) -> LexerResult<Token, NumberLexError> {
                 line_number: l,
             })
         }
-        _ => Ok(Token::Number {
-            here: begin,
-            len: src.offset() - begin,
-            value: src
-                .src()
-                .get(begin..src.offset())
-                .unwrap()
-                .iter()
-                .collect::<String>()
-                .parse()
-                .unwrap(),
-        }),
+        _ => {
+            let text = src.slice(begin, src.offset()).iter().collect::<String>();
+            if is_float {
+                Ok(Token::Float {
+                    here: begin,
+                    len: src.offset() - begin,
+                    value: text.parse().unwrap(),
+                })
+            } else {
+                let Ok(value) = text.parse() else {
+                    let (line_number, column_number) = src.get_position(begin);
+                    return Err(NumberLexError::TooLarge {
+                        file: src.path().map(|s| s.to_string()),
+                        line_number,
+                        column_number,
+                    });
+                };
+                Ok(Token::Number {
+                    here: begin,
+                    len: src.offset() - begin,
+                    value,
+                })
+            }
+        }
+    }
+}
+
+/// Lexes `'c'`, escapes are `\n`, `\r`, `\t`, `\0`, `\\`, `\'`, `\"`, `\x41` up to
+/// `\x7F` and `\u{1F600}`
+fn lex_char(src: &mut source::Source) -> LexerResult<Token, CharLexError> {
+    let begin = src.offset();
+    let (line_number, column_number) = src.get_position(begin);
+    let file = src.path().map(|s| s.to_string());
+    src.next();
+    let value = match src.peek().copied() {
+        Some('\'') => {
+            return Err(CharLexError::Empty {
+                file,
+                line_number,
+                column_number,
+            });
+        }
+        Some('\\') => {
+            let (line_number, column_number) = src.get_position(src.offset());
+            src.next();
+            lex_escape(src).ok_or(CharLexError::InvalidEscape {
+                file: file.clone(),
+                line_number,
+                column_number,
+            })?
+        }
+        Some(c) if c != '\n' => {
+            src.next();
+            c
+        }
+        _ => {
+            return Err(CharLexError::Unterminated {
+                file,
+                line_number,
+                column_number,
+            });
+        }
+    };
+    if src.peek() != Some(&'\'') {
+        return Err(CharLexError::Unterminated {
+            file,
+            line_number,
+            column_number,
+        });
+    }
+    src.next();
+    Ok(Token::Char { value, here: begin })
+}
+
+/// Lexes `"text"`, with the same escapes as characters. Strings may span lines
+fn lex_string(src: &mut source::Source) -> LexerResult<Token, StringLexError> {
+    let begin = src.offset();
+    let (line_number, column_number) = src.get_position(begin);
+    let file = src.path().map(|s| s.to_string());
+    src.next();
+    let mut value = String::new();
+    loop {
+        match src.peek().copied() {
+            Some('"') => break,
+            Some('\\') => {
+                let (line_number, column_number) = src.get_position(src.offset());
+                src.next();
+                value.push(lex_escape(src).ok_or(StringLexError::InvalidEscape {
+                    file: file.clone(),
+                    line_number,
+                    column_number,
+                })?);
+            }
+            Some(c) => {
+                src.next();
+                value.push(c);
+            }
+            None => {
+                return Err(StringLexError::Unterminated {
+                    file,
+                    line_number,
+                    column_number,
+                });
+            }
+        }
+    }
+    src.next();
+    Ok(Token::Str { value, here: begin })
+}
+
+/// Lexes what follows the backslash of an escape
+fn lex_escape(src: &mut source::Source) -> Option<char> {
+    let c = *src.peek()?;
+    src.next();
+    match c {
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        '\\' | '\'' | '"' => Some(c),
+        'x' => {
+            let mut value = 0;
+            for _ in 0..2 {
+                value = value * 16 + src.peek()?.to_digit(16)?;
+                src.next();
+            }
+            // Anything above is a byte rather than a character
+            (value <= 0x7F).then(|| char::from_u32(value).unwrap())
+        }
+        'u' => {
+            if src.peek() != Some(&'{') {
+                return None;
+            }
+            src.next();
+            let mut value = 0u32;
+            let mut digits = 0;
+            while let Some(digit) = src.peek().and_then(|c| c.to_digit(16)) {
+                value = value.checked_mul(16)? + digit;
+                digits += 1;
+                src.next();
+            }
+            if digits == 0 || digits > 6 || src.peek() != Some(&'}') {
+                return None;
+            }
+            src.next();
+            char::from_u32(value)
+        }
+        _ => None,
     }
 }
 
@@ -174,6 +570,12 @@ impl std::fmt::Display for LexerError {
             Self::Number(e) => {
                 writeln!(f, "{e}")
             }
+            Self::Char(e) => {
+                writeln!(f, "{e}")
+            }
+            Self::String(e) => {
+                writeln!(f, "{e}")
+            }
             Self::UnexpectedEOF {
                 line_number,
                 column_number,
@@ -199,6 +601,70 @@ impl std::fmt::Display for LexerError {
     }
 }
 
+impl std::fmt::Display for CharLexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unterminated {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Unterminated character literal",
+            ),
+            Self::Empty {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Empty character literal",
+            ),
+            Self::InvalidEscape {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Invalid escape in character literal",
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for StringLexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unterminated {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Unterminated string literal",
+            ),
+            Self::InvalidEscape {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Invalid escape in string literal",
+            ),
+        }
+    }
+}
+
 impl std::fmt::Display for NumberLexError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -212,6 +678,16 @@ impl std::fmt::Display for NumberLexError {
                 (*line_number, *column_number),
                 "Numbers MUST be separated from letters",
             ),
+            Self::TooLarge {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Number is too large for an integer",
+            ),
         }
     }
 }
@@ -225,13 +701,52 @@ impl std::fmt::Display for Token {
             Token::CloseParen { .. } => write!(f, ")"),
             Token::OpenCurly { .. } => write!(f, "{{"),
             Token::CloseCurly { .. } => write!(f, "}}"),
+            Token::OpenBracket { .. } => write!(f, "["),
+            Token::CloseBracket { .. } => write!(f, "]"),
             Token::Star { .. } => write!(f, "*"),
+            Token::Slash { .. } => write!(f, "/"),
             Token::Equal { .. } => write!(f, "="),
+            Token::FatArrow { .. } => write!(f, "=>"),
+            Token::Arrow { .. } => write!(f, "->"),
+            Token::Ampersand { .. } => write!(f, "&"),
+            Token::Pipe { .. } => write!(f, "|"),
+            Token::Question { .. } => write!(f, "?"),
+            Token::Less { .. } => write!(f, "<"),
+            Token::Greater { .. } => write!(f, ">"),
             Token::Number { value, .. } => write!(f, "{value}"),
+            Token::Float { value, .. } => write!(f, "{value:?}"),
+            Token::Char { value, .. } => write!(f, "{value:?}"),
+            Token::Str { value, .. } => write!(f, "{value:?}"),
             Token::Return { .. } => write!(f, "return"),
             Token::Var { .. } => write!(f, "var"),
+            Token::Let { .. } => write!(f, "let"),
+            Token::Mut { .. } => write!(f, "mut"),
+            Token::As { .. } => write!(f, "as"),
+            Token::Struct { .. } => write!(f, "struct"),
+            Token::Enum { .. } => write!(f, "enum"),
+            Token::Match { .. } => write!(f, "match"),
+            Token::Fn { .. } => write!(f, "fn"),
+            Token::Extern { .. } => write!(f, "extern"),
+            Token::Null { .. } => write!(f, "null"),
+            Token::Const { .. } => write!(f, "const"),
+            Token::Static { .. } => write!(f, "static"),
+            Token::For { .. } => write!(f, "for"),
+            Token::In { .. } => write!(f, "in"),
+            Token::Mod { .. } => write!(f, "mod"),
+            Token::Trait { .. } => write!(f, "trait"),
+            Token::Impl { .. } => write!(f, "impl"),
+            Token::Dyn { .. } => write!(f, "dyn"),
+            Token::Defer { .. } => write!(f, "defer"),
+            Token::Break { .. } => write!(f, "break"),
+            Token::Continue { .. } => write!(f, "continue"),
             Token::Ident { value, .. } => write!(f, "{value}"),
             Token::Semicolon { .. } => write!(f, ";"),
+            Token::Colon { .. } => write!(f, ":"),
+            Token::ColonColon { .. } => write!(f, "::"),
+            Token::Comma { .. } => write!(f, ","),
+            Token::Dot { .. } => write!(f, "."),
+            Token::DotDot { .. } => write!(f, ".."),
+            Token::DotDotEqual { .. } => write!(f, "..="),
         }
     }
 }
@@ -271,12 +786,195 @@ mod tests {
         );
     }
 
+    #[test]
+    fn too_large_numbers() {
+        let src = source::Source::new("x = 99999999999999999999;");
+        assert_eq!(
+            lex_file(src),
+            Err(LexerError::Number(NumberLexError::TooLarge {
+                file: None,
+                line_number: 1,
+                column_number: 5,
+            }))
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let src = source::Source::new("1.5 2e10 3.25e-2 4.");
+        assert_eq!(
+            lex_file(src),
+            Ok(vec![
+                Token::Float {
+                    value: 1.5,
+                    here: 0,
+                    len: 3
+                },
+                Token::Float {
+                    value: 2e10,
+                    here: 4,
+                    len: 4
+                },
+                Token::Float {
+                    value: 3.25e-2,
+                    here: 9,
+                    len: 7
+                },
+                Token::Number {
+                    value: 4,
+                    here: 17,
+                    len: 1
+                },
+                Token::Dot { here: 18 },
+            ])
+        );
+    }
+
     #[test]
     fn keywords() {
-        let src = source::Source::new("return var");
+        let src = source::Source::new("return var as break continue");
+        assert_eq!(
+            lex_file(src),
+            Ok(vec![
+                Token::Return { here: 0 },
+                Token::Var { here: 7 },
+                Token::As { here: 11 },
+                Token::Break { here: 14 },
+                Token::Continue { here: 20 }
+            ])
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let src = source::Source::new("0..n 1..=2");
+        assert_eq!(
+            lex_file(src),
+            Ok(vec![
+                Token::Number {
+                    value: 0,
+                    here: 0,
+                    len: 1
+                },
+                Token::DotDot { here: 1 },
+                Token::Ident {
+                    value: String::from("n"),
+                    here: 3
+                },
+                Token::Number {
+                    value: 1,
+                    here: 5,
+                    len: 1
+                },
+                Token::DotDotEqual { here: 6 },
+                Token::Number {
+                    value: 2,
+                    here: 9,
+                    len: 1
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn tuple_fields() {
+        let src = source::Source::new("t.0.1");
         assert_eq!(
             lex_file(src),
-            Ok(vec![Token::Return { here: 0 }, Token::Var { here: 7 }])
+            Ok(vec![
+                Token::Ident {
+                    value: String::from("t"),
+                    here: 0
+                },
+                Token::Dot { here: 1 },
+                Token::Number {
+                    value: 0,
+                    here: 2,
+                    len: 1
+                },
+                Token::Dot { here: 3 },
+                Token::Number {
+                    value: 1,
+                    here: 4,
+                    len: 1
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn chars() {
+        let src = source::Source::new(r"'a' '\n' '\x41' '\u{1F600}' '\''");
+        assert_eq!(
+            lex_file(src),
+            Ok(vec![
+                Token::Char {
+                    value: 'a',
+                    here: 0
+                },
+                Token::Char {
+                    value: '\n',
+                    here: 4
+                },
+                Token::Char {
+                    value: 'A',
+                    here: 9
+                },
+                Token::Char {
+                    value: '😀',
+                    here: 16
+                },
+                Token::Char {
+                    value: '\'',
+                    here: 28
+                },
+            ])
+        );
+        assert_eq!(
+            lex_file(source::Source::new(r"'\x80'")),
+            Err(LexerError::Char(CharLexError::InvalidEscape {
+                file: None,
+                line_number: 1,
+                column_number: 2,
+            }))
+        );
+        assert_eq!(
+            lex_file(source::Source::new("'ab'")),
+            Err(LexerError::Char(CharLexError::Unterminated {
+                file: None,
+                line_number: 1,
+                column_number: 1,
+            }))
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let src = source::Source::new(r#""hi\n" "" "\"é""#);
+        assert_eq!(
+            lex_file(src),
+            Ok(vec![
+                Token::Str {
+                    value: String::from("hi\n"),
+                    here: 0
+                },
+                Token::Str {
+                    value: String::new(),
+                    here: 7
+                },
+                Token::Str {
+                    value: String::from("\"é"),
+                    here: 10
+                },
+            ])
+        );
+        assert_eq!(
+            lex_file(source::Source::new("\"abc")),
+            Err(LexerError::String(StringLexError::Unterminated {
+                file: None,
+                line_number: 1,
+                column_number: 1,
+            }))
         );
     }
 }
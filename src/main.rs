@@ -1,8 +1,17 @@
 mod ast;
 mod backend;
+mod consteval;
 mod error;
+mod escape;
+mod fold;
+mod infer;
 mod lexer;
+mod modules;
+mod monomorph;
+mod runtime;
 mod source;
+mod traits;
+mod typeck;
 
 use clap::Parser;
 use thiserror::Error;
@@ -11,10 +20,30 @@ use thiserror::Error;
 pub enum CompilerError {
     IO(#[from] std::io::Error),
     Lexer(#[from] lexer::LexerError),
-    Ast(#[from] ast::ASTError),
+    /// Boxed like `Type`, it holds two tokens
+    Ast(Box<ast::SyntaxError>),
+    Module(#[from] modules::ModuleError),
+    Trait(#[from] traits::TraitError),
+    Infer(#[from] infer::InferError),
+    Mono(#[from] monomorph::MonoError),
+    /// Boxed, it's the largest of the errors and every `Result` here carries it
+    Type(Box<typeck::TypeError>),
+    Const(#[from] consteval::ConstError),
     Backend(#[from] backend::BackendError),
 }
 
+impl From<ast::SyntaxError> for CompilerError {
+    fn from(error: ast::SyntaxError) -> Self {
+        Self::Ast(Box::new(error))
+    }
+}
+
+impl From<typeck::TypeError> for CompilerError {
+    fn from(error: typeck::TypeError) -> Self {
+        Self::Type(Box::new(error))
+    }
+}
+
 impl std::fmt::Display for CompilerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -26,6 +55,30 @@ impl std::fmt::Display for CompilerError {
                 writeln!(f, "Ast parsing failed")?;
                 writeln!(f, "{e}")
             }
+            Self::Module(e) => {
+                writeln!(f, "Module loading failed")?;
+                writeln!(f, "{e}")
+            }
+            Self::Trait(e) => {
+                writeln!(f, "Checking traits failed")?;
+                writeln!(f, "{e}")
+            }
+            Self::Infer(e) => {
+                writeln!(f, "Type inference failed")?;
+                writeln!(f, "{e}")
+            }
+            Self::Mono(e) => {
+                writeln!(f, "Instantiating generics failed")?;
+                writeln!(f, "{e}")
+            }
+            Self::Type(e) => {
+                writeln!(f, "Type checking failed")?;
+                writeln!(f, "{e}")
+            }
+            Self::Const(e) => {
+                writeln!(f, "Constant evaluation failed")?;
+                writeln!(f, "{e}")
+            }
             Self::Backend(e) => {
                 writeln!(f, "Codegen failure")?;
                 writeln!(f, "{e}")
@@ -41,19 +94,33 @@ impl std::fmt::Display for CompilerError {
 fn real_main() -> Result<(), CompilerError> {
     let conf = Config::parse();
     let src = source::Source::from_file(&conf.file_name)?;
-    println!("Lexing...");
-    let pre_lex = std::time::Instant::now();
-    let tokens = lexer::lex_file(src)?;
-    println!("Lexing took: {:.2?}", pre_lex.elapsed());
+    println!("Loading modules...");
+    let pre_load = std::time::Instant::now();
+    let (src, mut program) = modules::load(src)?;
+    println!("Loading modules took: {:.2?}", pre_load.elapsed());
 
-    println!("Parsing AST...");
-    let pre_parse = std::time::Instant::now();
-    let one_expr = ast::parse(&tokens)?;
-    println!("AST parsing took: {:.2?}", pre_parse.elapsed());
+    println!("Type checking...");
+    let pre_check = std::time::Instant::now();
+    traits::check(&program, &src)?;
+    let (instantiations, receivers) = infer::infer(&mut program, &src)?;
+    let (program, instances) = monomorph::monomorphize(program, &instantiations, &receivers, &src)?;
+    typeck::check(&program, &src, &instances)?;
+    let options = backend::Options {
+        opt_level: conf.opt_level,
+        overflow_checks: conf.overflow_checks.unwrap_or(conf.opt_level == 0),
+        backtrace: conf.backtrace,
+    };
+    let (program, warnings) = fold::fold_program(program, &src, options.overflow_checks);
+    for warning in &warnings {
+        println!("Warning");
+        println!("{warning}");
+    }
+    let globals = consteval::evaluate(&program, &src)?;
+    println!("Type checking took: {:.2?}", pre_check.elapsed());
 
     println!("Generating and compiling code...");
     let pre_comp = std::time::Instant::now();
-    backend::compile(&conf.output, &one_expr)?;
+    backend::compile(&conf.output, &program, &globals, &src, &options)?;
     println!("Compilation took: {:.2?}", pre_comp.elapsed());
     println!("Executable compiled. Available at: ./{}", conf.output);
 
@@ -73,4 +140,13 @@ struct Config {
     /// Executable output name
     #[arg(short, default_value_t = String::from("main"))]
     output: String,
+    /// Optimization level, 0 to 3
+    #[arg(short = 'O', default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+    opt_level: u8,
+    /// Panic on integer overflow instead of wrapping, defaults to on at -O0
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    overflow_checks: Option<bool>,
+    /// Print a stack backtrace when the program panics, needs libunwind
+    #[arg(long)]
+    backtrace: bool,
 }
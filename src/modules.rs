@@ -0,0 +1,1083 @@
+use crate::{CompilerError, ast, error, lexer, source};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ModuleError {
+    NotFound {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        path: String,
+        reason: String,
+    },
+    Cycle {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    Redeclared {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+}
+
+/// `Option` and `Result`, each loaded along with the program when some file names it and the
+/// main file doesn't declare its own. `Result` comes first since it names `Option`
+const PRELUDE: &[(&str, &str)] = &[
+    ("Result", include_str!("prelude/result.jsl")),
+    ("Option", include_str!("prelude/option.jsl")),
+];
+
+/// The standard library by module path. A program only gets the modules it names
+const STD: &[(&str, &str)] = &[
+    ("std", include_str!("std.jsl")),
+    ("std::io", include_str!("std/io.jsl")),
+];
+
+/// A loaded file before its names are resolved
+struct Module {
+    /// `a::b::` for the module declared as `mod b;` inside `a`, empty for the main file
+    prefix: String,
+    program: Vec<ast::Statement>,
+    /// Names of the structs, enums, functions and globals the file defines
+    items: HashSet<String>,
+    children: HashMap<String, Module>,
+}
+
+struct Loader {
+    src: source::SourceMap,
+    /// Files being loaded, a module can't end up including itself
+    stack: Vec<std::path::PathBuf>,
+    /// Full paths of every module, `a` and `a::b`
+    modules: HashSet<String>,
+    /// Full names of every function
+    functions: HashSet<String>,
+    /// Modules of the standard library some file names
+    std: HashSet<String>,
+    /// Items of the prelude some file names
+    prelude: HashSet<String>,
+}
+
+impl Loader {
+    fn load_file(
+        &mut self,
+        src: source::Source,
+        prefix: String,
+        is_main: bool,
+    ) -> Result<Module, CompilerError> {
+        let id = self.src.add(src);
+        let tokens = lexer::lex_file(self.src.get(id).clone())?;
+        self.std.extend(std_paths(&tokens));
+        self.prelude.extend(tokens.iter().filter_map(|t| match t {
+            lexer::Token::Ident { value, .. } if PRELUDE.iter().any(|(name, _)| name == value) => {
+                Some(value.to_string())
+            }
+            _ => None,
+        }));
+        let program = if is_main {
+            ast::parse(&tokens)
+        } else {
+            ast::parse_module(&tokens)
+        }
+        .map_err(|e| e.locate(&self.src))?;
+
+        let mut items = HashSet::new();
+        let mut children = HashMap::new();
+        for st in &program {
+            match st {
+                ast::Statement::Function { name, .. } => {
+                    self.functions.insert(format!("{prefix}{name}"));
+                    items.insert(name.to_string());
+                }
+                ast::Statement::Struct { name, .. }
+                | ast::Statement::Enum { name, .. }
+                | ast::Statement::Trait { name, .. }
+                | ast::Statement::Const { name, .. }
+                | ast::Statement::Static { name, .. } => {
+                    items.insert(name.to_string());
+                }
+                ast::Statement::Mod { name, here } => {
+                    if children.contains_key(name) {
+                        let (file, line_number, column_number) = self.src.locate(*here);
+                        return Err(ModuleError::Redeclared {
+                            file,
+                            line_number,
+                            column_number,
+                            name: name.to_string(),
+                        })?;
+                    }
+                    let child = self.load_mod(id, name, *here, &prefix)?;
+                    children.insert(name.to_string(), child);
+                }
+                _ => {}
+            }
+        }
+        // Methods are functions named after the type, which may come after the impl
+        for st in &program {
+            let ast::Statement::Impl { ty, methods, .. } = st else {
+                continue;
+            };
+            for method in methods {
+                if let ast::Statement::Function { name, .. } = method
+                    && let Some(path) = ty.method_path(name)
+                {
+                    let first = path.split("::").next().unwrap();
+                    if items.contains(first) || children.contains_key(first) {
+                        self.functions.insert(format!("{prefix}{path}"));
+                    } else {
+                        self.functions.insert(path);
+                    }
+                }
+            }
+        }
+        Ok(Module {
+            prefix,
+            program,
+            items,
+            children,
+        })
+    }
+
+    /// Loads the bundled module `path`, along with the bundled modules under it that some
+    /// file names
+    fn load_std(&mut self, path: &str) -> Result<Module, CompilerError> {
+        let (_, text) = STD.iter().find(|(p, _)| *p == path).unwrap();
+        self.modules.insert(path.to_string());
+        let src = source::Source::bundled(&format!("<{path}>"), text);
+        let mut module = self.load_file(src, format!("{path}::"), false)?;
+        // Loading a module can name the ones after it
+        for (child, _) in STD {
+            if let Some(name) = child.strip_prefix(module.prefix.as_str())
+                && !name.contains("::")
+                && self.std.contains(*child)
+            {
+                let loaded = self.load_std(child)?;
+                module.children.insert(name.to_string(), loaded);
+                module.program.push(ast::Statement::Mod {
+                    name: name.to_string(),
+                    here: 0,
+                });
+            }
+        }
+        Ok(module)
+    }
+
+    /// Loads `mod name;` from `name.jsl` next to the file declaring it
+    fn load_mod(
+        &mut self,
+        parent: usize,
+        name: &str,
+        here: usize,
+        prefix: &str,
+    ) -> Result<Module, CompilerError> {
+        let (file, line_number, column_number) = self.src.locate(here);
+        let dir = std::path::Path::new(self.src.get(parent).path().unwrap_or("."))
+            .parent()
+            .unwrap_or(std::path::Path::new(""));
+        let path = dir
+            .join(format!("{name}.jsl"))
+            .to_string_lossy()
+            .to_string();
+        let not_found = |e: std::io::Error| ModuleError::NotFound {
+            file: file.clone(),
+            line_number,
+            column_number,
+            path: path.clone(),
+            reason: e.to_string(),
+        };
+
+        let canonical = std::fs::canonicalize(&path).map_err(not_found)?;
+        if self.stack.contains(&canonical) {
+            return Err(ModuleError::Cycle {
+                file,
+                line_number,
+                column_number,
+                name: name.to_string(),
+            })?;
+        }
+        let src = source::Source::from_file(path.clone()).map_err(not_found)?;
+
+        let full = format!("{prefix}{name}");
+        self.modules.insert(full.clone());
+        self.stack.push(canonical);
+        let module = self.load_file(src, format!("{full}::"), false)?;
+        self.stack.pop();
+        Ok(module)
+    }
+}
+
+/// The modules `tokens` name under `std`, `std::io::File` names `std` and `std::io`
+fn std_paths(tokens: &[lexer::Token]) -> Vec<String> {
+    let mut paths = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if !matches!(token, lexer::Token::Ident { value, .. } if value == "std") {
+            continue;
+        }
+        let mut path = String::from("std");
+        let mut rest = &tokens[i + 1..];
+        while let [
+            lexer::Token::ColonColon { .. },
+            lexer::Token::Ident { value, .. },
+            tail @ ..,
+        ] = rest
+        {
+            paths.push(path.clone());
+            path = format!("{path}::{value}");
+            rest = tail;
+        }
+    }
+    paths
+}
+
+/// Rewrites the names used in one file to the names of the items they refer to
+struct Resolver<'a> {
+    module: &'a Module,
+    modules: &'a HashSet<String>,
+    functions: &'a HashSet<String>,
+    /// Variables in scope, they shadow the file's globals
+    locals: HashSet<String>,
+}
+
+impl Resolver<'_> {
+    /// `a::b` names an item of this file or of one of its modules when `a` does
+    fn path(&self, name: &str) -> String {
+        let first = name.split("::").next().unwrap();
+        if self.module.items.contains(first) || self.module.children.contains_key(first) {
+            format!("{}{name}", self.module.prefix)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn ty(&self, ty: ast::Type) -> ast::Type {
+        match ty {
+            ast::Type::Named(name) => ast::Type::Named(self.path(&name)),
+            ast::Type::Generic(name, args) => ast::Type::Generic(
+                self.path(&name),
+                args.into_iter().map(|ty| self.ty(ty)).collect(),
+            ),
+            ast::Type::Pointer(inner) => ast::Type::Pointer(Box::new(self.ty(*inner))),
+            ast::Type::Array(elem, len) => ast::Type::Array(Box::new(self.ty(*elem)), len),
+            ast::Type::Slice(elem) => ast::Type::Slice(Box::new(self.ty(*elem))),
+            ast::Type::Tuple(elems) => {
+                ast::Type::Tuple(elems.into_iter().map(|ty| self.ty(ty)).collect())
+            }
+            ast::Type::Function(params, ret) => ast::Type::Function(
+                params.into_iter().map(|ty| self.ty(ty)).collect(),
+                Box::new(self.ty(*ret)),
+            ),
+            ty => ty,
+        }
+    }
+
+    fn generics(&self, generics: Vec<ast::Generic>) -> Vec<ast::Generic> {
+        generics
+            .into_iter()
+            .map(|g| ast::Generic {
+                bounds: g.bounds.iter().map(|b| self.path(b)).collect(),
+                ..g
+            })
+            .collect()
+    }
+
+    fn params(&self, params: Vec<ast::Param>) -> Vec<ast::Param> {
+        params
+            .into_iter()
+            .map(|p| ast::Param {
+                ty: self.ty(p.ty),
+                ..p
+            })
+            .collect()
+    }
+
+    fn statement(&mut self, st: ast::Statement) -> ast::Statement {
+        let prefix = self.module.prefix.clone();
+        match st {
+            ast::Statement::Return(value) => ast::Statement::Return(self.expression(value)),
+            ast::Statement::DefineVar {
+                name,
+                mutable,
+                ty,
+                value,
+                here,
+            } => {
+                let value = self.expression(value);
+                self.locals.insert(name.to_string());
+                ast::Statement::DefineVar {
+                    name,
+                    mutable,
+                    ty: ty.map(|ty| self.ty(ty)),
+                    value,
+                    here,
+                }
+            }
+            ast::Statement::DefineTuple { names, ty, value } => {
+                let value = self.expression(value);
+                self.locals.extend(names.iter().map(|b| b.name.to_string()));
+                ast::Statement::DefineTuple {
+                    names,
+                    ty: ty.map(|ty| self.ty(ty)),
+                    value,
+                }
+            }
+            ast::Statement::Assign { target, value } => ast::Statement::Assign {
+                target: self.expression(target),
+                value: self.expression(value),
+            },
+            ast::Statement::Struct {
+                name,
+                generics,
+                fields,
+                here,
+            } => ast::Statement::Struct {
+                name: format!("{prefix}{name}"),
+                generics: self.generics(generics),
+                fields: fields
+                    .into_iter()
+                    .map(|f| ast::FieldDecl {
+                        ty: self.ty(f.ty),
+                        ..f
+                    })
+                    .collect(),
+                here,
+            },
+            ast::Statement::Enum {
+                name,
+                generics,
+                variants,
+                here,
+            } => ast::Statement::Enum {
+                name: format!("{prefix}{name}"),
+                generics: self.generics(generics),
+                variants: variants
+                    .into_iter()
+                    .map(|v| ast::VariantDecl {
+                        payload: v.payload.into_iter().map(|ty| self.ty(ty)).collect(),
+                        ..v
+                    })
+                    .collect(),
+                here,
+            },
+            ast::Statement::Function {
+                name,
+                generics,
+                params,
+                ret,
+                body,
+                here,
+            } => {
+                let locals = std::mem::replace(
+                    &mut self.locals,
+                    params.iter().map(|p| p.name.to_string()).collect(),
+                );
+                let body = body.into_iter().map(|st| self.statement(st)).collect();
+                self.locals = locals;
+                ast::Statement::Function {
+                    name: format!("{prefix}{name}"),
+                    generics: self.generics(generics),
+                    params: self.params(params),
+                    ret: self.ty(ret),
+                    body,
+                    here,
+                }
+            }
+            ast::Statement::Trait {
+                name,
+                methods,
+                here,
+            } => ast::Statement::Trait {
+                name: format!("{prefix}{name}"),
+                methods: methods
+                    .into_iter()
+                    .map(|m| ast::TraitMethod {
+                        params: self.params(m.params),
+                        ret: self.ty(m.ret),
+                        ..m
+                    })
+                    .collect(),
+                here,
+            },
+            // Methods keep their own names, they're only ever called through the type
+            ast::Statement::Impl {
+                generics,
+                trait_name,
+                ty,
+                methods,
+                here,
+            } => ast::Statement::Impl {
+                generics: self.generics(generics),
+                trait_name: trait_name.map(|t| self.path(&t)),
+                ty: self.ty(ty),
+                methods: methods
+                    .into_iter()
+                    .map(|method| match self.statement(method) {
+                        ast::Statement::Function {
+                            name,
+                            generics,
+                            params,
+                            ret,
+                            body,
+                            here,
+                        } => ast::Statement::Function {
+                            name: name[prefix.len()..].to_string(),
+                            generics,
+                            params,
+                            ret,
+                            body,
+                            here,
+                        },
+                        st => st,
+                    })
+                    .collect(),
+                here,
+            },
+            // Externs keep the name they are linked by
+            ast::Statement::Extern {
+                name,
+                params,
+                ret,
+                here,
+            } => ast::Statement::Extern {
+                name,
+                params: self.params(params),
+                ret: self.ty(ret),
+                here,
+            },
+            ast::Statement::Const {
+                name,
+                ty,
+                value,
+                here,
+            } => ast::Statement::Const {
+                name: format!("{prefix}{name}"),
+                ty: self.ty(ty),
+                value: self.expression(value),
+                here,
+            },
+            ast::Statement::Static {
+                name,
+                ty,
+                value,
+                here,
+            } => ast::Statement::Static {
+                name: format!("{prefix}{name}"),
+                ty: self.ty(ty),
+                value: self.expression(value),
+                here,
+            },
+            ast::Statement::Expression(value) => ast::Statement::Expression(self.expression(value)),
+            ast::Statement::For {
+                var,
+                start,
+                end,
+                inclusive,
+                body,
+                here,
+            } => {
+                let start = self.expression(start);
+                let end = self.expression(end);
+                let locals = self.locals.clone();
+                self.locals.insert(var.to_string());
+                let body = body.into_iter().map(|st| self.statement(st)).collect();
+                self.locals = locals;
+                ast::Statement::For {
+                    var,
+                    start,
+                    end,
+                    inclusive,
+                    body,
+                    here,
+                }
+            }
+            st @ (ast::Statement::Mod { .. }
+            | ast::Statement::Break { .. }
+            | ast::Statement::Continue { .. }) => st,
+            ast::Statement::Defer { stmt, here } => {
+                // Whatever the deferred statement defines is gone by the time it runs
+                let locals = self.locals.clone();
+                let stmt = Box::new(self.statement(*stmt));
+                self.locals = locals;
+                ast::Statement::Defer { stmt, here }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: ast::Expression) -> ast::Expression {
+        match expr {
+            ast::Expression::Variable { name, here } => {
+                if self.locals.contains(&name) {
+                    ast::Expression::Variable { name, here }
+                } else {
+                    ast::Expression::Variable {
+                        name: self.path(&name),
+                        here,
+                    }
+                }
+            }
+            ast::Expression::Binary { left, op, right } => ast::Expression::Binary {
+                left: Box::new(self.expression(*left)),
+                op,
+                right: Box::new(self.expression(*right)),
+            },
+            ast::Expression::Cast { value, to, here } => ast::Expression::Cast {
+                value: Box::new(self.expression(*value)),
+                to: self.ty(to),
+                here,
+            },
+            ast::Expression::Array { elements, here } => ast::Expression::Array {
+                elements: elements.into_iter().map(|e| self.expression(e)).collect(),
+                here,
+            },
+            ast::Expression::Tuple { elements, here } => ast::Expression::Tuple {
+                elements: elements.into_iter().map(|e| self.expression(e)).collect(),
+                here,
+            },
+            ast::Expression::Index { array, index, here } => ast::Expression::Index {
+                array: Box::new(self.expression(*array)),
+                index: Box::new(self.expression(*index)),
+                here,
+            },
+            ast::Expression::Slice {
+                value,
+                start,
+                end,
+                inclusive,
+                here,
+            } => ast::Expression::Slice {
+                value: Box::new(self.expression(*value)),
+                start: start.map(|e| Box::new(self.expression(*e))),
+                end: end.map(|e| Box::new(self.expression(*e))),
+                inclusive,
+                here,
+            },
+            ast::Expression::StructLiteral { name, fields, here } => {
+                ast::Expression::StructLiteral {
+                    name: self.path(&name),
+                    fields: fields
+                        .into_iter()
+                        .map(|f| ast::FieldInit {
+                            value: self.expression(f.value),
+                            ..f
+                        })
+                        .collect(),
+                    here,
+                }
+            }
+            ast::Expression::Field { value, field, here } => ast::Expression::Field {
+                value: Box::new(self.expression(*value)),
+                field,
+                here,
+            },
+            ast::Expression::Variant {
+                ty,
+                variant,
+                args,
+                here,
+            } => {
+                let ty = self.path(&ty);
+                let args = args.into_iter().map(|e| self.expression(e)).collect();
+                // `module::item` and `Type::method` parse like variants, `module::f()` and
+                // `Type::method()` are calls
+                let name = format!("{ty}::{variant}");
+                if self.functions.contains(&name) {
+                    return ast::Expression::Call { name, args, here };
+                }
+                if !self.modules.contains(&ty) {
+                    return ast::Expression::Variant {
+                        ty,
+                        variant,
+                        args,
+                        here,
+                    };
+                }
+                if !args.is_empty() {
+                    ast::Expression::Call { name, args, here }
+                } else {
+                    ast::Expression::Variable { name, here }
+                }
+            }
+            ast::Expression::Match { value, arms, here } => ast::Expression::Match {
+                value: Box::new(self.expression(*value)),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| {
+                        let pattern = match arm.pattern {
+                            ast::Pattern::Variant {
+                                ty,
+                                variant,
+                                bindings,
+                                here,
+                            } => ast::Pattern::Variant {
+                                ty: self.path(&ty),
+                                variant,
+                                bindings,
+                                here,
+                            },
+                            pattern => pattern,
+                        };
+                        let locals = self.locals.clone();
+                        if let ast::Pattern::Variant { bindings, .. } = &pattern {
+                            self.locals
+                                .extend(bindings.iter().map(|(name, _)| name.to_string()));
+                        }
+                        let value = self.expression(arm.value);
+                        self.locals = locals;
+                        ast::MatchArm { pattern, value }
+                    })
+                    .collect(),
+                here,
+            },
+            ast::Expression::AddressOf { value, here } => ast::Expression::AddressOf {
+                value: Box::new(self.expression(*value)),
+                here,
+            },
+            ast::Expression::Deref { value, here } => ast::Expression::Deref {
+                value: Box::new(self.expression(*value)),
+                here,
+            },
+            ast::Expression::Call { name, args, here } => ast::Expression::Call {
+                name: if !self.locals.contains(&name) && self.module.items.contains(&name) {
+                    format!("{}{name}", self.module.prefix)
+                } else {
+                    name
+                },
+                args: args.into_iter().map(|e| self.expression(e)).collect(),
+                here,
+            },
+            ast::Expression::Closure {
+                params,
+                ret,
+                body,
+                here,
+            } => {
+                // Closures see the variables around them
+                let locals = self.locals.clone();
+                self.locals
+                    .extend(params.iter().map(|p| p.name.to_string()));
+                let body = body.into_iter().map(|st| self.statement(st)).collect();
+                self.locals = locals;
+                ast::Expression::Closure {
+                    params: params
+                        .into_iter()
+                        .map(|p| ast::ClosureParam {
+                            ty: p.ty.map(|ty| self.ty(ty)),
+                            ..p
+                        })
+                        .collect(),
+                    ret: ret.map(|ty| self.ty(ty)),
+                    body,
+                    here,
+                }
+            }
+            ast::Expression::MethodCall {
+                value,
+                method,
+                args,
+                here,
+            } => ast::Expression::MethodCall {
+                value: Box::new(self.expression(*value)),
+                method,
+                args: args.into_iter().map(|e| self.expression(e)).collect(),
+                here,
+            },
+            ast::Expression::Try { value, here } => ast::Expression::Try {
+                value: Box::new(self.expression(*value)),
+                here,
+            },
+            e @ (ast::Expression::Number { .. }
+            | ast::Expression::Float { .. }
+            | ast::Expression::Char { .. }
+            | ast::Expression::Str { .. }
+            | ast::Expression::Null { .. }) => e,
+        }
+    }
+}
+
+/// Resolves the names of `module` and puts the items of every `mod` where it is declared
+fn flatten(
+    module: Module,
+    modules: &HashSet<String>,
+    functions: &HashSet<String>,
+    externs: &mut Vec<ast::Statement>,
+    program: &mut Vec<ast::Statement>,
+) {
+    let mut module = module;
+    let statements = std::mem::take(&mut module.program);
+    let mut resolver = Resolver {
+        module: &module,
+        modules,
+        functions,
+        locals: HashSet::new(),
+    };
+    let statements: Vec<_> = statements
+        .into_iter()
+        .map(|st| resolver.statement(st))
+        .collect();
+    for st in statements {
+        match st {
+            ast::Statement::Mod { name, .. } => {
+                let child = module.children.remove(&name).unwrap();
+                flatten(child, modules, functions, externs, program);
+            }
+            // Every file declares the externs it uses, the same declaration is only kept once
+            st @ ast::Statement::Extern { .. } if externs.iter().any(|e| same_extern(e, &st)) => {}
+            st @ ast::Statement::Extern { .. } => {
+                externs.push(st.clone());
+                program.push(st);
+            }
+            st => program.push(st),
+        }
+    }
+}
+
+fn same_extern(a: &ast::Statement, b: &ast::Statement) -> bool {
+    match (a, b) {
+        (
+            ast::Statement::Extern {
+                name: a_name,
+                params: a_params,
+                ret: a_ret,
+                ..
+            },
+            ast::Statement::Extern {
+                name: b_name,
+                params: b_params,
+                ret: b_ret,
+                ..
+            },
+        ) => {
+            a_name == b_name
+                && a_ret == b_ret
+                && a_params.len() == b_params.len()
+                && a_params.iter().zip(b_params).all(|(a, b)| a.ty == b.ty)
+        }
+        _ => false,
+    }
+}
+
+/// Loads `src` and every module it declares, returning the files and a single program
+/// with every item named by its full path
+pub fn load(
+    src: source::Source,
+) -> Result<(source::SourceMap, Vec<ast::Statement>), CompilerError> {
+    let mut loader = Loader {
+        src: source::SourceMap::default(),
+        stack: src
+            .path()
+            .and_then(|p| std::fs::canonicalize(p).ok())
+            .into_iter()
+            .collect(),
+        modules: HashSet::new(),
+        functions: HashSet::new(),
+        std: HashSet::new(),
+        prelude: HashSet::new(),
+    };
+    let mut main = loader.load_file(src, String::new(), true)?;
+    // A program declaring its own `std` keeps it
+    if loader.std.contains("std")
+        && !main.items.contains("std")
+        && !main.children.contains_key("std")
+    {
+        let std = loader.load_std("std")?;
+        main.children.insert(String::from("std"), std);
+        main.program.push(ast::Statement::Mod {
+            name: String::from("std"),
+            here: 0,
+        });
+    }
+    // A program declaring its own `Option` or `Result` keeps it
+    let mut prelude = vec![];
+    for (name, text) in PRELUDE {
+        if loader.prelude.contains(*name) && !main.items.contains(*name) {
+            let src = source::Source::bundled(&format!("<prelude::{name}>"), text);
+            prelude.push(loader.load_file(src, String::new(), false)?);
+        }
+    }
+    let mut program = vec![];
+    let mut externs = vec![];
+    for module in std::iter::once(main).chain(prelude) {
+        flatten(
+            module,
+            &loader.modules,
+            &loader.functions,
+            &mut externs,
+            &mut program,
+        );
+    }
+    Ok((loader.src, program))
+}
+
+impl std::fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound {
+                file,
+                line_number,
+                column_number,
+                path,
+                reason,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot read module file {path}: {reason}").as_str(),
+            ),
+            Self::Cycle {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Module {name} includes itself").as_str(),
+            ),
+            Self::Redeclared {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Module {name} is declared more than once").as_str(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{infer, monomorph, traits, typeck};
+
+    /// Writes `files` to a fresh directory and loads the first of them
+    fn load_files(
+        test: &str,
+        files: &[(&str, &str)],
+    ) -> Result<(source::SourceMap, Vec<ast::Statement>), CompilerError> {
+        let dir = std::env::temp_dir().join(format!("jizzle-modules-{test}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        let main = dir.join(files[0].0).to_string_lossy().to_string();
+        load(source::Source::from_file(main).unwrap())
+    }
+
+    #[test]
+    fn items() {
+        let (src, program) = load_files(
+            "items",
+            &[
+                (
+                    "main.jsl",
+                    "mod geo;\nvar p = geo::Point { x: 1, y: 2 };\nreturn geo::sum(p) + geo::ORIGIN;",
+                ),
+                (
+                    "geo.jsl",
+                    "struct Point { x: i64, y: i64 }\nconst ORIGIN: i64 = 0;\nfn sum(p: Point) -> i64 { return p.x + p.y + ORIGIN; }",
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(typeck::check(&program, &src, &Default::default()), Ok(()));
+        assert!(program.iter().any(|st| matches!(
+            st,
+            ast::Statement::Function { name, params, .. }
+                if name == "geo::sum" && params[0].ty == ast::Type::Named(String::from("geo::Point"))
+        )));
+    }
+
+    #[test]
+    fn errors_name_their_file() {
+        let (src, program) = load_files(
+            "errors",
+            &[
+                ("main.jsl", "mod util;\nreturn util::f();"),
+                ("util.jsl", "fn f() -> i64 {\n    return x;\n}"),
+            ],
+        )
+        .unwrap();
+        match typeck::check(&program, &src, &Default::default()) {
+            Err(typeck::TypeError::UndefinedVariable {
+                file: Some(file),
+                line_number: 2,
+                column_number: 12,
+                ..
+            }) => assert!(file.ends_with("util.jsl")),
+            e => panic!("unexpected result {e:?}"),
+        }
+    }
+
+    #[test]
+    fn syntax_errors_name_their_file() {
+        let result = load_files(
+            "syntax",
+            &[
+                ("main.jsl", "mod util;\nreturn util::f();"),
+                ("util.jsl", "fn f() -> i64 {\n    return 1\n}"),
+            ],
+        );
+        match result.map(|_| ()) {
+            Err(CompilerError::Ast(e)) => match *e {
+                ast::SyntaxError {
+                    file: Some(file),
+                    line_number: 3,
+                    column_number: 1,
+                    error: ast::ASTError::UnexpectedToken { .. },
+                } => assert!(file.ends_with("util.jsl")),
+                e => panic!("unexpected error {e:?}"),
+            },
+            e => panic!("unexpected result {e:?}"),
+        }
+
+        let result = load_files("eof", &[("main.jsl", "return 1")]);
+        match result.map(|_| ()) {
+            Err(CompilerError::Ast(e)) => assert_eq!(
+                (e.line_number, e.column_number, e.error),
+                (1, 8, ast::ASTError::UnexpectedEOF { here: 7 })
+            ),
+            e => panic!("unexpected result {e:?}"),
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let result = load_files(
+            "cycle",
+            &[
+                ("main.jsl", "mod a;\nreturn 0;"),
+                ("a.jsl", "mod b;"),
+                ("b.jsl", "mod a;"),
+            ],
+        );
+        match result {
+            Err(CompilerError::Module(ModuleError::Cycle {
+                file: Some(file),
+                name,
+                ..
+            })) => {
+                assert!(file.ends_with("b.jsl"));
+                assert_eq!(name, "a");
+            }
+            e => panic!("unexpected result {:?}", e.map(|_| ())),
+        }
+    }
+
+    /// Runs every check up to and including `typeck` on a loaded program
+    fn check_program(
+        src: &source::SourceMap,
+        mut program: Vec<ast::Statement>,
+    ) -> Vec<ast::Statement> {
+        traits::check(&program, src).unwrap();
+        let (instantiations, receivers) = infer::infer(&mut program, src).unwrap();
+        let (program, instances) =
+            monomorph::monomorphize(program, &instantiations, &receivers, src).unwrap();
+        assert_eq!(typeck::check(&program, src, &instances), Ok(()));
+        program
+    }
+
+    fn defines(program: &[ast::Statement], prefix: &str) -> bool {
+        program.iter().any(
+            |st| matches!(st, ast::Statement::Function { name, .. } if name.starts_with(prefix)),
+        )
+    }
+
+    #[test]
+    fn std() {
+        let (src, program) = load_files(
+            "std",
+            &[(
+                "main.jsl",
+                "var v: std::Vec<i64> = std::Vec::new();\nv.push(2);\nvar m: std::HashMap<str, i64> = std::HashMap::new();\nm.insert(\"a\", v.get(0));\nvar s = std::String::from(\"b\");\ns.push('c');\nreturn *m.get(\"a\") + s.len();",
+            )],
+        )
+        .unwrap();
+        let program = check_program(&src, program);
+        // Only the modules a program names are loaded
+        assert!(defines(&program, "std::String::push"));
+        assert!(!defines(&program, "std::io::"));
+
+        let (_, program) = load_files("no_std", &[("main.jsl", "return 0;")]).unwrap();
+        assert_eq!(program.len(), 1);
+    }
+
+    #[test]
+    fn prelude() {
+        let (src, program) = load_files(
+            "prelude",
+            &[(
+                "main.jsl",
+                "fn half(x: i64) -> Option<i64> {\n    return match x - x / 2 * 2 { 0 => Option::Some(x / 2), _ => Option::None };\n}\nfn quarter(x: i64) -> Option<i64> {\n    return half(half(x)?);\n}\nreturn quarter(8).unwrap_or(0);",
+            )],
+        )
+        .unwrap();
+        let program = check_program(&src, program);
+        assert!(defines(&program, "Option<i64>::unwrap_or"));
+
+        // Declaring one of them keeps the other
+        let (src, program) = load_files(
+            "prelude_shadowed",
+            &[(
+                "main.jsl",
+                "enum Result { Good, Bad }\nfn f(r: Result) -> Option<i64> {\n    return match r { Result::Good => Option::Some(1), Result::Bad => Option::None };\n}\nreturn f(Result::Good).unwrap_or(0);",
+            )],
+        )
+        .unwrap();
+        let program = check_program(&src, program);
+        assert!(defines(&program, "Option<i64>::unwrap_or"));
+
+        let (src, mut program) = load_files(
+            "prelude_errors",
+            &[(
+                "main.jsl",
+                "fn f() -> Result<i64, i32> { return Result::Err(1 as i32); }\nfn g() -> Option<i64> {\n    return Option::Some(f()?);\n}\nreturn 0;",
+            )],
+        )
+        .unwrap();
+        let (instantiations, receivers) = infer::infer(&mut program, &src).unwrap();
+        let (program, instances) =
+            monomorph::monomorphize(program, &instantiations, &receivers, &src).unwrap();
+        assert!(matches!(
+            typeck::check(&program, &src, &instances),
+            Err(typeck::TypeError::CannotPropagate {
+                line_number: 3,
+                column_number: 28,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn std_io() {
+        let (src, program) = load_files(
+            "std_io",
+            &[(
+                "main.jsl",
+                "fn cat(path: str) -> Result<i64, std::io::Error> {\n    var out = std::io::stdout();\n    var s = std::io::read_file(path)?;\n    return out.write_str(s.as_str());\n}\nfn main(args: []str) -> i32 {\n    return match cat(args[1]) {\n        Result::Ok(_) => 0,\n        Result::Err(_) => 1,\n    };\n}",
+            )],
+        )
+        .unwrap();
+        let program = check_program(&src, program);
+        assert!(defines(&program, "std::io::File::read_line"));
+
+        // The libc functions `std::io` uses don't take their names
+        let (src, program) = load_files(
+            "std_io_names",
+            &[(
+                "main.jsl",
+                "fn write(s: str) { print_str(s); }\nfn read() -> i64 { return 0; }\nwrite(\"a\");\nlet f = std::io::File::open(\"a.txt\");\nreturn read();",
+            )],
+        )
+        .unwrap();
+        check_program(&src, program);
+    }
+}
@@ -0,0 +1,1289 @@
+// Generic functions, structs and enums work like templates: every combination of type arguments
+// one is used with gets its own copy, named like `max<i64>` or `Pair<i64, f64>`, and the
+// generic definitions themselves are dropped. The copies are type checked like any other
+// item, so errors inside them are reported along with the uses that required them.
+use crate::{ast, error, infer, source};
+use std::collections::{HashMap, HashSet, VecDeque};
+use thiserror::Error;
+
+/// Deepest chain of instances requiring each other, polymorphic recursion like `f<T>` calling
+/// `f<(T, T)>` would otherwise never end
+const MAX_DEPTH: usize = 64;
+/// Largest type arguments of an instance, since those can double in size at every level
+const MAX_TYPE_SIZE: usize = 1024;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MonoError {
+    WrongTypeArgCount {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    NotGeneric {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    TooDeep {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    NotImplemented {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: ast::Type,
+        trait_name: String,
+    },
+}
+
+/// Where an instance of a generic function was first needed
+#[derive(Debug, PartialEq)]
+pub struct Instance {
+    pub here: usize,
+    /// The instance whose body needed it, if any
+    pub parent: Option<String>,
+}
+
+pub type Instances = HashMap<String, Instance>;
+
+/// The instance `name` and the ones that led to it, innermost first, with where each was needed
+pub fn chain(instances: &Instances, name: &str) -> Vec<(String, usize)> {
+    let mut chain = vec![];
+    let mut current = Some(name.to_string());
+    while let Some(name) = current
+        && let Some(instance) = instances.get(&name)
+    {
+        current = instance.parent.clone();
+        chain.push((name, instance.here));
+    }
+    chain
+}
+
+/// Symbol of the function `name`. Instances spell their type arguments like Rust's legacy
+/// mangling does, `max<*i64>` becomes `jz.max$LT$$BP$i64$GT$`. The `jz.` keeps them apart from
+/// C's symbols, a `fn malloc` would otherwise replace the one the runtime calls
+pub fn mangle(name: &str) -> String {
+    let mut symbol = String::from("jz.");
+    for c in name.chars() {
+        match c {
+            '<' => symbol.push_str("$LT$"),
+            '>' => symbol.push_str("$GT$"),
+            '*' => symbol.push_str("$BP$"),
+            '(' => symbol.push_str("$LP$"),
+            ')' => symbol.push_str("$RP$"),
+            ',' => symbol.push_str("$C$"),
+            ' ' => {}
+            c if c.is_alphanumeric() || c == '_' || c == ':' => symbol.push(c),
+            c => symbol.push_str(&format!("$u{:x}$", c as u32)),
+        }
+    }
+    symbol
+}
+
+/// Number of types making up `ty`
+fn size(ty: &ast::Type) -> usize {
+    1 + match ty {
+        ast::Type::Array(elem, _) | ast::Type::Pointer(elem) | ast::Type::Slice(elem) => size(elem),
+        ast::Type::Tuple(elems) => elems.iter().map(size).sum(),
+        ast::Type::Generic(_, args) => args.iter().map(size).sum(),
+        ast::Type::Function(params, ret) => params.iter().map(size).sum::<usize>() + size(ret),
+        _ => 0,
+    }
+}
+
+fn instance_name(name: &str, args: &[ast::Type]) -> String {
+    let args: Vec<_> = args.iter().map(|a| a.to_string()).collect();
+    format!("{name}<{}>", args.join(", "))
+}
+
+/// A method, lowered to a function named `Type::method`
+struct Method {
+    /// What the methods of the type are found by, `Pair` for `impl<T> Pair<T>`
+    owner: String,
+    name: String,
+    /// How many of the function's type parameters are the impl's
+    impl_generics: usize,
+    /// Whether `self` is taken by pointer, `None` without a `self`
+    by_pointer: Option<bool>,
+}
+
+/// Where a statement ends up: as is, or replaced by the instances of a generic item
+enum Slot {
+    Statement(Box<ast::Statement>),
+    Instances(String),
+}
+
+struct Monomorphizer<'a> {
+    src: &'a source::SourceMap,
+    instantiations: &'a infer::Instantiations,
+    receivers: &'a infer::Receivers,
+    /// Generic definitions by name, enums are kept with the structs
+    functions: HashMap<String, ast::Statement>,
+    structs: HashMap<String, ast::Statement>,
+    /// Every method by the name of its function
+    methods: HashMap<String, Method>,
+    /// Names of the methods of every trait
+    traits: HashMap<String, Vec<String>>,
+    /// Traits implemented by every type, by the trait and what the type's methods are found by
+    impls: HashSet<(String, String)>,
+    /// The generic struct or enum and type arguments of every instance of one
+    struct_args: HashMap<String, (String, Vec<ast::Type>)>,
+    /// Instances of every generic item, in the order they were needed
+    created: HashMap<String, Vec<ast::Statement>>,
+    /// Struct and enum instances, including the ones whose fields are still being generated
+    struct_instances: HashSet<String>,
+    instances: Instances,
+    /// Function instances whose bodies haven't been generated yet
+    queue: VecDeque<(String, String, Vec<ast::Type>)>,
+    /// Type arguments of the item being generated
+    subst: HashMap<String, ast::Type>,
+    /// The instance whose body is being generated
+    current: Option<String>,
+    /// Struct instances being generated inside each other
+    depth: usize,
+}
+
+impl Monomorphizer<'_> {
+    /// Checks the type arguments of an instance of `name` needed `depth` instances deep
+    fn check_args(
+        &self,
+        name: &str,
+        generics: &[ast::Generic],
+        args: &[ast::Type],
+        depth: usize,
+        here: usize,
+    ) -> Result<(), MonoError> {
+        let (file, line_number, column_number) = self.src.locate(here);
+        if generics.len() != args.len() {
+            return Err(MonoError::WrongTypeArgCount {
+                file,
+                line_number,
+                column_number,
+                name: name.to_string(),
+                expected: generics.len(),
+                got: args.len(),
+            });
+        }
+        if depth >= MAX_DEPTH || args.iter().map(size).sum::<usize>() > MAX_TYPE_SIZE {
+            return Err(MonoError::TooDeep {
+                file,
+                line_number,
+                column_number,
+                name: name.to_string(),
+            });
+        }
+        for (generic, arg) in generics.iter().zip(args) {
+            if let Some(bound) = generic.bounds.iter().find(|b| !self.implements(arg, b)) {
+                return Err(MonoError::NotImplemented {
+                    file,
+                    line_number,
+                    column_number,
+                    ty: arg.clone(),
+                    trait_name: bound.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// What the methods of `ty` are found by and the type arguments they're instantiated
+    /// with, `Pair` and `[i64]` for the instance `Pair<i64>`
+    fn owner(&self, ty: &ast::Type) -> (ast::Type, Vec<ast::Type>) {
+        if let ast::Type::Named(name) = ty
+            && let Some((generic, args)) = self.struct_args.get(name)
+        {
+            return (ast::Type::Named(generic.to_string()), args.clone());
+        }
+        (ty.clone(), vec![])
+    }
+
+    fn implements(&self, ty: &ast::Type, trait_name: &str) -> bool {
+        let (owner, _) = self.owner(ty);
+        owner.method_path("").is_some()
+            && self
+                .impls
+                .contains(&(trait_name.to_string(), owner.to_string()))
+    }
+
+    fn ty(&mut self, ty: &ast::Type, here: usize) -> Result<ast::Type, MonoError> {
+        Ok(match ty {
+            ast::Type::Named(name) => self.subst.get(name).cloned().unwrap_or(ty.clone()),
+            ast::Type::Array(elem, len) => ast::Type::Array(Box::new(self.ty(elem, here)?), *len),
+            ast::Type::Pointer(inner) => ast::Type::Pointer(Box::new(self.ty(inner, here)?)),
+            ast::Type::Slice(elem) => ast::Type::Slice(Box::new(self.ty(elem, here)?)),
+            ast::Type::Tuple(elems) => ast::Type::Tuple(
+                elems
+                    .iter()
+                    .map(|e| self.ty(e, here))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ast::Type::Generic(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| self.ty(a, here))
+                    .collect::<Result<_, _>>()?;
+                ast::Type::Named(self.struct_instance(name, args, here)?)
+            }
+            ast::Type::Function(params, ret) => ast::Type::Function(
+                params
+                    .iter()
+                    .map(|p| self.ty(p, here))
+                    .collect::<Result<_, _>>()?,
+                Box::new(self.ty(ret, here)?),
+            ),
+            ty => ty.clone(),
+        })
+    }
+
+    /// The instance of the generic struct or enum `name` for `args`, generated the first time
+    /// it's needed
+    fn struct_instance(
+        &mut self,
+        name: &str,
+        args: Vec<ast::Type>,
+        here: usize,
+    ) -> Result<String, MonoError> {
+        let Some(generic) = self.structs.get(name).cloned() else {
+            let (file, line_number, column_number) = self.src.locate(here);
+            return Err(MonoError::NotGeneric {
+                file,
+                line_number,
+                column_number,
+                name: name.to_string(),
+            });
+        };
+        let (ast::Statement::Struct { generics, .. } | ast::Statement::Enum { generics, .. }) =
+            &generic
+        else {
+            unreachable!()
+        };
+        self.check_args(name, generics, &args, self.depth, here)?;
+        let instance = instance_name(name, &args);
+        // Fields can point to the struct being generated
+        if !self.struct_instances.insert(instance.clone()) {
+            return Ok(instance);
+        }
+        self.struct_args
+            .insert(instance.clone(), (name.to_string(), args.clone()));
+        let subst = generics
+            .iter()
+            .map(|g| g.name.to_string())
+            .zip(args)
+            .collect();
+        let outer = std::mem::replace(&mut self.subst, subst);
+        self.depth += 1;
+        let st = match generic {
+            ast::Statement::Struct { fields, here, .. } => {
+                self.fields(fields).map(|fields| ast::Statement::Struct {
+                    name: instance.clone(),
+                    generics: vec![],
+                    fields,
+                    here,
+                })
+            }
+            ast::Statement::Enum { variants, here, .. } => {
+                self.variants(variants)
+                    .map(|variants| ast::Statement::Enum {
+                        name: instance.clone(),
+                        generics: vec![],
+                        variants,
+                        here,
+                    })
+            }
+            _ => unreachable!(),
+        };
+        self.depth -= 1;
+        self.subst = outer;
+        self.created.entry(name.to_string()).or_default().push(st?);
+        Ok(instance)
+    }
+
+    fn fields(&mut self, fields: Vec<ast::FieldDecl>) -> Result<Vec<ast::FieldDecl>, MonoError> {
+        fields
+            .into_iter()
+            .map(|f| {
+                Ok(ast::FieldDecl {
+                    ty: self.ty(&f.ty, f.here)?,
+                    ..f
+                })
+            })
+            .collect()
+    }
+
+    fn variants(
+        &mut self,
+        variants: Vec<ast::VariantDecl>,
+    ) -> Result<Vec<ast::VariantDecl>, MonoError> {
+        variants
+            .into_iter()
+            .map(|v| {
+                Ok(ast::VariantDecl {
+                    payload: v
+                        .payload
+                        .iter()
+                        .map(|ty| self.ty(ty, v.here))
+                        .collect::<Result<_, _>>()?,
+                    ..v
+                })
+            })
+            .collect()
+    }
+
+    fn function_instance(
+        &mut self,
+        name: &str,
+        args: Vec<ast::Type>,
+        here: usize,
+    ) -> Result<String, MonoError> {
+        let ast::Statement::Function { generics, .. } = &self.functions[name] else {
+            unreachable!()
+        };
+        let depth = self
+            .current
+            .as_ref()
+            .map_or(0, |current| chain(&self.instances, current).len());
+        self.check_args(name, generics, &args, depth, here)?;
+        // Methods of generic types are named after the type instance, `Pair<i64>::first`
+        let instance = match self.methods.get(name) {
+            Some(method) if method.impl_generics > 0 => {
+                let (impl_args, args) = args.split_at(method.impl_generics);
+                let owner = instance_name(&method.owner, impl_args);
+                match args {
+                    [] => format!("{owner}::{}", method.name),
+                    args => instance_name(&format!("{owner}::{}", method.name), args),
+                }
+            }
+            _ => instance_name(name, &args),
+        };
+        if !self.instances.contains_key(&instance) {
+            self.instances.insert(
+                instance.clone(),
+                Instance {
+                    here,
+                    parent: self.current.clone(),
+                },
+            );
+            self.queue
+                .push_back((name.to_string(), instance.clone(), args));
+        }
+        Ok(instance)
+    }
+
+    /// Generates the bodies of every function instance needed so far, and the ones they need
+    fn generate(&mut self) -> Result<(), MonoError> {
+        while let Some((generic, instance, args)) = self.queue.pop_front() {
+            let ast::Statement::Function {
+                generics,
+                params,
+                ret,
+                body,
+                here,
+                ..
+            } = self.functions[&generic].clone()
+            else {
+                unreachable!()
+            };
+            self.subst = generics.into_iter().map(|g| g.name).zip(args).collect();
+            self.current = Some(instance.clone());
+            let function = ast::Statement::Function {
+                name: instance,
+                generics: vec![],
+                params: self.params(params)?,
+                ret: self.ty(&ret, here)?,
+                body: self.block(body)?,
+                here,
+            };
+            self.created.entry(generic).or_default().push(function);
+        }
+        self.subst.clear();
+        self.current = None;
+        Ok(())
+    }
+
+    fn params(&mut self, params: Vec<ast::Param>) -> Result<Vec<ast::Param>, MonoError> {
+        params
+            .into_iter()
+            .map(|p| {
+                Ok(ast::Param {
+                    ty: self.ty(&p.ty, p.here)?,
+                    ..p
+                })
+            })
+            .collect()
+    }
+
+    fn block(&mut self, body: Vec<ast::Statement>) -> Result<Vec<ast::Statement>, MonoError> {
+        body.into_iter().map(|st| self.statement(st)).collect()
+    }
+
+    fn statement(&mut self, st: ast::Statement) -> Result<ast::Statement, MonoError> {
+        Ok(match st {
+            ast::Statement::Return(value) => ast::Statement::Return(self.expression(value)?),
+            ast::Statement::DefineVar {
+                name,
+                mutable,
+                ty,
+                value,
+                here,
+            } => ast::Statement::DefineVar {
+                name,
+                mutable,
+                ty: ty.map(|ty| self.ty(&ty, here)).transpose()?,
+                value: self.expression(value)?,
+                here,
+            },
+            ast::Statement::DefineTuple { names, ty, value } => ast::Statement::DefineTuple {
+                ty: ty.map(|ty| self.ty(&ty, value.here())).transpose()?,
+                names,
+                value: self.expression(value)?,
+            },
+            ast::Statement::Assign { target, value } => ast::Statement::Assign {
+                target: self.expression(target)?,
+                value: self.expression(value)?,
+            },
+            ast::Statement::Struct {
+                name,
+                generics,
+                fields,
+                here,
+            } => ast::Statement::Struct {
+                name,
+                generics,
+                fields: self.fields(fields)?,
+                here,
+            },
+            ast::Statement::Enum {
+                name,
+                generics,
+                variants,
+                here,
+            } => ast::Statement::Enum {
+                name,
+                generics,
+                variants: self.variants(variants)?,
+                here,
+            },
+            ast::Statement::Function {
+                name,
+                generics,
+                params,
+                ret,
+                body,
+                here,
+            } => ast::Statement::Function {
+                name,
+                generics,
+                params: self.params(params)?,
+                ret: self.ty(&ret, here)?,
+                body: self.block(body)?,
+                here,
+            },
+            ast::Statement::Extern {
+                name,
+                params,
+                ret,
+                here,
+            } => ast::Statement::Extern {
+                name,
+                params: self.params(params)?,
+                ret: self.ty(&ret, here)?,
+                here,
+            },
+            ast::Statement::Const {
+                name,
+                ty,
+                value,
+                here,
+            } => ast::Statement::Const {
+                name,
+                ty: self.ty(&ty, here)?,
+                value: self.expression(value)?,
+                here,
+            },
+            ast::Statement::Static {
+                name,
+                ty,
+                value,
+                here,
+            } => ast::Statement::Static {
+                name,
+                ty: self.ty(&ty, here)?,
+                value: self.expression(value)?,
+                here,
+            },
+            ast::Statement::Expression(value) => {
+                ast::Statement::Expression(self.expression(value)?)
+            }
+            ast::Statement::For {
+                var,
+                start,
+                end,
+                inclusive,
+                body,
+                here,
+            } => ast::Statement::For {
+                var,
+                start: self.expression(start)?,
+                end: self.expression(end)?,
+                inclusive,
+                body: self.block(body)?,
+                here,
+            },
+            ast::Statement::Defer { stmt, here } => ast::Statement::Defer {
+                stmt: Box::new(self.statement(*stmt)?),
+                here,
+            },
+            st @ (ast::Statement::Mod { .. }
+            | ast::Statement::Trait { .. }
+            | ast::Statement::Break { .. }
+            | ast::Statement::Continue { .. }) => st,
+            // Lowered into functions up front, impls only appear at the top level
+            ast::Statement::Impl { .. } => unreachable!(),
+        })
+    }
+
+    /// The instance of the enum `ty` a variant or pattern at `here` refers to, `ty` itself
+    /// unless it's generic
+    fn enum_instance(&mut self, ty: String, here: usize) -> Result<String, MonoError> {
+        if !self.structs.contains_key(&ty) {
+            return Ok(ty);
+        }
+        let args = self.type_args(here)?;
+        self.struct_instance(&ty, args, here)
+    }
+
+    /// Type arguments `infer` found for the use of a generic item at `here`, in terms of the
+    /// instance being generated
+    fn type_args(&mut self, here: usize) -> Result<Vec<ast::Type>, MonoError> {
+        self.instantiations[&here]
+            .iter()
+            .map(|ty| self.ty(ty, here))
+            .collect()
+    }
+
+    /// Turns `value.method(args)` into a call of the function implementing the method. Calls
+    /// through `dyn` are left for the backend, and calls of missing methods for `typeck`
+    fn method_call(
+        &mut self,
+        value: ast::Expression,
+        method: String,
+        mut args: Vec<ast::Expression>,
+        here: usize,
+    ) -> Result<ast::Expression, MonoError> {
+        let receiver = match self.receivers.get(&here) {
+            Some(ty) => Some(self.ty(ty, here)?),
+            None => None,
+        };
+        let is_pointer = matches!(receiver, Some(ast::Type::Pointer(_)));
+        let target = match receiver {
+            Some(ast::Type::Pointer(inner)) => Some(*inner),
+            ty => ty,
+        };
+        let resolved = target
+            .filter(|ty| !matches!(ty, ast::Type::Dyn(_)))
+            .and_then(|ty| {
+                let (owner, owner_args) = self.owner(&ty);
+                let path = owner.method_path(&method)?;
+                let by_pointer = self.methods.get(&path)?.by_pointer?;
+                Some((path, owner_args, by_pointer))
+            });
+        let Some((path, owner_args, by_pointer)) = resolved else {
+            return Ok(ast::Expression::MethodCall {
+                value: Box::new(value),
+                method,
+                args,
+                here,
+            });
+        };
+        let name = if self.functions.contains_key(&path) {
+            // Calls through a type parameter's bounds have no type arguments of their own
+            let type_args = if self.instantiations.contains_key(&here) {
+                self.type_args(here)?
+            } else {
+                owner_args
+            };
+            self.function_instance(&path, type_args, here)?
+        } else {
+            path
+        };
+        // At the value's own offset, which is how `typeck` tells the `&` apart from a written
+        // one when the receiver can't be pointed to
+        let value_here = value.here();
+        let value = match (by_pointer, is_pointer) {
+            (true, false) => ast::Expression::AddressOf {
+                value: Box::new(value),
+                here: value_here,
+            },
+            (false, true) => ast::Expression::Deref {
+                value: Box::new(value),
+                here: value_here,
+            },
+            _ => value,
+        };
+        args.insert(0, value);
+        Ok(ast::Expression::Call { name, args, here })
+    }
+
+    /// Checks the value cast to `*dyn trait_name` at `here` implements it, and instantiates
+    /// the methods its vtable needs
+    fn dyn_cast(&mut self, trait_name: &str, here: usize) -> Result<(), MonoError> {
+        let Some(ty) = self.receivers.get(&here) else {
+            return Ok(());
+        };
+        let ast::Type::Pointer(ty) = self.ty(ty, here)? else {
+            return Ok(());
+        };
+        if let ast::Type::Dyn(_) = *ty {
+            return Ok(());
+        }
+        if !self.implements(&ty, trait_name) {
+            let (file, line_number, column_number) = self.src.locate(here);
+            return Err(MonoError::NotImplemented {
+                file,
+                line_number,
+                column_number,
+                ty: *ty,
+                trait_name: trait_name.to_string(),
+            });
+        }
+        let (owner, owner_args) = self.owner(&ty);
+        for method in self.traits[trait_name].clone() {
+            let path = owner.method_path(&method).unwrap();
+            if self.functions.contains_key(&path) {
+                self.function_instance(&path, owner_args.clone(), here)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn expressions(
+        &mut self,
+        exprs: Vec<ast::Expression>,
+    ) -> Result<Vec<ast::Expression>, MonoError> {
+        exprs.into_iter().map(|e| self.expression(e)).collect()
+    }
+
+    fn expression(&mut self, expr: ast::Expression) -> Result<ast::Expression, MonoError> {
+        Ok(match expr {
+            ast::Expression::Binary { left, op, right } => ast::Expression::Binary {
+                left: Box::new(self.expression(*left)?),
+                op,
+                right: Box::new(self.expression(*right)?),
+            },
+            ast::Expression::Cast { value, to, here } => {
+                let to = self.ty(&to, here)?;
+                if let ast::Type::Pointer(inner) = &to
+                    && let ast::Type::Dyn(trait_name) = &**inner
+                {
+                    self.dyn_cast(trait_name, here)?;
+                }
+                ast::Expression::Cast {
+                    value: Box::new(self.expression(*value)?),
+                    to,
+                    here,
+                }
+            }
+            ast::Expression::Array { elements, here } => ast::Expression::Array {
+                elements: self.expressions(elements)?,
+                here,
+            },
+            ast::Expression::Tuple { elements, here } => ast::Expression::Tuple {
+                elements: self.expressions(elements)?,
+                here,
+            },
+            ast::Expression::Index { array, index, here } => ast::Expression::Index {
+                array: Box::new(self.expression(*array)?),
+                index: Box::new(self.expression(*index)?),
+                here,
+            },
+            ast::Expression::Slice {
+                value,
+                start,
+                end,
+                inclusive,
+                here,
+            } => ast::Expression::Slice {
+                value: Box::new(self.expression(*value)?),
+                start: start
+                    .map(|e| self.expression(*e).map(Box::new))
+                    .transpose()?,
+                end: end.map(|e| self.expression(*e).map(Box::new)).transpose()?,
+                inclusive,
+                here,
+            },
+            ast::Expression::StructLiteral { name, fields, here } => {
+                let name = if self.structs.contains_key(&name) {
+                    let args = self.type_args(here)?;
+                    self.struct_instance(&name, args, here)?
+                } else {
+                    name
+                };
+                ast::Expression::StructLiteral {
+                    name,
+                    fields: fields
+                        .into_iter()
+                        .map(|f| {
+                            Ok(ast::FieldInit {
+                                value: self.expression(f.value)?,
+                                ..f
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                    here,
+                }
+            }
+            ast::Expression::Field { value, field, here } => ast::Expression::Field {
+                value: Box::new(self.expression(*value)?),
+                field,
+                here,
+            },
+            ast::Expression::Variant {
+                ty,
+                variant,
+                args,
+                here,
+            } => ast::Expression::Variant {
+                ty: self.enum_instance(ty, here)?,
+                variant,
+                args: self.expressions(args)?,
+                here,
+            },
+            ast::Expression::Match { value, arms, here } => ast::Expression::Match {
+                value: Box::new(self.expression(*value)?),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| {
+                        let pattern = match arm.pattern {
+                            ast::Pattern::Variant {
+                                ty,
+                                variant,
+                                bindings,
+                                here,
+                            } => ast::Pattern::Variant {
+                                ty: self.enum_instance(ty, here)?,
+                                variant,
+                                bindings,
+                                here,
+                            },
+                            pattern => pattern,
+                        };
+                        Ok(ast::MatchArm {
+                            pattern,
+                            value: self.expression(arm.value)?,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                here,
+            },
+            ast::Expression::AddressOf { value, here } => ast::Expression::AddressOf {
+                value: Box::new(self.expression(*value)?),
+                here,
+            },
+            ast::Expression::Deref { value, here } => ast::Expression::Deref {
+                value: Box::new(self.expression(*value)?),
+                here,
+            },
+            ast::Expression::Try { value, here } => ast::Expression::Try {
+                value: Box::new(self.expression(*value)?),
+                here,
+            },
+            ast::Expression::Call { name, args, here } => {
+                let name = if self.functions.contains_key(&name) {
+                    let type_args = self.type_args(here)?;
+                    self.function_instance(&name, type_args, here)?
+                } else {
+                    name
+                };
+                ast::Expression::Call {
+                    name,
+                    args: self.expressions(args)?,
+                    here,
+                }
+            }
+            ast::Expression::Closure {
+                params,
+                ret,
+                body,
+                here,
+            } => ast::Expression::Closure {
+                params: params
+                    .into_iter()
+                    .map(|p| {
+                        Ok(ast::ClosureParam {
+                            ty: p.ty.map(|ty| self.ty(&ty, p.here)).transpose()?,
+                            ..p
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                ret: ret.map(|ty| self.ty(&ty, here)).transpose()?,
+                body: self.block(body)?,
+                here,
+            },
+            ast::Expression::MethodCall {
+                value,
+                method,
+                args,
+                here,
+            } => {
+                let value = self.expression(*value)?;
+                let args = self.expressions(args)?;
+                self.method_call(value, method, args, here)?
+            }
+            expr @ (ast::Expression::Number { .. }
+            | ast::Expression::Float { .. }
+            | ast::Expression::Char { .. }
+            | ast::Expression::Str { .. }
+            | ast::Expression::Variable { .. }
+            | ast::Expression::Null { .. }) => expr,
+        })
+    }
+}
+
+/// The methods of an impl as functions named `Type::method`, `Self` replaced by the type and
+/// the impl's type parameters coming first
+fn lower_impl(
+    monomorphizer: &mut Monomorphizer,
+    generics: Vec<ast::Generic>,
+    trait_name: Option<String>,
+    ty: ast::Type,
+    methods: Vec<ast::Statement>,
+) -> Vec<ast::Statement> {
+    let owner = match &ty {
+        ast::Type::Generic(name, _) => ast::Type::Named(name.to_string()),
+        ty => ty.clone(),
+    };
+    if let Some(trait_name) = trait_name {
+        monomorphizer.impls.insert((trait_name, owner.to_string()));
+    }
+    let subst = HashMap::from([(String::from("Self"), ty)]);
+    methods
+        .into_iter()
+        .filter_map(|method| {
+            let ast::Statement::Function {
+                name,
+                generics: method_generics,
+                params,
+                ret,
+                body,
+                here,
+            } = method
+            else {
+                return None;
+            };
+            let params: Vec<_> = params
+                .into_iter()
+                .map(|p| ast::Param {
+                    ty: p.ty.substitute(&subst),
+                    ..p
+                })
+                .collect();
+            let path = owner.method_path(&name)?;
+            monomorphizer.methods.insert(
+                path.clone(),
+                Method {
+                    owner: owner.to_string(),
+                    name,
+                    impl_generics: generics.len(),
+                    by_pointer: params
+                        .first()
+                        .filter(|p| p.name == "self")
+                        .map(|p| matches!(p.ty, ast::Type::Pointer(_))),
+                },
+            );
+            Some(ast::Statement::Function {
+                name: path,
+                generics: generics.iter().cloned().chain(method_generics).collect(),
+                params,
+                ret: ret.substitute(&subst),
+                body,
+                here,
+            })
+        })
+        .collect()
+}
+
+/// Replaces every generic function, struct and enum with the instances the program uses, and
+/// method calls with calls of the functions implementing them
+pub fn monomorphize(
+    program: Vec<ast::Statement>,
+    instantiations: &infer::Instantiations,
+    receivers: &infer::Receivers,
+    src: &source::SourceMap,
+) -> Result<(Vec<ast::Statement>, Instances), MonoError> {
+    let mut monomorphizer = Monomorphizer {
+        src,
+        instantiations,
+        receivers,
+        functions: HashMap::new(),
+        structs: HashMap::new(),
+        methods: HashMap::new(),
+        traits: HashMap::new(),
+        impls: HashSet::new(),
+        struct_args: HashMap::new(),
+        created: HashMap::new(),
+        struct_instances: HashSet::new(),
+        instances: HashMap::new(),
+        queue: VecDeque::new(),
+        subst: HashMap::new(),
+        current: None,
+        depth: 0,
+    };
+    let program: Vec<_> = program
+        .into_iter()
+        .flat_map(|st| match st {
+            ast::Statement::Impl {
+                generics,
+                trait_name,
+                ty,
+                methods,
+                ..
+            } => lower_impl(&mut monomorphizer, generics, trait_name, ty, methods),
+            st => vec![st],
+        })
+        .collect();
+    let mut slots = vec![];
+    for st in program {
+        match &st {
+            ast::Statement::Trait { name, methods, .. } => {
+                let methods = methods.iter().map(|m| m.name.to_string()).collect();
+                monomorphizer.traits.insert(name.to_string(), methods);
+                slots.push(Slot::Statement(Box::new(st)));
+            }
+            ast::Statement::Function { name, generics, .. } if !generics.is_empty() => {
+                slots.push(Slot::Instances(name.to_string()));
+                monomorphizer.functions.insert(name.to_string(), st);
+            }
+            ast::Statement::Struct { name, generics, .. }
+            | ast::Statement::Enum { name, generics, .. }
+                if !generics.is_empty() =>
+            {
+                slots.push(Slot::Instances(name.to_string()));
+                monomorphizer.structs.insert(name.to_string(), st);
+            }
+            _ => slots.push(Slot::Statement(Box::new(st))),
+        }
+    }
+    let slots = slots
+        .into_iter()
+        .map(|slot| match slot {
+            Slot::Statement(st) => Ok(Slot::Statement(Box::new(monomorphizer.statement(*st)?))),
+            slot => Ok(slot),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    monomorphizer.generate()?;
+    let program = slots
+        .into_iter()
+        .flat_map(|slot| match slot {
+            Slot::Statement(st) => vec![*st],
+            Slot::Instances(name) => monomorphizer.created.remove(&name).unwrap_or_default(),
+        })
+        .collect();
+    Ok((program, monomorphizer.instances))
+}
+
+impl std::fmt::Display for MonoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongTypeArgCount {
+                file,
+                line_number,
+                column_number,
+                name,
+                expected,
+                got,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{name} takes {expected} type arguments, got {got}").as_str(),
+            ),
+            Self::NotGeneric {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{name} is not a generic struct or enum").as_str(),
+            ),
+            Self::TooDeep {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Reached the limit of nested generic instances while instantiating {name}")
+                    .as_str(),
+            ),
+            Self::NotImplemented {
+                file,
+                line_number,
+                column_number,
+                ty,
+                trait_name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{ty} doesn't implement {trait_name}").as_str(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, typeck};
+
+    fn monomorphize_str(
+        s: &str,
+    ) -> Result<(Vec<ast::Statement>, Instances, source::SourceMap), MonoError> {
+        let src = source::Source::new(s);
+        let tokens = lexer::lex_file(src.clone()).unwrap();
+        let mut program = ast::parse(&tokens).unwrap();
+        let src = src.into();
+        let (instantiations, receivers) = infer::infer(&mut program, &src).unwrap();
+        let (program, instances) = monomorphize(program, &instantiations, &receivers, &src)?;
+        Ok((program, instances, src))
+    }
+
+    fn names(program: &[ast::Statement]) -> Vec<&str> {
+        program
+            .iter()
+            .filter_map(|st| match st {
+                ast::Statement::Function { name, .. } | ast::Statement::Struct { name, .. } => {
+                    Some(name.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn instances() {
+        let (program, instances, src) = monomorphize_str(
+            "fn id<T>(x: T) -> T { return x; }\nstruct Pair<A, B> { a: A, b: B }\nfn unused<T>() {}\nlet p = Pair { a: id(1), b: id(2.5) };\nlet q: Pair<i32, f64> = p;\nreturn id(q).a as i64;",
+        )
+        .unwrap();
+        assert_eq!(
+            names(&program),
+            vec!["id<i32>", "id<f64>", "id<Pair<i32, f64>>", "Pair<i32, f64>"]
+        );
+        assert_eq!(
+            program[3],
+            ast::Statement::Struct {
+                name: String::from("Pair<i32, f64>"),
+                generics: vec![],
+                fields: vec![
+                    ast::FieldDecl {
+                        name: String::from("a"),
+                        ty: ast::Type::I32,
+                        here: 54
+                    },
+                    ast::FieldDecl {
+                        name: String::from("b"),
+                        ty: ast::Type::F64,
+                        here: 60
+                    },
+                ],
+                here: 41,
+            }
+        );
+        assert_eq!(instances["id<f64>"].parent, None);
+        assert_eq!(typeck::check(&program, &src, &instances), Ok(()));
+    }
+
+    #[test]
+    fn recursive_struct() {
+        let (program, _, _) = monomorphize_str(
+            "struct List<T> { value: T, next: *List<T> }\nlet l: *List<u8> = null;",
+        )
+        .unwrap();
+        assert_eq!(
+            program[0],
+            ast::Statement::Struct {
+                name: String::from("List<u8>"),
+                generics: vec![],
+                fields: vec![
+                    ast::FieldDecl {
+                        name: String::from("value"),
+                        ty: ast::Type::U8,
+                        here: 17
+                    },
+                    ast::FieldDecl {
+                        name: String::from("next"),
+                        ty: ast::Type::Pointer(Box::new(ast::Type::Named(String::from(
+                            "List<u8>"
+                        )))),
+                        here: 27
+                    },
+                ],
+                here: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn generic_enums() {
+        let (program, instances, src) = monomorphize_str(
+            "enum Maybe<T> { Just(T), Nothing }\nfn get(m: Maybe<u8>) -> u8 { return match m { Maybe::Just(x) => x, Maybe::Nothing => 0 }; }\nreturn get(Maybe::Just(7)) as i64;",
+        )
+        .unwrap();
+        assert_eq!(
+            program[0],
+            ast::Statement::Enum {
+                name: String::from("Maybe<u8>"),
+                generics: vec![],
+                variants: vec![
+                    ast::VariantDecl {
+                        name: String::from("Just"),
+                        payload: vec![ast::Type::U8],
+                        here: 16
+                    },
+                    ast::VariantDecl {
+                        name: String::from("Nothing"),
+                        payload: vec![],
+                        here: 25
+                    },
+                ],
+                here: 5,
+            }
+        );
+        assert_eq!(typeck::check(&program, &src, &instances), Ok(()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            monomorphize_str("struct P<T> { x: T }\nlet p: P<i64, i64> = P { x: 1 };")
+                .err()
+                .unwrap(),
+            MonoError::WrongTypeArgCount {
+                file: None,
+                line_number: 2,
+                column_number: 5,
+                name: String::from("P"),
+                expected: 1,
+                got: 2,
+            }
+        );
+        assert!(matches!(
+            monomorphize_str("fn f<T>(x: T) -> i64 { return f((x, x)); }\nreturn f(1);").err(),
+            Some(MonoError::TooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn errors_in_instances() {
+        let (program, instances, src) = monomorphize_str(
+            "struct S { v: i64 }\nfn add<T>(a: T, b: T) -> T { return a + b; }\nfn twice<T>(x: T) -> T { return add(x, x); }\ntwice(S { v: 1 });",
+        )
+        .unwrap();
+        match typeck::check(&program, &src, &instances) {
+            Err(typeck::TypeError::InInstance { error, chain }) => {
+                assert!(matches!(*error, typeck::TypeError::InvalidOperands { .. }));
+                assert_eq!(
+                    chain,
+                    vec![
+                        (String::from("add<S>"), None, 3, 33),
+                        (String::from("twice<S>"), None, 4, 1),
+                    ]
+                );
+            }
+            got => panic!("expected an error in add<S>, got {got:?}"),
+        }
+    }
+
+    #[test]
+    fn methods() {
+        let (program, instances, src) = monomorphize_str(
+            "struct Pair<T> { a: T, b: T }\nimpl<T> Pair<T> { fn first(self: *Self) -> T { return self.a; } fn swap(self) -> Self { return Pair { a: self.b, b: self.a }; } }\ntrait Show { fn show(self) -> i64; }\nimpl Show for i64 { fn show(self) -> i64 { return self; } }\nfn total<T: Show>(x: T) -> i64 { return x.show(); }\nvar p = Pair { a: 1, b: 2 }.swap();\nlet q = &p;\nreturn q.first() + total(3);",
+        )
+        .unwrap();
+        assert_eq!(
+            names(&program),
+            vec![
+                "Pair<i64>",
+                "Pair<i64>::first",
+                "Pair<i64>::swap",
+                "i64::show",
+                "total<i64>"
+            ]
+        );
+        assert_eq!(typeck::check(&program, &src, &instances), Ok(()));
+        assert!(matches!(
+            monomorphize_str(
+                "trait Show { fn show(self) -> i64; }\nfn total<T: Show>(x: T) -> i64 { return x.show(); }\nreturn total(1.5);"
+            )
+            .err(),
+            Some(MonoError::NotImplemented { ty: ast::Type::F64, .. })
+        ));
+    }
+
+    #[test]
+    fn pointer_receivers_need_mutable_bindings() {
+        let (program, instances, src) = monomorphize_str(
+            "struct Counter { n: i64 }\nimpl Counter { fn bump(self: *Self) { self.n = self.n + 1; } }\nlet c = Counter { n: 0 };\nc.bump();\nreturn c.n;",
+        )
+        .unwrap();
+        assert_eq!(
+            typeck::check(&program, &src, &instances),
+            Err(typeck::TypeError::ReceiverImmutable {
+                file: None,
+                line_number: 4,
+                column_number: 1,
+                name: String::from("c"),
+                declared: Box::new((None, 3, 5)),
+            })
+        );
+    }
+
+    #[test]
+    fn trait_objects() {
+        const SHAPE: &str = "trait Shape { fn area(self) -> f64; fn grow(self: *Self) -> Self; }\nstruct Square { side: f64 }\nimpl Shape for Square { fn area(self) -> f64 { return self.side * self.side; } fn grow(self: *Self) -> Self { return Square { side: self.side * 2.0 }; } }\n";
+        let (program, instances, src) = monomorphize_str(&format!(
+            "{SHAPE}var s = Square {{ side: 1.0 }};\nlet shape = &s as *dyn Shape;\nreturn shape.area() as i64;"
+        ))
+        .unwrap();
+        assert_eq!(typeck::check(&program, &src, &instances), Ok(()));
+
+        let (program, instances, src) = monomorphize_str(&format!(
+            "{SHAPE}var s = Square {{ side: 1.0 }};\nlet shape = &s as *dyn Shape;\nshape.grow();"
+        ))
+        .unwrap();
+        assert!(matches!(
+            typeck::check(&program, &src, &instances),
+            Err(typeck::TypeError::NotDynCallable { .. })
+        ));
+        assert!(matches!(
+            monomorphize_str(&format!("{SHAPE}var x = 1;\nlet shape = &x as *dyn Shape;")).err(),
+            Some(MonoError::NotImplemented {
+                ty: ast::Type::I64,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn mangling() {
+        assert_eq!(mangle("geo::sum"), "jz.geo::sum");
+        assert_eq!(mangle("malloc"), "jz.malloc");
+        assert_eq!(
+            mangle("max<*i64, (u8, [f32; 2])>"),
+            "jz.max$LT$$BP$i64$C$$LP$u8$C$$u5b$f32$u3b$2$u5d$$RP$$GT$"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+// Runtime linked into every Jizzle program by `backend::compile`.
+// Every symbol is prefixed with `jz_` so it can't clash with user functions, which are
+// prefixed with `jz.` themselves so they can't replace the libc functions used here.
+#include <errno.h>
+#include <fcntl.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+// Exit code of a program that panicked, distinct from a crash or a `return 1`
+#define JZ_PANIC_EXIT_CODE 101
+
+#ifdef JZ_BACKTRACE
+#define UNW_LOCAL_ONLY
+#include <libunwind.h>
+
+// Prints the functions on the stack, innermost first
+static void jz_backtrace(void) {
+    unw_context_t context;
+    unw_cursor_t cursor;
+    unw_getcontext(&context);
+    unw_init_local(&cursor, &context);
+    fputs("stack backtrace:\n", stderr);
+    for (int i = 0; unw_step(&cursor) > 0; i++) {
+        char name[256];
+        unw_word_t offset;
+        if (unw_get_proc_name(&cursor, name, sizeof(name), &offset) == 0) {
+            fprintf(stderr, "%4d: %s+0x%lx\n", i, name, (unsigned long)offset);
+        } else {
+            fprintf(stderr, "%4d: <unknown>\n", i);
+        }
+    }
+}
+#endif
+
+// `location` is the `file:line:column` the panic happened at, or NULL inside the runtime
+__attribute__((noreturn)) void jz_panic(const char *location, const char *format, ...) {
+    va_list args;
+    // Anything the program printed before the panic should still show up
+    fflush(stdout);
+    fputs("panicked", stderr);
+    if (location != NULL) {
+        fprintf(stderr, " at %s", location);
+    }
+    fputs(": ", stderr);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fputc('\n', stderr);
+#ifdef JZ_BACKTRACE
+    jz_backtrace();
+#endif
+    _Exit(JZ_PANIC_EXIT_CODE);
+}
+
+void *jz_alloc(int64_t size) {
+    if (size < 0) {
+        jz_panic(NULL, "cannot allocate %ld bytes", (long)size);
+    }
+    void *ptr = malloc((size_t)size);
+    if (ptr == NULL && size != 0) {
+        jz_panic(NULL, "out of memory allocating %ld bytes", (long)size);
+    }
+    return ptr;
+}
+
+void *jz_realloc(void *ptr, int64_t size) {
+    if (size < 0) {
+        jz_panic(NULL, "cannot allocate %ld bytes", (long)size);
+    }
+    void *new_ptr = realloc(ptr, (size_t)size);
+    if (new_ptr == NULL && size != 0) {
+        jz_panic(NULL, "out of memory allocating %ld bytes", (long)size);
+    }
+    return new_ptr;
+}
+
+void jz_free(void *ptr) {
+    free(ptr);
+}
+
+void jz_print_i64(int64_t value) {
+    printf("%ld\n", (long)value);
+}
+
+void jz_print_f64(double value) {
+    printf("%g\n", value);
+}
+
+// `str` and `[]T` values, returned as a pointer and a length
+typedef struct {
+    const void *ptr;
+    int64_t len;
+} jz_slice;
+
+// Slices are passed as their pointer and length
+void jz_print_str(const char *ptr, int64_t len) {
+    fwrite(ptr, 1, (size_t)len, stdout);
+    fputc('\n', stdout);
+}
+
+// The `[]str` handed to `fn main(args: []str)`, its strings point into `argv`
+jz_slice jz_args(int argc, char **argv) {
+    jz_slice *args = jz_alloc((int64_t)argc * (int64_t)sizeof(jz_slice));
+    for (int i = 0; i < argc; i++) {
+        args[i] = (jz_slice){argv[i], (int64_t)strlen(argv[i])};
+    }
+    return (jz_slice){args, argc};
+}
+
+int32_t jz_errno(void) {
+    return errno;
+}
+
+static const char *env_value(const char *ptr, int64_t len) {
+    char *name = jz_alloc(len + 1);
+    memcpy(name, ptr, (size_t)len);
+    name[len] = '\0';
+    const char *value = getenv(name);
+    jz_free(name);
+    return value;
+}
+
+jz_slice jz_env(const char *ptr, int64_t len) {
+    const char *value = env_value(ptr, len);
+    if (value == NULL) {
+        return (jz_slice){NULL, 0};
+    }
+    return (jz_slice){value, (int64_t)strlen(value)};
+}
+
+int64_t jz_has_env(const char *ptr, int64_t len) {
+    return env_value(ptr, len) != NULL;
+}
+
+// `std::io` declares these instead of the libc functions themselves, which would keep programs
+// from defining their own `read` or `write`
+int64_t jz_read(int32_t fd, void *buf, int64_t count) {
+    return (int64_t)read(fd, buf, (size_t)count);
+}
+
+int64_t jz_write(int32_t fd, const void *buf, int64_t count) {
+    return (int64_t)write(fd, buf, (size_t)count);
+}
+
+int32_t jz_open(const char *path, int32_t flags, int32_t mode) {
+    return open(path, flags, (mode_t)mode);
+}
+
+int32_t jz_close(int32_t fd) {
+    return close(fd);
+}
+
+int64_t jz_lseek(int32_t fd, int64_t offset, int32_t whence) {
+    return (int64_t)lseek(fd, (off_t)offset, whence);
+}
+
+// Offset of the first `byte` in the `len` bytes at `data`, `len` when there's none
+int64_t jz_find_byte(const uint8_t *data, int64_t len, uint8_t byte) {
+    const uint8_t *found = memchr(data, byte, (size_t)len);
+    return found == NULL ? len : found - data;
+}
@@ -0,0 +1,113 @@
+use crate::ast;
+
+/// C source of the runtime, compiled and linked together with every program
+pub const SOURCE: &str = include_str!("runtime.c");
+
+/// A function programs can call without declaring it
+pub struct Builtin {
+    pub name: &'static str,
+    /// The symbol `runtime.c` defines it as, builtins without one are expanded inline by the
+    /// backend
+    pub symbol: Option<&'static str>,
+    pub params: Vec<ast::Type>,
+    pub ret: ast::Type,
+}
+
+pub fn builtins() -> Vec<Builtin> {
+    let bytes = || ast::Type::Pointer(Box::new(ast::Type::Unit));
+    let int_op = |name| Builtin {
+        name,
+        symbol: None,
+        params: vec![ast::Type::I64, ast::Type::I64],
+        ret: ast::Type::I64,
+    };
+    // Like `__builtin_add_overflow`, stores the wrapped result and returns 1 on overflow
+    let checked_op = |name| Builtin {
+        name,
+        symbol: None,
+        params: vec![
+            ast::Type::I64,
+            ast::Type::I64,
+            ast::Type::Pointer(Box::new(ast::Type::I64)),
+        ],
+        ret: ast::Type::I64,
+    };
+    vec![
+        int_op("wrapping_add"),
+        int_op("wrapping_sub"),
+        int_op("wrapping_mul"),
+        int_op("saturating_add"),
+        int_op("saturating_sub"),
+        checked_op("checked_add"),
+        checked_op("checked_sub"),
+        checked_op("checked_mul"),
+        // Reports the message with where it was called from and exits, see `jz_panic`
+        Builtin {
+            name: "panic",
+            symbol: None,
+            params: vec![ast::Type::Str],
+            ret: ast::Type::Unit,
+        },
+        Builtin {
+            name: "alloc",
+            symbol: Some("jz_alloc"),
+            params: vec![ast::Type::I64],
+            ret: bytes(),
+        },
+        Builtin {
+            name: "realloc",
+            symbol: Some("jz_realloc"),
+            params: vec![bytes(), ast::Type::I64],
+            ret: bytes(),
+        },
+        Builtin {
+            name: "free",
+            symbol: Some("jz_free"),
+            params: vec![bytes()],
+            ret: ast::Type::Unit,
+        },
+        Builtin {
+            name: "print_i64",
+            symbol: Some("jz_print_i64"),
+            params: vec![ast::Type::I64],
+            ret: ast::Type::Unit,
+        },
+        Builtin {
+            name: "print_f64",
+            symbol: Some("jz_print_f64"),
+            params: vec![ast::Type::F64],
+            ret: ast::Type::Unit,
+        },
+        Builtin {
+            name: "print_str",
+            symbol: Some("jz_print_str"),
+            params: vec![ast::Type::Str],
+            ret: ast::Type::Unit,
+        },
+        // The `errno` left by the last failing libc call
+        Builtin {
+            name: "errno",
+            symbol: Some("jz_errno"),
+            params: vec![],
+            ret: ast::Type::I32,
+        },
+        // An empty string when the variable isn't set, `has_env` tells the two apart
+        Builtin {
+            name: "env",
+            symbol: Some("jz_env"),
+            params: vec![ast::Type::Str],
+            ret: ast::Type::Str,
+        },
+        // 1 when the variable is set, even to an empty string
+        Builtin {
+            name: "has_env",
+            symbol: Some("jz_has_env"),
+            params: vec![ast::Type::Str],
+            ret: ast::Type::I64,
+        },
+    ]
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    builtins().iter().any(|b| b.name == name)
+}
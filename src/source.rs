@@ -1,7 +1,10 @@
+#[derive(Clone)]
 pub struct Source {
     src: Vec<char>,
     file_name: Option<String>,
     offset: usize,
+    /// Where the file starts in its `SourceMap`, every offset handed out includes it
+    base: usize,
 }
 impl Source {
     pub fn new(src: &str) -> Self {
@@ -9,6 +12,15 @@ impl Source {
             offset: 0,
             file_name: None,
             src: src.chars().collect(),
+            base: 0,
+        }
+    }
+
+    /// A file compiled into the compiler, `name` stands in for its path in errors
+    pub fn bundled(name: &str, src: &str) -> Self {
+        Self {
+            file_name: Some(name.to_string()),
+            ..Self::new(src.trim())
         }
     }
 
@@ -22,6 +34,7 @@ impl Source {
             offset: 0,
             file_name: Some(path.into()),
             src: s.chars().collect(),
+            base: 0,
         })
     }
 
@@ -33,15 +46,17 @@ impl Source {
     }
 
     pub fn offset(&self) -> usize {
-        self.offset
+        self.base + self.offset
     }
 
-    pub fn src(&self) -> &[char] {
-        &self.src
+    /// The characters between two offsets
+    pub fn slice(&self, begin: usize, end: usize) -> &[char] {
+        &self.src[begin - self.base..end - self.base]
     }
 
-    pub fn as_string(&self) -> String {
-        self.src.iter().collect()
+    fn contains(&self, offset: usize) -> bool {
+        // The offset right after the last character is where EOF gets reported
+        (self.base..=self.base + self.src.len()).contains(&offset)
     }
 
     pub fn skip_whitespace(&mut self) {
@@ -53,12 +68,16 @@ impl Source {
     pub fn peek(&self) -> Option<&char> {
         self.src.get(self.offset)
     }
+    pub fn peek_nth(&self, n: usize) -> Option<&char> {
+        self.src.get(self.offset + n)
+    }
     pub fn next(&mut self) -> Option<&char> {
         let c = self.src.get(self.offset);
         self.offset += 1;
         c
     }
     pub fn get_position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset - self.base;
         let line = self.src[0..offset].iter().filter(|&&c| c == '\n').count() + 1;
         let column = self.src[0..offset]
             .iter()
@@ -90,3 +109,61 @@ impl Source {
         String::new()
     }
 }
+
+/// Owns every file of a program. Each file gets its own range of offsets, so an offset
+/// alone is enough to find the file it points into
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<Source>,
+}
+
+impl SourceMap {
+    /// Adds `src`, moving its offsets past those of every file added before. Returns the
+    /// file's id
+    pub fn add(&mut self, mut src: Source) -> usize {
+        src.base = self
+            .files
+            .last()
+            .map_or(0, |last| last.base + last.src.len() + 1);
+        src.offset = 0;
+        self.files.push(src);
+        self.files.len() - 1
+    }
+
+    pub fn get(&self, id: usize) -> &Source {
+        &self.files[id]
+    }
+
+    pub fn file_id(&self, offset: usize) -> usize {
+        self.files
+            .iter()
+            .position(|f| f.contains(offset))
+            .expect("offset outside of every file")
+    }
+
+    pub fn path(&self, offset: usize) -> Option<&str> {
+        self.get(self.file_id(offset)).path()
+    }
+
+    pub fn get_position(&self, offset: usize) -> (usize, usize) {
+        self.get(self.file_id(offset)).get_position(offset)
+    }
+
+    /// The file name, line and column of `offset`, as every error reports it
+    pub fn locate(&self, offset: usize) -> (Option<String>, usize, usize) {
+        let (line_number, column_number) = self.get_position(offset);
+        (
+            self.path(offset).map(|s| s.to_string()),
+            line_number,
+            column_number,
+        )
+    }
+}
+
+impl From<Source> for SourceMap {
+    fn from(src: Source) -> Self {
+        let mut map = Self::default();
+        map.add(src);
+        map
+    }
+}
@@ -0,0 +1,458 @@
+// Checks traits and their implementations before anything relies on them. An impl of a trait
+// has to provide exactly the trait's methods, with the trait's signatures once `Self` is
+// replaced by the implementing type. Methods are found through the name of their type, so a
+// type can't get the same method from two impls, and a generic impl has to be for the
+// generic struct applied to the impl's own type parameters.
+use crate::{ast, error, source};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TraitError {
+    UnknownTrait {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    InvalidImplType {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: ast::Type,
+    },
+    NoSelf {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        trait_name: String,
+        method: String,
+    },
+    MissingMethod {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        trait_name: String,
+        method: String,
+    },
+    NotInTrait {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        trait_name: String,
+        method: String,
+    },
+    SignatureMismatch {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        trait_name: String,
+        expected: String,
+    },
+    DuplicateMethod {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        method: String,
+        previous: Box<(Option<String>, usize, usize)>,
+    },
+}
+
+/// `fn name(a: A, b: B) -> R` for error messages
+fn signature(name: &str, params: &[ast::Param], ret: &ast::Type) -> String {
+    let params: Vec<_> = params
+        .iter()
+        .map(|p| format!("{}: {}", p.name, p.ty))
+        .collect();
+    match ret {
+        ast::Type::Unit => format!("fn {name}({})", params.join(", ")),
+        ret => format!("fn {name}({}) -> {ret}", params.join(", ")),
+    }
+}
+
+struct Checker<'a> {
+    src: &'a source::SourceMap,
+    traits: HashMap<&'a str, &'a [ast::TraitMethod]>,
+    /// Where every method was defined, by the name of the function implementing it
+    methods: HashMap<String, usize>,
+}
+
+impl Checker<'_> {
+    fn check_bounds(&self, generics: &[ast::Generic]) -> Result<(), TraitError> {
+        for generic in generics {
+            if let Some(bound) = generic
+                .bounds
+                .iter()
+                .find(|b| !self.traits.contains_key(b.as_str()))
+            {
+                let (file, line_number, column_number) = self.src.locate(generic.here);
+                return Err(TraitError::UnknownTrait {
+                    file,
+                    line_number,
+                    column_number,
+                    name: bound.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_impl(
+        &mut self,
+        generics: &[ast::Generic],
+        trait_name: Option<&str>,
+        ty: &ast::Type,
+        methods: &[ast::Statement],
+        here: usize,
+    ) -> Result<(), TraitError> {
+        self.check_bounds(generics)?;
+        let valid = match ty {
+            ast::Type::Generic(_, args) => {
+                args.len() == generics.len()
+                    && args
+                        .iter()
+                        .zip(generics)
+                        .all(|(arg, g)| *arg == ast::Type::Named(g.name.to_string()))
+            }
+            ty => generics.is_empty() && ty.method_path("").is_some(),
+        };
+        if !valid {
+            let (file, line_number, column_number) = self.src.locate(here);
+            return Err(TraitError::InvalidImplType {
+                file,
+                line_number,
+                column_number,
+                ty: ty.clone(),
+            });
+        }
+        for method in methods {
+            let ast::Statement::Function {
+                name,
+                generics,
+                here,
+                ..
+            } = method
+            else {
+                continue;
+            };
+            self.check_bounds(generics)?;
+            let path = ty.method_path(name).unwrap();
+            if let Some(previous) = self.methods.insert(path, *here) {
+                let (file, line_number, column_number) = self.src.locate(*here);
+                return Err(TraitError::DuplicateMethod {
+                    file,
+                    line_number,
+                    column_number,
+                    method: name.to_string(),
+                    previous: Box::new(self.src.locate(previous)),
+                });
+            }
+        }
+
+        let Some(trait_name) = trait_name else {
+            return Ok(());
+        };
+        let Some(required) = self.traits.get(trait_name) else {
+            let (file, line_number, column_number) = self.src.locate(here);
+            return Err(TraitError::UnknownTrait {
+                file,
+                line_number,
+                column_number,
+                name: trait_name.to_string(),
+            });
+        };
+        let subst = HashMap::from([(String::from("Self"), ty.clone())]);
+        for method in methods {
+            let ast::Statement::Function {
+                name,
+                generics,
+                params,
+                ret,
+                here,
+                ..
+            } = method
+            else {
+                continue;
+            };
+            let (file, line_number, column_number) = self.src.locate(*here);
+            let Some(decl) = required.iter().find(|m| m.name == *name) else {
+                return Err(TraitError::NotInTrait {
+                    file,
+                    line_number,
+                    column_number,
+                    trait_name: trait_name.to_string(),
+                    method: name.to_string(),
+                });
+            };
+            let expected: Vec<_> = decl
+                .params
+                .iter()
+                .map(|p| ast::Param {
+                    ty: p.ty.substitute(&subst),
+                    ..p.clone()
+                })
+                .collect();
+            let ret_expected = decl.ret.substitute(&subst);
+            let same = generics.is_empty()
+                && params.len() == expected.len()
+                && params
+                    .iter()
+                    .zip(&expected)
+                    .all(|(p, e)| p.ty.substitute(&subst) == e.ty)
+                && ret.substitute(&subst) == ret_expected;
+            if !same {
+                return Err(TraitError::SignatureMismatch {
+                    file,
+                    line_number,
+                    column_number,
+                    trait_name: trait_name.to_string(),
+                    expected: signature(name, &expected, &ret_expected),
+                });
+            }
+        }
+        if let Some(missing) = required.iter().find(|m| {
+            !methods
+                .iter()
+                .any(|f| matches!(f, ast::Statement::Function { name, .. } if *name == m.name))
+        }) {
+            let (file, line_number, column_number) = self.src.locate(here);
+            return Err(TraitError::MissingMethod {
+                file,
+                line_number,
+                column_number,
+                trait_name: trait_name.to_string(),
+                method: missing.name.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub fn check(program: &[ast::Statement], src: &source::SourceMap) -> Result<(), TraitError> {
+    let mut checker = Checker {
+        src,
+        traits: HashMap::new(),
+        methods: HashMap::new(),
+    };
+    for st in program {
+        if let ast::Statement::Trait { name, methods, .. } = st {
+            // Every method needs a receiver to be called through `value.method()`
+            if let Some(method) = methods
+                .iter()
+                .find(|m| m.params.first().is_none_or(|p| p.name != "self"))
+            {
+                let (file, line_number, column_number) = src.locate(method.here);
+                return Err(TraitError::NoSelf {
+                    file,
+                    line_number,
+                    column_number,
+                    trait_name: name.to_string(),
+                    method: method.name.to_string(),
+                });
+            }
+            checker.traits.insert(name, methods);
+        }
+    }
+    for st in program {
+        match st {
+            ast::Statement::Function { generics, .. }
+            | ast::Statement::Struct { generics, .. }
+            | ast::Statement::Enum { generics, .. } => checker.check_bounds(generics)?,
+            ast::Statement::Impl {
+                generics,
+                trait_name,
+                ty,
+                methods,
+                here,
+            } => checker.check_impl(generics, trait_name.as_deref(), ty, methods, *here)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for TraitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownTrait {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Unknown trait {name}").as_str(),
+            ),
+            Self::InvalidImplType {
+                file,
+                line_number,
+                column_number,
+                ty,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!(
+                    "Cannot implement methods for {ty}, only for named types and generic types applied to the impl's type parameters"
+                )
+                .as_str(),
+            ),
+            Self::NoSelf {
+                file,
+                line_number,
+                column_number,
+                trait_name,
+                method,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{trait_name}::{method} has to take self as its first parameter").as_str(),
+            ),
+            Self::MissingMethod {
+                file,
+                line_number,
+                column_number,
+                trait_name,
+                method,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Missing {trait_name}::{method} in this impl").as_str(),
+            ),
+            Self::NotInTrait {
+                file,
+                line_number,
+                column_number,
+                trait_name,
+                method,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{method} is not a method of {trait_name}").as_str(),
+            ),
+            Self::SignatureMismatch {
+                file,
+                line_number,
+                column_number,
+                trait_name,
+                expected,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Method doesn't match {trait_name}, expected {expected}").as_str(),
+            ),
+            Self::DuplicateMethod {
+                file,
+                line_number,
+                column_number,
+                method,
+                previous,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("{method} is already defined for this type").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    previous.0.as_deref(),
+                    (previous.1, previous.2),
+                    format!("{method} is first defined here").as_str(),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+
+    fn check_str(s: &str) -> Result<(), TraitError> {
+        let src = source::Source::new(s);
+        let tokens = lexer::lex_file(src.clone()).unwrap();
+        let program = ast::parse(&tokens).unwrap();
+        check(&program, &src.into())
+    }
+
+    const SHAPE: &str = "trait Shape { fn area(self) -> f64; fn scale(self: *Self, by: f64); }\nstruct Square { side: f64 }\n";
+
+    #[test]
+    fn complete_impls() {
+        assert_eq!(
+            check_str(&format!(
+                "{SHAPE}impl Shape for Square {{\n    fn area(self) -> f64 {{ return self.side * self.side; }}\n    fn scale(self: *Square, by: f64) {{ self.side = self.side * by; }}\n}}\nimpl Square {{ fn new(side: f64) -> Square {{ return Square {{ side: side }}; }} }}\nfn total<T: Shape>(s: T) -> f64 {{ return s.area(); }}"
+            )),
+            Ok(())
+        );
+        assert_eq!(
+            check_str(
+                "struct Pair<T> { a: T, b: T }\nimpl<T> Pair<T> { fn first(self) -> T { return self.a; } }"
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            check_str(&format!(
+                "{SHAPE}impl Shape for Square {{\n    fn area(self) -> f64 {{ return 0.0; }}\n}}"
+            )),
+            Err(TraitError::MissingMethod {
+                file: None,
+                line_number: 3,
+                column_number: 16,
+                trait_name: String::from("Shape"),
+                method: String::from("scale"),
+            })
+        );
+        assert_eq!(
+            check_str(&format!(
+                "{SHAPE}impl Shape for Square {{\n    fn area(self) -> f64 {{ return 0.0; }}\n    fn scale(self, by: f64) {{}}\n}}"
+            )),
+            Err(TraitError::SignatureMismatch {
+                file: None,
+                line_number: 5,
+                column_number: 8,
+                trait_name: String::from("Shape"),
+                expected: String::from("fn scale(self: *Square, by: f64)"),
+            })
+        );
+        assert_eq!(
+            check_str("struct S { x: i64 }\nimpl S { fn f(self) {} }\nimpl S { fn f(self) {} }"),
+            Err(TraitError::DuplicateMethod {
+                file: None,
+                line_number: 3,
+                column_number: 13,
+                method: String::from("f"),
+                previous: Box::new((None, 2, 13)),
+            })
+        );
+        assert_eq!(
+            check_str("fn f<T: Show>(x: T) {}"),
+            Err(TraitError::UnknownTrait {
+                file: None,
+                line_number: 1,
+                column_number: 6,
+                name: String::from("Show"),
+            })
+        );
+        assert!(matches!(
+            check_str("struct P<T> { x: T }\nimpl<T> P<i64> {}"),
+            Err(TraitError::InvalidImplType { .. })
+        ));
+        assert!(matches!(
+            check_str("trait T { fn f(x: i64); }"),
+            Err(TraitError::NoSelf { .. })
+        ));
+    }
+}
@@ -0,0 +1,3112 @@
+use crate::{ast, error, lexer, monomorph, runtime, source};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TypeError {
+    Mismatch {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        expected: ast::Type,
+        got: ast::Type,
+    },
+    UndefinedVariable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    InvalidCast {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        from: ast::Type,
+        to: ast::Type,
+    },
+    InvalidOperands {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        op: lexer::Token,
+        ty: ast::Type,
+    },
+    NotIndexable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        got: ast::Type,
+    },
+    NotSliceable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        got: ast::Type,
+    },
+    UnboundedSlice {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    IndexOutOfBounds {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        index: u64,
+        len: u64,
+    },
+    EmptyArray {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    InvalidAssignTarget {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    UnknownType {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    NoFields {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        got: ast::Type,
+    },
+    UnknownField {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: ast::Type,
+        field: String,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    MissingFields {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        fields: Vec<String>,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    DuplicateField {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        field: String,
+    },
+    Redefinition {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        previous: Box<(Option<String>, usize, usize)>,
+    },
+    RecursiveType {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    WrongKind {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        expected: &'static str,
+    },
+    UnknownVariant {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: String,
+        variant: String,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    WrongArgCount {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        expected: usize,
+        got: usize,
+    },
+    NonExhaustive {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        missing: Vec<String>,
+    },
+    UndefinedFunction {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    NotAPointer {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        got: ast::Type,
+    },
+    NotAddressable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    MissingReturn {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        ret: ast::Type,
+    },
+    NotTryable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: ast::Type,
+    },
+    CannotPropagate {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: ast::Type,
+        ret: ast::Type,
+    },
+    ReturnInDefer {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        defer: Box<(Option<String>, usize, usize)>,
+    },
+    /// `break` or `continue` outside of a loop. A `defer` runs as its block exits, so one
+    /// directly inside a loop is outside of it too
+    OutsideLoop {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        keyword: String,
+    },
+    ReservedName {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    InvalidMain {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    CodeOutsideMain {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+    },
+    AssignToConst {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    AssignToImmutable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    AssignToCaptured {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        closure: Box<(Option<String>, usize, usize)>,
+    },
+    PointerToImmutable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    /// Constants live in read-only memory
+    PointerToConst {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    /// A method taking `self: *Self` called on an immutable binding
+    ReceiverImmutable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        declared: Box<(Option<String>, usize, usize)>,
+    },
+    NotAValue {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    NotCallable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+        ty: ast::Type,
+    },
+    CannotDestructure {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        got: ast::Type,
+        names: usize,
+    },
+    UnknownTrait {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        name: String,
+    },
+    BareDyn {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: ast::Type,
+    },
+    ArrayTooLong {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        len: u64,
+    },
+    NoMethod {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        ty: ast::Type,
+        method: String,
+    },
+    NotDynCallable {
+        file: Option<String>,
+        line_number: usize,
+        column_number: usize,
+        trait_name: String,
+        method: String,
+    },
+    /// An error in an instance of a generic function, along with the uses that required it
+    InInstance {
+        error: Box<TypeError>,
+        chain: Vec<(String, Option<String>, usize, usize)>,
+    },
+}
+
+enum TypeDecl {
+    Struct(Vec<ast::FieldDecl>),
+    Enum(Vec<ast::VariantDecl>),
+}
+
+struct TypeInfo {
+    decl: TypeDecl,
+    here: usize,
+}
+
+struct FunctionInfo {
+    params: Vec<ast::Type>,
+    ret: ast::Type,
+    here: usize,
+}
+
+struct GlobalInfo {
+    ty: ast::Type,
+    is_const: bool,
+    here: usize,
+}
+
+#[derive(Clone)]
+struct VariableInfo {
+    ty: ast::Type,
+    /// Where a `let` without `mut` declared the variable
+    immutable: Option<usize>,
+    /// Where the closure holding a copy of the variable starts
+    captured: Option<usize>,
+}
+
+impl VariableInfo {
+    fn mutable(ty: ast::Type) -> Self {
+        Self {
+            ty,
+            immutable: None,
+            captured: None,
+        }
+    }
+}
+
+struct Checker<'a> {
+    src: &'a source::SourceMap,
+    variables: std::collections::HashMap<String, VariableInfo>,
+    types: std::collections::HashMap<String, TypeInfo>,
+    functions: std::collections::HashMap<String, FunctionInfo>,
+    globals: std::collections::HashMap<String, GlobalInfo>,
+    traits: std::collections::HashMap<String, Vec<ast::TraitMethod>>,
+    /// Return type of the function being checked
+    ret: ast::Type,
+    /// Where the `defer` being checked is, the function is already returning when it runs
+    deferred: Option<usize>,
+    /// How many loops the code being checked is in, within its function
+    loops: usize,
+}
+
+impl Checker<'_> {
+    fn position(&self, here: usize) -> (Option<String>, usize, usize) {
+        self.src.locate(here)
+    }
+
+    fn mismatch(&self, here: usize, expected: ast::Type, got: ast::Type) -> TypeError {
+        let (file, line_number, column_number) = self.position(here);
+        TypeError::Mismatch {
+            file,
+            line_number,
+            column_number,
+            expected,
+            got,
+        }
+    }
+
+    fn check_type(&self, ty: &ast::Type, here: usize) -> Result<(), TypeError> {
+        match ty {
+            // LLVM counts array elements in 32 bits
+            ast::Type::Array(_, len) if u32::try_from(*len).is_err() => {
+                let (file, line_number, column_number) = self.position(here);
+                Err(TypeError::ArrayTooLong {
+                    file,
+                    line_number,
+                    column_number,
+                    len: *len,
+                })
+            }
+            ast::Type::Array(elem, _) | ast::Type::Slice(elem) => self.check_type(elem, here),
+            ast::Type::Pointer(inner) => match &**inner {
+                ast::Type::Dyn(name) if !self.traits.contains_key(name) => {
+                    let (file, line_number, column_number) = self.position(here);
+                    Err(TypeError::UnknownTrait {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                    })
+                }
+                ast::Type::Dyn(_) => Ok(()),
+                inner => self.check_type(inner, here),
+            },
+            // Trait objects have no size of their own
+            ast::Type::Dyn(_) => {
+                let (file, line_number, column_number) = self.position(here);
+                Err(TypeError::BareDyn {
+                    file,
+                    line_number,
+                    column_number,
+                    ty: ty.clone(),
+                })
+            }
+            ast::Type::Tuple(elems) => elems.iter().try_for_each(|e| self.check_type(e, here)),
+            ast::Type::Function(params, ret) => {
+                params.iter().try_for_each(|p| self.check_type(p, here))?;
+                self.check_type(ret, here)
+            }
+            ast::Type::Named(name) if !self.types.contains_key(name) => {
+                let (file, line_number, column_number) = self.position(here);
+                Err(TypeError::UnknownType {
+                    file,
+                    line_number,
+                    column_number,
+                    name: name.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Looks up a user defined type, making sure it's a struct (`want_struct`) or an enum
+    fn lookup_type(
+        &self,
+        name: &str,
+        want_struct: bool,
+        here: usize,
+    ) -> Result<&TypeInfo, TypeError> {
+        self.check_type(&ast::Type::Named(name.to_string()), here)?;
+        let info = &self.types[name];
+        if matches!(info.decl, TypeDecl::Struct(_)) != want_struct {
+            let (file, line_number, column_number) = self.position(here);
+            return Err(TypeError::WrongKind {
+                file,
+                line_number,
+                column_number,
+                name: name.to_string(),
+                expected: if want_struct { "a struct" } else { "an enum" },
+            });
+        }
+        Ok(info)
+    }
+
+    fn declare_types(&mut self, program: &[ast::Statement]) -> Result<(), TypeError> {
+        for st in program {
+            let (name, decl, here) = match st {
+                ast::Statement::Struct {
+                    name, fields, here, ..
+                } => (name, TypeDecl::Struct(fields.clone()), here),
+                ast::Statement::Enum {
+                    name,
+                    variants,
+                    here,
+                    ..
+                } => (name, TypeDecl::Enum(variants.clone()), here),
+                _ => continue,
+            };
+            if let Some(previous) = self.types.get(name) {
+                let (file, line_number, column_number) = self.position(*here);
+                return Err(TypeError::Redefinition {
+                    file,
+                    line_number,
+                    column_number,
+                    name: name.to_string(),
+                    previous: Box::new(self.src.locate(previous.here)),
+                });
+            }
+            self.types
+                .insert(name.to_string(), TypeInfo { decl, here: *here });
+        }
+
+        for st in program {
+            let members: Vec<_> = match st {
+                ast::Statement::Struct { fields, .. } => {
+                    for f in fields {
+                        self.check_type(&f.ty, f.here)?;
+                    }
+                    fields.iter().map(|f| (&f.name, f.here)).collect()
+                }
+                ast::Statement::Enum { variants, .. } => {
+                    for v in variants {
+                        for ty in &v.payload {
+                            self.check_type(ty, v.here)?;
+                        }
+                    }
+                    variants.iter().map(|v| (&v.name, v.here)).collect()
+                }
+                _ => continue,
+            };
+            for (i, (name, here)) in members.iter().enumerate() {
+                if members[..i].iter().any(|(n, _)| n == name) {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::DuplicateField {
+                        file,
+                        line_number,
+                        column_number,
+                        field: name.to_string(),
+                    });
+                }
+            }
+        }
+
+        for st in program {
+            let (ast::Statement::Struct { name, here, .. }
+            | ast::Statement::Enum { name, here, .. }) = st
+            else {
+                continue;
+            };
+            if self.contains_type(&ast::Type::Named(name.to_string()), name, 0) {
+                let (file, line_number, column_number) = self.position(*here);
+                return Err(TypeError::RecursiveType {
+                    file,
+                    line_number,
+                    column_number,
+                    name: name.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn declare_functions(&mut self, program: &[ast::Statement]) -> Result<(), TypeError> {
+        for st in program {
+            let (ast::Statement::Function {
+                name,
+                params,
+                ret,
+                here,
+                ..
+            }
+            | ast::Statement::Extern {
+                name,
+                params,
+                ret,
+                here,
+            }) = st
+            else {
+                continue;
+            };
+            // Without a `fn main` the top-level statements make up `main`
+            if let ast::Statement::Function { .. } = st
+                && name == "main"
+                && !is_main_signature(params, ret)
+            {
+                let (file, line_number, column_number) = self.position(*here);
+                return Err(TypeError::InvalidMain {
+                    file,
+                    line_number,
+                    column_number,
+                });
+            }
+            if (name == "main" && matches!(st, ast::Statement::Extern { .. }))
+                || runtime::is_builtin(name)
+            {
+                let (file, line_number, column_number) = self.position(*here);
+                return Err(TypeError::ReservedName {
+                    file,
+                    line_number,
+                    column_number,
+                    name: name.to_string(),
+                });
+            }
+            if let Some(previous) = self.functions.get(name) {
+                let (file, line_number, column_number) = self.position(*here);
+                return Err(TypeError::Redefinition {
+                    file,
+                    line_number,
+                    column_number,
+                    name: name.to_string(),
+                    previous: Box::new(self.src.locate(previous.here)),
+                });
+            }
+            for p in params {
+                self.check_type(&p.ty, p.here)?;
+            }
+            self.check_type(ret, *here)?;
+            self.functions.insert(
+                name.to_string(),
+                FunctionInfo {
+                    params: params.iter().map(|p| p.ty.clone()).collect(),
+                    ret: ret.clone(),
+                    here: *here,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Declares every `const` and `static` so functions can use them regardless of order.
+    /// Whether the initializers are actually constant is up to `consteval`
+    fn declare_globals(&mut self, program: &[ast::Statement]) -> Result<(), TypeError> {
+        for st in program {
+            let (is_const, name, ty, here) = match st {
+                ast::Statement::Const { name, ty, here, .. } => (true, name, ty, here),
+                ast::Statement::Static { name, ty, here, .. } => (false, name, ty, here),
+                _ => continue,
+            };
+            if let Some(previous) = self.globals.get(name) {
+                let (file, line_number, column_number) = self.position(*here);
+                return Err(TypeError::Redefinition {
+                    file,
+                    line_number,
+                    column_number,
+                    name: name.to_string(),
+                    previous: Box::new(self.src.locate(previous.here)),
+                });
+            }
+            self.check_type(ty, *here)?;
+            self.globals.insert(
+                name.to_string(),
+                GlobalInfo {
+                    ty: ty.clone(),
+                    is_const,
+                    here: *here,
+                },
+            );
+        }
+        for st in program {
+            if let ast::Statement::Const { ty, value, .. }
+            | ast::Statement::Static { ty, value, .. } = st
+            {
+                self.check_value(ty, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a value of `ty` (transitively) holds a `name` by value, which would make it
+    /// infinitely large
+    fn contains_type(&self, ty: &ast::Type, name: &str, depth: usize) -> bool {
+        match ty {
+            ast::Type::Array(elem, _) => self.contains_type(elem, name, depth),
+            ast::Type::Named(s) if depth > 0 && s == name => true,
+            // Any cycle has to go through at most every type once
+            ast::Type::Named(s) if depth <= self.types.len() => match &self.types[s].decl {
+                TypeDecl::Struct(fields) => fields
+                    .iter()
+                    .any(|f| self.contains_type(&f.ty, name, depth + 1)),
+                TypeDecl::Enum(variants) => variants
+                    .iter()
+                    .flat_map(|v| &v.payload)
+                    .any(|t| self.contains_type(t, name, depth + 1)),
+            },
+            _ => false,
+        }
+    }
+
+    fn lookup_variant(
+        &self,
+        ty: &str,
+        variant: &str,
+        here: usize,
+    ) -> Result<ast::VariantDecl, TypeError> {
+        let info = self.lookup_type(ty, false, here)?;
+        let TypeDecl::Enum(variants) = &info.decl else {
+            unreachable!()
+        };
+        variants
+            .iter()
+            .find(|v| v.name == variant)
+            .cloned()
+            .ok_or_else(|| {
+                let (file, line_number, column_number) = self.position(here);
+                TypeError::UnknownVariant {
+                    file,
+                    line_number,
+                    column_number,
+                    ty: ty.to_string(),
+                    variant: variant.to_string(),
+                    declared: Box::new(self.src.locate(info.here)),
+                }
+            })
+    }
+
+    fn arg_count(&self, here: usize, expected: usize, got: usize) -> Result<(), TypeError> {
+        if expected == got {
+            return Ok(());
+        }
+        let (file, line_number, column_number) = self.position(here);
+        Err(TypeError::WrongArgCount {
+            file,
+            line_number,
+            column_number,
+            expected,
+            got,
+        })
+    }
+
+    /// Checks that `pattern` can match a value of `ty`, returning the variables it binds
+    fn check_pattern(
+        &self,
+        pattern: &ast::Pattern,
+        ty: &ast::Type,
+    ) -> Result<Vec<(String, ast::Type)>, TypeError> {
+        match pattern {
+            ast::Pattern::Wildcard { .. } => Ok(vec![]),
+            ast::Pattern::Number { here, .. } => {
+                if !matches!(ty, ast::Type::I32 | ast::Type::I64 | ast::Type::U8) {
+                    return Err(self.mismatch(*here, ty.clone(), ast::Type::I64));
+                }
+                Ok(vec![])
+            }
+            ast::Pattern::Char { here, .. } => {
+                if *ty != ast::Type::Char {
+                    return Err(self.mismatch(*here, ty.clone(), ast::Type::Char));
+                }
+                Ok(vec![])
+            }
+            ast::Pattern::Variant {
+                ty: enum_name,
+                variant,
+                bindings,
+                here,
+            } => {
+                let decl = self.lookup_variant(enum_name, variant, *here)?;
+                let pattern_type = ast::Type::Named(enum_name.to_string());
+                if *ty != pattern_type {
+                    return Err(self.mismatch(*here, ty.clone(), pattern_type));
+                }
+                self.arg_count(*here, decl.payload.len(), bindings.len())?;
+                Ok(bindings
+                    .iter()
+                    .zip(decl.payload)
+                    .filter(|((name, _), _)| name != "_")
+                    .map(|((name, _), ty)| (name.to_string(), ty))
+                    .collect())
+            }
+        }
+    }
+
+    fn check_exhaustive(
+        &self,
+        ty: &ast::Type,
+        arms: &[ast::MatchArm],
+        here: usize,
+    ) -> Result<(), TypeError> {
+        if arms
+            .iter()
+            .any(|a| matches!(a.pattern, ast::Pattern::Wildcard { .. }))
+        {
+            return Ok(());
+        }
+        let missing: Vec<_> = match ty {
+            ast::Type::Named(name) => {
+                let TypeDecl::Enum(variants) = &self.types[name].decl else {
+                    unreachable!()
+                };
+                variants
+                    .iter()
+                    .filter(|v| {
+                        !arms.iter().any(|a| {
+                            matches!(&a.pattern, ast::Pattern::Variant { variant, .. } if *variant == v.name)
+                        })
+                    })
+                    .map(|v| format!("{name}::{}", v.name))
+                    .collect()
+            }
+            _ => vec![String::from("_")],
+        };
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let (file, line_number, column_number) = self.position(here);
+        Err(TypeError::NonExhaustive {
+            file,
+            line_number,
+            column_number,
+            missing,
+        })
+    }
+
+    fn type_of(&mut self, expr: &ast::Expression) -> Result<ast::Type, TypeError> {
+        match expr {
+            ast::Expression::Number { .. } => Ok(ast::Type::I64),
+            ast::Expression::Float { .. } => Ok(ast::Type::F64),
+            ast::Expression::Char { .. } => Ok(ast::Type::Char),
+            ast::Expression::Str { .. } => Ok(ast::Type::Str),
+            ast::Expression::Variable { name, here }
+                if !self.variables.contains_key(name)
+                    && !self.globals.contains_key(name)
+                    && self.functions.contains_key(name) =>
+            {
+                if runtime::builtins()
+                    .iter()
+                    .any(|b| b.name == name && b.symbol.is_none())
+                {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::NotAValue {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                    });
+                }
+                let info = &self.functions[name];
+                Ok(ast::Type::Function(
+                    info.params.clone().into(),
+                    Box::new(info.ret.clone()),
+                ))
+            }
+            ast::Expression::Variable { name, here } => self
+                .variables
+                .get(name)
+                .map(|v| &v.ty)
+                .or_else(|| self.globals.get(name).map(|g| &g.ty))
+                .cloned()
+                .ok_or_else(|| {
+                    let (file, line_number, column_number) = self.position(*here);
+                    TypeError::UndefinedVariable {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                    }
+                }),
+            ast::Expression::Binary { left, op, right } => {
+                let left = self.type_of(left)?;
+                let right = self.type_of(right)?;
+                match (&left, op, &right) {
+                    (
+                        ast::Type::Pointer(_),
+                        lexer::Token::Plus { .. } | lexer::Token::Minus { .. },
+                        ast::Type::I64,
+                    ) if !is_dyn_pointer(&left) => return Ok(left),
+                    (ast::Type::Pointer(_), lexer::Token::Minus { .. }, ast::Type::Pointer(_))
+                        if left == right && !is_dyn_pointer(&left) =>
+                    {
+                        return Ok(ast::Type::I64);
+                    }
+                    _ => {}
+                }
+                if left != right {
+                    return Err(self.mismatch(op.here(), left, right));
+                }
+                if !is_numeric(&left) {
+                    let (file, line_number, column_number) = self.position(op.here());
+                    return Err(TypeError::InvalidOperands {
+                        file,
+                        line_number,
+                        column_number,
+                        op: op.clone(),
+                        ty: left,
+                    });
+                }
+                Ok(left)
+            }
+            ast::Expression::Cast { value, to, here } => {
+                let from = self.type_of(value)?;
+                self.check_type(to, *here)?;
+                let valid = match (&from, to) {
+                    // `monomorph` made sure the type implements the trait
+                    (from, to) if is_dyn_pointer(from) => from == to,
+                    (from, to) if is_dyn_pointer(to) => matches!(from, ast::Type::Pointer(_)),
+                    (ast::Type::Pointer(_), ast::Type::Pointer(_))
+                    | (ast::Type::Pointer(_), ast::Type::I64)
+                    | (ast::Type::I64, ast::Type::Pointer(_)) => true,
+                    // Slices convert to a pointer to their first element, strings to their
+                    // bytes
+                    (ast::Type::Slice(elem), ast::Type::Pointer(to)) => elem == to,
+                    (ast::Type::Str, ast::Type::Pointer(to)) => **to == ast::Type::U8,
+                    (ast::Type::Str, ast::Type::Slice(to))
+                    | (ast::Type::Slice(to), ast::Type::Str) => **to == ast::Type::U8,
+                    // Like Rust, only bytes are always valid characters
+                    (ast::Type::Char, ast::Type::I32 | ast::Type::I64 | ast::Type::U8)
+                    | (ast::Type::U8 | ast::Type::Char, ast::Type::Char) => true,
+                    (from, to) => is_numeric(from) && is_numeric(to),
+                };
+                if !valid {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::InvalidCast {
+                        file,
+                        line_number,
+                        column_number,
+                        from,
+                        to: to.clone(),
+                    });
+                }
+                Ok(to.clone())
+            }
+            ast::Expression::Array { elements, here } => {
+                let Some((first, rest)) = elements.split_first() else {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::EmptyArray {
+                        file,
+                        line_number,
+                        column_number,
+                    });
+                };
+                let elem = self.type_of(first)?;
+                for e in rest {
+                    let got = self.type_of(e)?;
+                    if got != elem {
+                        return Err(self.mismatch(e.here(), elem, got));
+                    }
+                }
+                Ok(ast::Type::Array(Box::new(elem), elements.len() as u64))
+            }
+            ast::Expression::Tuple { elements, .. } => Ok(ast::Type::Tuple(
+                elements
+                    .iter()
+                    .map(|e| self.type_of(e))
+                    .collect::<Result<_, _>>()?,
+            )),
+            ast::Expression::Index { array, index, here } => {
+                let (elem, len) = match self.type_of(array)? {
+                    ast::Type::Array(elem, len) => (*elem, Some(len)),
+                    ast::Type::Pointer(elem) if *elem != ast::Type::Unit => (*elem, None),
+                    ast::Type::Slice(elem) => (*elem, None),
+                    ast::Type::Str => (ast::Type::U8, None),
+                    got => {
+                        let (file, line_number, column_number) = self.position(*here);
+                        return Err(TypeError::NotIndexable {
+                            file,
+                            line_number,
+                            column_number,
+                            got,
+                        });
+                    }
+                };
+                let index_type = self.type_of(index)?;
+                if index_type != ast::Type::I64 {
+                    return Err(self.mismatch(index.here(), ast::Type::I64, index_type));
+                }
+                if let ast::Expression::Number { value, here, .. } = **index
+                    && let Some(len) = len
+                    && value >= len
+                {
+                    let (file, line_number, column_number) = self.position(here);
+                    return Err(TypeError::IndexOutOfBounds {
+                        file,
+                        line_number,
+                        column_number,
+                        index: value,
+                        len,
+                    });
+                }
+                Ok(elem)
+            }
+            ast::Expression::Slice {
+                value,
+                start,
+                end,
+                here,
+                ..
+            } => {
+                let ty = match self.type_of(value)? {
+                    ast::Type::Array(elem, _) => {
+                        self.check_pointable(value, *here)?;
+                        ast::Type::Slice(elem)
+                    }
+                    ast::Type::Slice(elem) => ast::Type::Slice(elem),
+                    ast::Type::Pointer(elem)
+                        if !matches!(*elem, ast::Type::Unit | ast::Type::Dyn(_)) =>
+                    {
+                        if end.is_none() {
+                            let (file, line_number, column_number) = self.position(*here);
+                            return Err(TypeError::UnboundedSlice {
+                                file,
+                                line_number,
+                                column_number,
+                            });
+                        }
+                        ast::Type::Slice(elem)
+                    }
+                    ast::Type::Str => ast::Type::Str,
+                    got => {
+                        let (file, line_number, column_number) = self.position(*here);
+                        return Err(TypeError::NotSliceable {
+                            file,
+                            line_number,
+                            column_number,
+                            got,
+                        });
+                    }
+                };
+                for bound in start.iter().chain(end) {
+                    self.check_value(&ast::Type::I64, bound)?;
+                }
+                Ok(ty)
+            }
+            ast::Expression::StructLiteral { name, fields, here } => {
+                let ty = ast::Type::Named(name.to_string());
+                let info = self.lookup_type(name, true, *here)?;
+                let declared = info.here;
+                let TypeDecl::Struct(decls) = &info.decl else {
+                    unreachable!()
+                };
+                let decls = decls.clone();
+                for (i, init) in fields.iter().enumerate() {
+                    if fields[..i].iter().any(|f| f.name == init.name) {
+                        let (file, line_number, column_number) = self.position(init.here);
+                        return Err(TypeError::DuplicateField {
+                            file,
+                            line_number,
+                            column_number,
+                            field: init.name.to_string(),
+                        });
+                    }
+                    let Some(decl) = decls.iter().find(|f| f.name == init.name) else {
+                        let (file, line_number, column_number) = self.position(init.here);
+                        return Err(TypeError::UnknownField {
+                            file,
+                            line_number,
+                            column_number,
+                            ty,
+                            field: init.name.to_string(),
+                            declared: Box::new(self.src.locate(declared)),
+                        });
+                    };
+                    self.check_value(&decl.ty, &init.value)?;
+                }
+                let missing: Vec<_> = decls
+                    .iter()
+                    .filter(|decl| !fields.iter().any(|f| f.name == decl.name))
+                    .map(|decl| decl.name.to_string())
+                    .collect();
+                if !missing.is_empty() {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::MissingFields {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                        fields: missing,
+                        declared: Box::new(self.src.locate(declared)),
+                    });
+                }
+                Ok(ty)
+            }
+            ast::Expression::Field { value, field, here } => {
+                // Fields can be accessed through a pointer, like `->` in C
+                let ty = match self.type_of(value)? {
+                    ast::Type::Pointer(inner)
+                        if matches!(
+                            *inner,
+                            ast::Type::Named(_)
+                                | ast::Type::Tuple(_)
+                                | ast::Type::Array(..)
+                                | ast::Type::Slice(_)
+                                | ast::Type::Str
+                        ) =>
+                    {
+                        *inner
+                    }
+                    ty => ty,
+                };
+                if field == "len"
+                    && matches!(
+                        ty,
+                        ast::Type::Array(..) | ast::Type::Slice(_) | ast::Type::Str
+                    )
+                {
+                    return Ok(ast::Type::I64);
+                }
+                let (file, line_number, column_number) = self.position(*here);
+                if let ast::Type::Tuple(elems) = &ty
+                    && let Ok(index) = field.parse::<usize>()
+                {
+                    return elems
+                        .get(index)
+                        .cloned()
+                        .ok_or(TypeError::IndexOutOfBounds {
+                            file,
+                            line_number,
+                            column_number,
+                            index: index as u64,
+                            len: elems.len() as u64,
+                        });
+                }
+                let fields = match &ty {
+                    ast::Type::Named(name) => match &self.types[name].decl {
+                        TypeDecl::Struct(fields) => Some((fields, self.types[name].here)),
+                        TypeDecl::Enum(_) => None,
+                    },
+                    _ => None,
+                };
+                let Some((fields, declared)) = fields else {
+                    return Err(TypeError::NoFields {
+                        file,
+                        line_number,
+                        column_number,
+                        got: ty,
+                    });
+                };
+                match fields.iter().find(|f| f.name == *field) {
+                    Some(f) => Ok(f.ty.clone()),
+                    None => Err(TypeError::UnknownField {
+                        file,
+                        line_number,
+                        column_number,
+                        declared: Box::new(self.src.locate(declared)),
+                        ty,
+                        field: field.to_string(),
+                    }),
+                }
+            }
+            ast::Expression::Variant {
+                ty,
+                variant,
+                args,
+                here,
+            } => {
+                let decl = self.lookup_variant(ty, variant, *here)?;
+                self.arg_count(*here, decl.payload.len(), args.len())?;
+                for (arg, expected) in args.iter().zip(decl.payload) {
+                    self.check_value(&expected, arg)?;
+                }
+                Ok(ast::Type::Named(ty.to_string()))
+            }
+            // A `None` is returned as the function's own `None`, an `Err` along with its error
+            ast::Expression::Try { value, here } => {
+                let ty = self.type_of(value)?;
+                let Some((ok, err)) = ty.try_variants() else {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::NotTryable {
+                        file,
+                        line_number,
+                        column_number,
+                        ty,
+                    });
+                };
+                self.check_not_deferred(*here)?;
+                let failure = self.lookup_variant(&ty.to_string(), err, *here)?;
+                if self.ret.try_variants() != Some((ok, err))
+                    || self
+                        .lookup_variant(&self.ret.to_string(), err, *here)?
+                        .payload
+                        != failure.payload
+                {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::CannotPropagate {
+                        file,
+                        line_number,
+                        column_number,
+                        ty,
+                        ret: self.ret.clone(),
+                    });
+                }
+                let success = self.lookup_variant(&ty.to_string(), ok, *here)?;
+                Ok(success.payload[0].clone())
+            }
+            ast::Expression::Match { value, arms, here } => {
+                let ty = self.type_of(value)?;
+                let mut result: Option<ast::Type> = None;
+                for arm in arms {
+                    let bindings = self.check_pattern(&arm.pattern, &ty)?;
+                    let got =
+                        self.with_bindings(bindings, |checker| checker.type_of(&arm.value))?;
+                    match &result {
+                        Some(expected) if *expected != got => {
+                            return Err(self.mismatch(arm.value.here(), expected.clone(), got));
+                        }
+                        _ => result = Some(got),
+                    }
+                }
+                self.check_exhaustive(&ty, arms, *here)?;
+                result.ok_or_else(|| {
+                    let (file, line_number, column_number) = self.position(*here);
+                    TypeError::NonExhaustive {
+                        file,
+                        line_number,
+                        column_number,
+                        missing: vec![String::from("_")],
+                    }
+                })
+            }
+            ast::Expression::Null { .. } => Ok(ast::Type::Pointer(Box::new(ast::Type::Unit))),
+            ast::Expression::AddressOf { value, here } => {
+                if !is_place(value) || self.is_length(value)? {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::NotAddressable {
+                        file,
+                        line_number,
+                        column_number,
+                    });
+                }
+                self.check_pointable(value, *here)?;
+                Ok(ast::Type::Pointer(Box::new(self.type_of(value)?)))
+            }
+            ast::Expression::Deref { value, here } => match self.type_of(value)? {
+                ast::Type::Pointer(inner)
+                    if !matches!(*inner, ast::Type::Unit | ast::Type::Dyn(_)) =>
+                {
+                    Ok(*inner)
+                }
+                got => {
+                    let (file, line_number, column_number) = self.position(*here);
+                    Err(TypeError::NotAPointer {
+                        file,
+                        line_number,
+                        column_number,
+                        got,
+                    })
+                }
+            },
+            // Variables holding function values shadow functions
+            ast::Expression::Call { name, args, here } if self.variables.contains_key(name) => {
+                let ast::Type::Function(params, ret) = self.variables[name].ty.clone() else {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::NotCallable {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                        ty: self.variables[name].ty.clone(),
+                    });
+                };
+                self.arg_count(*here, params.len(), args.len())?;
+                for (arg, expected) in args.iter().zip(&params) {
+                    self.check_value(expected, arg)?;
+                }
+                Ok(*ret)
+            }
+            ast::Expression::Closure {
+                params,
+                ret,
+                body,
+                here,
+            } => {
+                // `infer` annotated every parameter and the return type
+                let params: Vec<_> = params
+                    .iter()
+                    .map(|p| {
+                        let ty = p.ty.clone().unwrap();
+                        self.check_type(&ty, p.here)?;
+                        Ok((p.name.to_string(), ty))
+                    })
+                    .collect::<Result<_, _>>()?;
+                let ret = ret.clone().unwrap();
+                self.check_type(&ret, *here)?;
+                // Captured variables are copies, assigning to them would go unnoticed outside
+                let variables =
+                    self.variables
+                        .iter()
+                        .map(|(name, v)| {
+                            let captured = VariableInfo {
+                                captured: Some(*here),
+                                ..v.clone()
+                            };
+                            (name.to_string(), captured)
+                        })
+                        .chain(params.iter().map(|(name, ty)| {
+                            (name.to_string(), VariableInfo::mutable(ty.clone()))
+                        }))
+                        .collect();
+                let outer_variables = std::mem::replace(&mut self.variables, variables);
+                let outer_ret = std::mem::replace(&mut self.ret, ret.clone());
+                let outer_deferred = self.deferred.take();
+                let outer_loops = std::mem::take(&mut self.loops);
+                let result = body.iter().try_for_each(|st| self.check_statement(st));
+                self.variables = outer_variables;
+                self.ret = outer_ret;
+                self.deferred = outer_deferred;
+                self.loops = outer_loops;
+                result?;
+                if ret != ast::Type::Unit && !body.iter().any(diverges) {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::MissingReturn {
+                        file,
+                        line_number,
+                        column_number,
+                        name: String::from("closure"),
+                        ret,
+                    });
+                }
+                let params = params.into_iter().map(|(_, ty)| ty).collect();
+                Ok(ast::Type::Function(params, Box::new(ret)))
+            }
+            ast::Expression::Call { name, args, here } => {
+                let Some(info) = self.functions.get(name) else {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::UndefinedFunction {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                    });
+                };
+                let (params, ret) = (info.params.clone(), info.ret.clone());
+                self.arg_count(*here, params.len(), args.len())?;
+                for (arg, expected) in args.iter().zip(&params) {
+                    self.check_value(expected, arg)?;
+                }
+                Ok(ret)
+            }
+            // Only calls through `dyn` are left, `monomorph` turned the rest into `Call`s
+            ast::Expression::MethodCall {
+                value,
+                method,
+                args,
+                here,
+            } => {
+                let ty = self.type_of(value)?;
+                let decl = match &ty {
+                    ast::Type::Pointer(inner) => match &**inner {
+                        ast::Type::Dyn(trait_name) => self.traits[trait_name]
+                            .iter()
+                            .find(|m| m.name == *method)
+                            .map(|m| (trait_name.to_string(), m.clone())),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let Some((trait_name, decl)) = decl else {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::NoMethod {
+                        file,
+                        line_number,
+                        column_number,
+                        ty,
+                        method: method.to_string(),
+                    });
+                };
+                // The vtable can't know the type behind the pointer anywhere else
+                if decl.params[1..].iter().any(|p| mentions_self(&p.ty)) || mentions_self(&decl.ret)
+                {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::NotDynCallable {
+                        file,
+                        line_number,
+                        column_number,
+                        trait_name,
+                        method: method.to_string(),
+                    });
+                }
+                self.arg_count(*here, decl.params.len() - 1, args.len())?;
+                for (arg, p) in args.iter().zip(&decl.params[1..]) {
+                    self.check_value(&p.ty, arg)?;
+                }
+                Ok(decl.ret)
+            }
+        }
+    }
+
+    /// Checks that `value` can be stored in a place of type `expected`. Like `void *` in C,
+    /// `*()` converts to and from any other pointer
+    fn check_value(
+        &mut self,
+        expected: &ast::Type,
+        value: &ast::Expression,
+    ) -> Result<(), TypeError> {
+        let got = self.type_of(value)?;
+        let untyped = ast::Type::Pointer(Box::new(ast::Type::Unit));
+        let converts = matches!(
+            (expected, &got),
+            (ast::Type::Pointer(_), ast::Type::Pointer(_))
+        ) && (*expected == untyped || got == untyped)
+            && !is_dyn_pointer(expected)
+            && !is_dyn_pointer(&got);
+        if got != *expected && !converts {
+            return Err(self.mismatch(value.here(), expected.clone(), got));
+        }
+        Ok(())
+    }
+
+    /// Finds the variable whose storage assigning to `place` writes, if any; writes through a
+    /// pointer land elsewhere
+    /// Rejects pointing into `place`, which both `&` and slicing an array do, when it's an
+    /// immutable binding or a constant. Pointers and slices can always be written through
+    fn check_pointable(&mut self, place: &ast::Expression, here: usize) -> Result<(), TypeError> {
+        if let Some(name) = self.place_variable(place)?
+            && let Some(variable) = self.variables.get(name)
+            && let Some(declared) = variable.immutable
+        {
+            let (file, line_number, column_number) = self.position(here);
+            let name = name.to_string();
+            let declared = Box::new(self.src.locate(declared));
+            // `monomorph` takes the address of receivers at the value itself
+            return Err(if here == place.here() {
+                TypeError::ReceiverImmutable {
+                    file,
+                    line_number,
+                    column_number,
+                    name,
+                    declared,
+                }
+            } else {
+                TypeError::PointerToImmutable {
+                    file,
+                    line_number,
+                    column_number,
+                    name,
+                    declared,
+                }
+            });
+        }
+        if let Some(name) = self.place_variable(place)?
+            && !self.variables.contains_key(name)
+            && let Some(global) = self.globals.get(name)
+            && global.is_const
+        {
+            let (file, line_number, column_number) = self.position(here);
+            return Err(TypeError::PointerToConst {
+                file,
+                line_number,
+                column_number,
+                name: name.to_string(),
+                declared: Box::new(self.src.locate(global.here)),
+            });
+        }
+        Ok(())
+    }
+
+    fn place_variable<'e>(
+        &mut self,
+        place: &'e ast::Expression,
+    ) -> Result<Option<&'e str>, TypeError> {
+        match place {
+            ast::Expression::Variable { name, .. } => Ok(Some(name)),
+            ast::Expression::Index { array, .. } | ast::Expression::Field { value: array, .. } => {
+                if matches!(
+                    self.type_of(array)?,
+                    ast::Type::Pointer(_) | ast::Type::Slice(_) | ast::Type::Str
+                ) {
+                    Ok(None)
+                } else {
+                    self.place_variable(array)
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether `expr` is the length of an array, slice or string, which is only ever read
+    fn is_length(&mut self, expr: &ast::Expression) -> Result<bool, TypeError> {
+        let ast::Expression::Field { value, field, .. } = expr else {
+            return Ok(false);
+        };
+        let ty = match self.type_of(value)? {
+            ast::Type::Pointer(inner) => *inner,
+            ty => ty,
+        };
+        Ok(field == "len"
+            && matches!(
+                ty,
+                ast::Type::Array(..) | ast::Type::Slice(_) | ast::Type::Str
+            ))
+    }
+
+    /// Runs `f` with `bindings` in scope, restoring whatever they shadowed afterwards
+    fn with_bindings<T>(
+        &mut self,
+        bindings: Vec<(String, ast::Type)>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let shadowed: Vec<_> = bindings
+            .into_iter()
+            .map(|(name, ty)| {
+                let old = self
+                    .variables
+                    .insert(name.to_string(), VariableInfo::mutable(ty));
+                (name, old)
+            })
+            .collect();
+        let result = f(self);
+        for (name, old) in shadowed.into_iter().rev() {
+            match old {
+                Some(info) => self.variables.insert(name, info),
+                None => self.variables.remove(&name),
+            };
+        }
+        result
+    }
+
+    /// Fails if the code at `here` would return from inside a `defer`
+    fn check_not_deferred(&self, here: usize) -> Result<(), TypeError> {
+        match self.deferred {
+            Some(defer) => {
+                let (file, line_number, column_number) = self.position(here);
+                Err(TypeError::ReturnInDefer {
+                    file,
+                    line_number,
+                    column_number,
+                    defer: Box::new(self.position(defer)),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn check_statement(&mut self, st: &ast::Statement) -> Result<(), TypeError> {
+        match st {
+            ast::Statement::Return(value) => {
+                self.check_not_deferred(value.here())?;
+                let expected = self.ret.clone();
+                self.check_value(&expected, value)?;
+            }
+            ast::Statement::DefineVar {
+                name,
+                mutable,
+                ty,
+                value,
+                here,
+            } => {
+                let got = match ty {
+                    Some(ty) => {
+                        self.check_type(ty, value.here())?;
+                        self.check_value(ty, value)?;
+                        ty.clone()
+                    }
+                    None => self.type_of(value)?,
+                };
+                self.variables.insert(
+                    name.to_string(),
+                    VariableInfo {
+                        ty: got,
+                        immutable: (!mutable).then_some(*here),
+                        captured: None,
+                    },
+                );
+            }
+            ast::Statement::DefineTuple { names, ty, value } => {
+                let got = match ty {
+                    Some(ty) => {
+                        self.check_type(ty, value.here())?;
+                        self.check_value(ty, value)?;
+                        ty.clone()
+                    }
+                    None => self.type_of(value)?,
+                };
+                let elems = match &got {
+                    ast::Type::Tuple(elems) if elems.len() == names.len() => elems.clone(),
+                    _ => {
+                        let (file, line_number, column_number) = self.position(value.here());
+                        return Err(TypeError::CannotDestructure {
+                            file,
+                            line_number,
+                            column_number,
+                            got,
+                            names: names.len(),
+                        });
+                    }
+                };
+                for (binding, ty) in names.iter().zip(elems) {
+                    self.variables.insert(
+                        binding.name.to_string(),
+                        VariableInfo {
+                            ty,
+                            immutable: (!binding.mutable).then_some(binding.here),
+                            captured: None,
+                        },
+                    );
+                }
+            }
+            ast::Statement::Assign { target, value } => {
+                if !is_place(target) || self.is_length(target)? {
+                    let (file, line_number, column_number) = self.position(target.here());
+                    return Err(TypeError::InvalidAssignTarget {
+                        file,
+                        line_number,
+                        column_number,
+                    });
+                }
+                let root = self.place_variable(target)?;
+                if let Some(name) = root
+                    && let Some(variable) = self.variables.get(name)
+                    && let Some(closure) = variable.captured
+                {
+                    let (file, line_number, column_number) = self.position(target.here());
+                    return Err(TypeError::AssignToCaptured {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                        closure: Box::new(self.src.locate(closure)),
+                    });
+                }
+                if let Some(name) = root
+                    && let Some(variable) = self.variables.get(name)
+                    && let Some(declared) = variable.immutable
+                {
+                    let (file, line_number, column_number) = self.position(target.here());
+                    return Err(TypeError::AssignToImmutable {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                        declared: Box::new(self.src.locate(declared)),
+                    });
+                }
+                if let Some(name) = root
+                    && !self.variables.contains_key(name)
+                    && let Some(global) = self.globals.get(name)
+                    && global.is_const
+                {
+                    let (file, line_number, column_number) = self.position(target.here());
+                    return Err(TypeError::AssignToConst {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                        declared: Box::new(self.src.locate(global.here)),
+                    });
+                }
+                let expected = self.type_of(target)?;
+                self.check_value(&expected, value)?;
+            }
+            ast::Statement::Expression(value) => {
+                self.type_of(value)?;
+            }
+            ast::Statement::Function {
+                name,
+                params,
+                ret,
+                body,
+                here,
+                ..
+            } => {
+                let variables = params
+                    .iter()
+                    .map(|p| (p.name.to_string(), VariableInfo::mutable(p.ty.clone())))
+                    .collect();
+                let outer_variables = std::mem::replace(&mut self.variables, variables);
+                let outer_ret = std::mem::replace(&mut self.ret, ret.clone());
+                let outer_deferred = self.deferred.take();
+                let outer_loops = std::mem::take(&mut self.loops);
+                let result = body.iter().try_for_each(|st| self.check_statement(st));
+                self.variables = outer_variables;
+                self.ret = outer_ret;
+                self.deferred = outer_deferred;
+                self.loops = outer_loops;
+                result?;
+                if *ret != ast::Type::Unit && !body.iter().any(diverges) {
+                    let (file, line_number, column_number) = self.position(*here);
+                    return Err(TypeError::MissingReturn {
+                        file,
+                        line_number,
+                        column_number,
+                        name: name.to_string(),
+                        ret: ret.clone(),
+                    });
+                }
+            }
+            ast::Statement::For {
+                var,
+                start,
+                end,
+                body,
+                here,
+                ..
+            } => {
+                let ty = self.type_of(start)?;
+                if !matches!(ty, ast::Type::I32 | ast::Type::I64 | ast::Type::U8) {
+                    return Err(self.mismatch(start.here(), ast::Type::I64, ty));
+                }
+                self.check_value(&ty, end)?;
+                // Neither the loop variable nor anything defined in the body outlives the loop.
+                // The loop owns the variable, so the body can't assign to it
+                let outer = self.variables.clone();
+                self.variables.insert(
+                    var.to_string(),
+                    VariableInfo {
+                        ty,
+                        immutable: Some(*here),
+                        captured: None,
+                    },
+                );
+                self.loops += 1;
+                let result = body.iter().try_for_each(|st| self.check_statement(st));
+                self.loops -= 1;
+                self.variables = outer;
+                result?;
+            }
+            ast::Statement::Defer { stmt, here } => {
+                // The statement runs after the rest of the block, so nothing it defines is
+                // visible there
+                let outer = self.variables.clone();
+                let outer_deferred = self.deferred.replace(*here);
+                let outer_loops = std::mem::take(&mut self.loops);
+                let result = self.check_statement(stmt);
+                self.variables = outer;
+                self.deferred = outer_deferred;
+                self.loops = outer_loops;
+                result?;
+            }
+            ast::Statement::Break { here } | ast::Statement::Continue { here }
+                if self.loops == 0 =>
+            {
+                let (file, line_number, column_number) = self.position(*here);
+                let keyword = match st {
+                    ast::Statement::Break { .. } => "break",
+                    _ => "continue",
+                };
+                return Err(TypeError::OutsideLoop {
+                    file,
+                    line_number,
+                    column_number,
+                    keyword: keyword.to_string(),
+                });
+            }
+            ast::Statement::Break { .. } | ast::Statement::Continue { .. } => {}
+            // Handled up front by the `declare_*` passes
+            ast::Statement::Struct { .. }
+            | ast::Statement::Enum { .. }
+            | ast::Statement::Extern { .. }
+            | ast::Statement::Const { .. }
+            | ast::Statement::Static { .. } => {}
+            ast::Statement::Trait { .. } => {}
+            // Replaced by the module's items when modules are loaded
+            ast::Statement::Mod { .. } => {}
+            // Lowered into functions by `monomorph`
+            ast::Statement::Impl { .. } => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+fn is_numeric(ty: &ast::Type) -> bool {
+    matches!(
+        ty,
+        ast::Type::I32 | ast::Type::I64 | ast::Type::U8 | ast::Type::F32 | ast::Type::F64
+    )
+}
+
+fn is_dyn_pointer(ty: &ast::Type) -> bool {
+    matches!(ty, ast::Type::Pointer(inner) if matches!(**inner, ast::Type::Dyn(_)))
+}
+
+/// Whether `ty`, from a trait's method, refers to the implementing type
+fn mentions_self(ty: &ast::Type) -> bool {
+    let subst = std::collections::HashMap::from([(String::from("Self"), ast::Type::Unit)]);
+    ty.substitute(&subst) != *ty
+}
+
+/// Whether the expression names a memory location that can be assigned to
+fn is_place(expr: &ast::Expression) -> bool {
+    match expr {
+        ast::Expression::Variable { .. } | ast::Expression::Deref { .. } => true,
+        ast::Expression::Index { array, .. } | ast::Expression::Field { value: array, .. } => {
+            is_place(array)
+        }
+        _ => false,
+    }
+}
+
+/// Whether the rest of the block after `st` never runs, a `return` or a call to `panic`
+fn diverges(st: &ast::Statement) -> bool {
+    match st {
+        ast::Statement::Return(_) => true,
+        ast::Statement::Expression(ast::Expression::Call { name, .. }) => name == "panic",
+        _ => false,
+    }
+}
+
+/// `fn main(args: []str) -> i32`, or `fn main() -> i32` for programs ignoring their arguments
+fn is_main_signature(params: &[ast::Param], ret: &ast::Type) -> bool {
+    let args = ast::Type::Slice(Box::new(ast::Type::Str));
+    *ret == ast::Type::I32 && (params.is_empty() || (params.len() == 1 && params[0].ty == args))
+}
+
+/// Where `st` is, if it is code that would run as part of the implicit `main`
+fn top_level_code(st: &ast::Statement) -> Option<usize> {
+    match st {
+        ast::Statement::Return(e) | ast::Statement::Expression(e) => Some(e.here()),
+        ast::Statement::Assign { target, .. } => Some(target.here()),
+        ast::Statement::DefineVar { here, .. }
+        | ast::Statement::For { here, .. }
+        | ast::Statement::Defer { here, .. }
+        | ast::Statement::Break { here }
+        | ast::Statement::Continue { here } => Some(*here),
+        ast::Statement::DefineTuple { names, .. } => names.first().map(|b| b.here),
+        _ => None,
+    }
+}
+
+pub fn check(
+    program: &[ast::Statement],
+    src: &source::SourceMap,
+    instances: &monomorph::Instances,
+) -> Result<(), TypeError> {
+    let mut checker = Checker {
+        src,
+        variables: std::collections::HashMap::new(),
+        types: std::collections::HashMap::new(),
+        functions: std::collections::HashMap::new(),
+        globals: std::collections::HashMap::new(),
+        traits: program
+            .iter()
+            .filter_map(|st| match st {
+                ast::Statement::Trait { name, methods, .. } => {
+                    Some((name.to_string(), methods.clone()))
+                }
+                _ => None,
+            })
+            .collect(),
+        // `main` returns the process exit code
+        ret: ast::Type::I64,
+        deferred: None,
+        loops: 0,
+    };
+    for builtin in runtime::builtins() {
+        checker.functions.insert(
+            builtin.name.to_string(),
+            FunctionInfo {
+                params: builtin.params,
+                ret: builtin.ret,
+                // Builtins can't be redefined, so this never shows up in an error
+                here: 0,
+            },
+        );
+    }
+    checker.declare_types(program)?;
+    checker.declare_functions(program)?;
+    checker.declare_globals(program)?;
+    if checker.functions.contains_key("main")
+        && let Some(here) = program.iter().find_map(top_level_code)
+    {
+        let (file, line_number, column_number) = src.locate(here);
+        return Err(TypeError::CodeOutsideMain {
+            file,
+            line_number,
+            column_number,
+        });
+    }
+    for st in program {
+        checker.check_statement(st).map_err(|error| match st {
+            ast::Statement::Function { name, .. } if instances.contains_key(name) => {
+                TypeError::InInstance {
+                    error: Box::new(error),
+                    chain: monomorph::chain(instances, name)
+                        .into_iter()
+                        .map(|(name, here)| {
+                            let (file, line_number, column_number) = src.locate(here);
+                            (name, file, line_number, column_number)
+                        })
+                        .collect(),
+                }
+            }
+            _ => error,
+        })?;
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mismatch {
+                file,
+                line_number,
+                column_number,
+                expected,
+                got,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Mismatched types. Expected: {expected}, got: {got}").as_str(),
+            ),
+            Self::UndefinedVariable {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Undefined variable: {name}").as_str(),
+            ),
+            Self::InvalidCast {
+                file,
+                line_number,
+                column_number,
+                from,
+                to,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot convert {from} to {to}").as_str(),
+            ),
+            Self::InvalidOperands {
+                file,
+                line_number,
+                column_number,
+                op,
+                ty,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot apply {op} to values of type {ty}").as_str(),
+            ),
+            Self::NotIndexable {
+                file,
+                line_number,
+                column_number,
+                got,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot index into a value of type {got}").as_str(),
+            ),
+            Self::NotSliceable {
+                file,
+                line_number,
+                column_number,
+                got,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot slice a value of type {got}").as_str(),
+            ),
+            Self::UnboundedSlice {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Slicing a pointer needs an end, pointers carry no length",
+            ),
+            Self::IndexOutOfBounds {
+                file,
+                line_number,
+                column_number,
+                index,
+                len,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Index out of bounds: the len is {len} but the index is {index}").as_str(),
+            ),
+            Self::EmptyArray {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Cannot infer the element type of an empty array",
+            ),
+            Self::InvalidAssignTarget {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Invalid left-hand side of assignment",
+            ),
+            Self::UnknownType {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Unknown type: {name}").as_str(),
+            ),
+            Self::NoFields {
+                file,
+                line_number,
+                column_number,
+                got,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Type {got} has no fields").as_str(),
+            ),
+            Self::UnknownField {
+                file,
+                line_number,
+                column_number,
+                ty,
+                field,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("{ty} has no field named {field}").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{ty} is declared here").as_str(),
+                )
+            }
+            Self::MissingFields {
+                file,
+                line_number,
+                column_number,
+                name,
+                fields,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Missing fields in {name} literal: {}", fields.join(", ")).as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{name} is declared here").as_str(),
+                )
+            }
+            Self::DuplicateField {
+                file,
+                line_number,
+                column_number,
+                field,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Field {field} is specified more than once").as_str(),
+            ),
+            Self::Redefinition {
+                file,
+                line_number,
+                column_number,
+                name,
+                previous,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("{name} is defined multiple times").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    previous.0.as_deref(),
+                    (previous.1, previous.2),
+                    format!("{name} was previously defined here").as_str(),
+                )
+            }
+            Self::RecursiveType {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{name} contains itself and would have an infinite size").as_str(),
+            ),
+            Self::WrongKind {
+                file,
+                line_number,
+                column_number,
+                name,
+                expected,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{name} is not {expected}").as_str(),
+            ),
+            Self::UnknownVariant {
+                file,
+                line_number,
+                column_number,
+                ty,
+                variant,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("{ty} has no variant named {variant}").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{ty} is declared here").as_str(),
+                )
+            }
+            Self::WrongArgCount {
+                file,
+                line_number,
+                column_number,
+                expected,
+                got,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Expected {expected} values, got {got}").as_str(),
+            ),
+            Self::NonExhaustive {
+                file,
+                line_number,
+                column_number,
+                missing,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Non-exhaustive match, missing: {}", missing.join(", ")).as_str(),
+            ),
+            Self::UnknownTrait {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Unknown trait: {name}").as_str(),
+            ),
+            Self::BareDyn {
+                file,
+                line_number,
+                column_number,
+                ty,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{ty} can only be used behind a pointer, like *{ty}").as_str(),
+            ),
+            Self::ArrayTooLong {
+                file,
+                line_number,
+                column_number,
+                len,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Arrays hold at most {} elements, got {len}", u32::MAX).as_str(),
+            ),
+            Self::NoMethod {
+                file,
+                line_number,
+                column_number,
+                ty,
+                method,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{ty} has no method {method}").as_str(),
+            ),
+            Self::NotDynCallable {
+                file,
+                line_number,
+                column_number,
+                trait_name,
+                method,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!(
+                    "{method} can't be called through dyn {trait_name}, it uses Self other than as its receiver"
+                )
+                .as_str(),
+            ),
+            Self::UndefinedFunction {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Undefined function: {name}").as_str(),
+            ),
+            Self::NotAPointer {
+                file,
+                line_number,
+                column_number,
+                got,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot dereference a value of type {got}").as_str(),
+            ),
+            Self::NotAddressable {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Cannot take the address of a temporary value",
+            ),
+            Self::MissingReturn {
+                file,
+                line_number,
+                column_number,
+                name,
+                ret,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Function {name} must return a value of type {ret}").as_str(),
+            ),
+            Self::NotTryable {
+                file,
+                line_number,
+                column_number,
+                ty,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("The ? operator needs an Option or a Result, got {ty}").as_str(),
+            ),
+            Self::CannotPropagate {
+                file,
+                line_number,
+                column_number,
+                ty,
+                ret,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot use ? on {ty} in a function returning {ret}").as_str(),
+            ),
+            Self::ReturnInDefer {
+                file,
+                line_number,
+                column_number,
+                defer,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    "Cannot return from a deferred statement",
+                )?;
+                error::display_error(
+                    f,
+                    defer.0.as_deref(),
+                    (defer.1, defer.2),
+                    "deferred here",
+                )
+            }
+            Self::OutsideLoop {
+                file,
+                line_number,
+                column_number,
+                keyword,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot use {keyword} outside of a loop").as_str(),
+            ),
+            Self::ReservedName {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{name} is reserved and cannot be defined").as_str(),
+            ),
+            Self::InvalidMain {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "main must be declared as fn main(args: []str) -> i32 or fn main() -> i32",
+            ),
+            Self::CodeOutsideMain {
+                file,
+                line_number,
+                column_number,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                "Statements outside of fn main never run, move them into it",
+            ),
+            Self::AssignToConst {
+                file,
+                line_number,
+                column_number,
+                name,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Cannot assign to constant {name}").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{name} is declared here").as_str(),
+                )
+            }
+            Self::AssignToImmutable {
+                file,
+                line_number,
+                column_number,
+                name,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Cannot assign twice to immutable variable {name}").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{name} is declared here").as_str(),
+                )
+            }
+            Self::AssignToCaptured {
+                file,
+                line_number,
+                column_number,
+                name,
+                closure,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Cannot assign to {name}, the closure only has a copy of it").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    closure.0.as_deref(),
+                    (closure.1, closure.2),
+                    format!("{name} is captured here").as_str(),
+                )
+            }
+            Self::PointerToImmutable {
+                file,
+                line_number,
+                column_number,
+                name,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Cannot take a pointer to immutable variable {name}").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{name} is declared here").as_str(),
+                )
+            }
+            Self::PointerToConst {
+                file,
+                line_number,
+                column_number,
+                name,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Cannot take a pointer to constant {name}").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{name} is declared here").as_str(),
+                )
+            }
+            Self::ReceiverImmutable {
+                file,
+                line_number,
+                column_number,
+                name,
+                declared,
+            } => {
+                error::display_error(
+                    f,
+                    file.as_deref(),
+                    (*line_number, *column_number),
+                    format!("Cannot call a *Self method on immutable variable {name}").as_str(),
+                )?;
+                error::display_error(
+                    f,
+                    declared.0.as_deref(),
+                    (declared.1, declared.2),
+                    format!("{name} is declared here").as_str(),
+                )
+            }
+            Self::NotAValue {
+                file,
+                line_number,
+                column_number,
+                name,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{name} is expanded inline and can't be used as a value").as_str(),
+            ),
+            Self::NotCallable {
+                file,
+                line_number,
+                column_number,
+                name,
+                ty,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("{name} is a {ty}, not a function").as_str(),
+            ),
+            Self::CannotDestructure {
+                file,
+                line_number,
+                column_number,
+                got,
+                names,
+            } => error::display_error(
+                f,
+                file.as_deref(),
+                (*line_number, *column_number),
+                format!("Cannot destructure a value of type {got} into {names} variables").as_str(),
+            ),
+            Self::InInstance { error, chain } => {
+                write!(f, "{error}")?;
+                for (name, file, line_number, column_number) in chain {
+                    error::display_error(
+                        f,
+                        file.as_deref(),
+                        (*line_number, *column_number),
+                        format!("while instantiating {name} here").as_str(),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_str(s: &str) -> Result<(), TypeError> {
+        let src = source::Source::new(s);
+        let tokens = lexer::lex_file(src.clone()).unwrap();
+        let program = ast::parse(&tokens).unwrap();
+        check(&program, &src.into(), &monomorph::Instances::new())
+    }
+
+    #[test]
+    fn casts() {
+        assert_eq!(
+            check_str("var x: f64 = 1 as f64 * 2.5; return x as i64;"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn mixed_arithmetic() {
+        assert_eq!(
+            check_str("var x = 1.5;\nreturn 1 + x;"),
+            Err(TypeError::Mismatch {
+                file: None,
+                line_number: 2,
+                column_number: 10,
+                expected: ast::Type::I64,
+                got: ast::Type::F64,
+            })
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(
+            check_str("var a: [i64; 3] = [1, 2, 3]; a[2] = a[0] * a[1]; return a[1];"),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("var a = [1, 2, 3];\nreturn a[3];"),
+            Err(TypeError::IndexOutOfBounds {
+                file: None,
+                line_number: 2,
+                column_number: 10,
+                index: 3,
+                len: 3,
+            })
+        );
+        assert_eq!(
+            check_str("var a = [1, 2.5];"),
+            Err(TypeError::Mismatch {
+                file: None,
+                line_number: 1,
+                column_number: 13,
+                expected: ast::Type::I64,
+                got: ast::Type::F64,
+            })
+        );
+        assert_eq!(
+            check_str("let p = alloc(8) as *[u8; 4294967296];"),
+            Err(TypeError::ArrayTooLong {
+                file: None,
+                line_number: 1,
+                column_number: 18,
+                len: 4294967296,
+            })
+        );
+    }
+
+    #[test]
+    fn structs() {
+        assert_eq!(
+            check_str(
+                "struct P { x: i64, y: f64 }\nvar p = P { y: 1.5, x: 2 };\np.x = 3;\nreturn p.x;"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("struct P { x: i64, y: f64 }\nvar p = P { x: 2 };"),
+            Err(TypeError::MissingFields {
+                file: None,
+                line_number: 2,
+                column_number: 9,
+                name: String::from("P"),
+                fields: vec![String::from("y")],
+                declared: Box::new((None, 1, 8)),
+            })
+        );
+        assert_eq!(
+            check_str("struct P { x: i64 }\nvar p = P { x: 2 };\nreturn p.z;"),
+            Err(TypeError::UnknownField {
+                file: None,
+                line_number: 3,
+                column_number: 10,
+                ty: ast::Type::Named(String::from("P")),
+                field: String::from("z"),
+                declared: Box::new((None, 1, 8)),
+            })
+        );
+        assert_eq!(
+            check_str("struct A { b: B }\nstruct B { a: [A; 2] }"),
+            Err(TypeError::RecursiveType {
+                file: None,
+                line_number: 1,
+                column_number: 8,
+                name: String::from("A"),
+            })
+        );
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(
+            check_str(
+                "enum S { Idle, Run(i64, f64) }\nvar s = S::Run(1, 2.5);\nreturn match s { S::Run(n, _) => n, S::Idle => 0 };"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str(
+                "enum S { Idle, Run(i64) }\nvar s = S::Idle;\nreturn match s { S::Idle => 0 };"
+            ),
+            Err(TypeError::NonExhaustive {
+                file: None,
+                line_number: 3,
+                column_number: 8,
+                missing: vec![String::from("S::Run")],
+            })
+        );
+        assert_eq!(
+            check_str("enum S { Idle }\nvar s = S::Walk;"),
+            Err(TypeError::UnknownVariant {
+                file: None,
+                line_number: 2,
+                column_number: 12,
+                ty: String::from("S"),
+                variant: String::from("Walk"),
+                declared: Box::new((None, 1, 6)),
+            })
+        );
+        assert_eq!(
+            check_str("var x = 1;\nreturn match x { 0 => 1 };"),
+            Err(TypeError::NonExhaustive {
+                file: None,
+                line_number: 2,
+                column_number: 8,
+                missing: vec![String::from("_")],
+            })
+        );
+    }
+
+    #[test]
+    fn pointers() {
+        assert_eq!(
+            check_str(
+                "struct N { v: i64, next: *N }\nfn set(out: *i64, n: *N) { *out = n.v; }\nvar x = 0;\nvar n = N { v: 1, next: null };\nset(&x, &n);\nreturn x + (&x + 1 - &x);"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("var x = 1;\nreturn *x;"),
+            Err(TypeError::NotAPointer {
+                file: None,
+                line_number: 2,
+                column_number: 8,
+                got: ast::Type::I64,
+            })
+        );
+        assert_eq!(
+            check_str("var p = &1;"),
+            Err(TypeError::NotAddressable {
+                file: None,
+                line_number: 1,
+                column_number: 9,
+            })
+        );
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            check_str(
+                "extern fn abs(x: i32) -> i32;\nfn twice(x: i64) -> i64 { return x * 2; }\nreturn twice(abs(0 as i32) as i64);"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("fn f() -> i64 { var x = 1; }"),
+            Err(TypeError::MissingReturn {
+                file: None,
+                line_number: 1,
+                column_number: 4,
+                name: String::from("f"),
+                ret: ast::Type::I64,
+            })
+        );
+        assert_eq!(
+            check_str("fn f(x: i64) {}\nf(1.5);"),
+            Err(TypeError::Mismatch {
+                file: None,
+                line_number: 2,
+                column_number: 3,
+                expected: ast::Type::I64,
+                got: ast::Type::F64,
+            })
+        );
+        assert_eq!(
+            check_str("var x = 1;\nfn f() -> i64 { return x; }"),
+            Err(TypeError::UndefinedVariable {
+                file: None,
+                line_number: 2,
+                column_number: 24,
+                name: String::from("x"),
+            })
+        );
+    }
+
+    #[test]
+    fn panic_builtin() {
+        assert_eq!(
+            check_str("fn f(x: i64) -> i64 {\n    panic(\"unsupported\");\n}\nreturn f(1);"),
+            Ok(())
+        );
+        assert!(matches!(
+            check_str("panic(1);"),
+            Err(TypeError::Mismatch {
+                expected: ast::Type::Str,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn heap_builtins() {
+        assert_eq!(
+            check_str(
+                "var p: *i64 = alloc(16);\np = realloc(p, 32);\np[3] = 1;\nprint_i64(p[3]);\nfree(p);"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("fn free(p: *i64) {}"),
+            Err(TypeError::ReservedName {
+                file: None,
+                line_number: 1,
+                column_number: 4,
+                name: String::from("free"),
+            })
+        );
+    }
+
+    #[test]
+    fn main_function() {
+        assert_eq!(
+            check_str(
+                "fn main(args: []str) -> i32 {\n    print_str(args[0]);\n    print_str(env(\"HOME\"));\n    return (args.len + has_env(\"HOME\")) as i32;\n}"
+            ),
+            Ok(())
+        );
+        assert_eq!(check_str("fn main() -> i32 { return 0 as i32; }"), Ok(()));
+        assert_eq!(
+            check_str("fn main(args: []i64) -> i32 { return 0; }"),
+            Err(TypeError::InvalidMain {
+                file: None,
+                line_number: 1,
+                column_number: 4,
+            })
+        );
+        assert_eq!(
+            check_str("var x = 1;\nfn main() -> i32 { return 0 as i32; }"),
+            Err(TypeError::CodeOutsideMain {
+                file: None,
+                line_number: 1,
+                column_number: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn arithmetic_builtins() {
+        assert_eq!(
+            check_str(
+                "var sum = 0;\nvar overflowed = checked_add(9223372036854775807, 1, &sum);\nreturn wrapping_mul(sum, 2) + saturating_sub(overflowed, 1);"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("var x = wrapping_add(1, 2.5);"),
+            Err(TypeError::Mismatch {
+                file: None,
+                line_number: 1,
+                column_number: 25,
+                expected: ast::Type::I64,
+                got: ast::Type::F64,
+            })
+        );
+    }
+
+    #[test]
+    fn for_loops() {
+        assert_eq!(
+            check_str(
+                "var sum = 0;\nfor i in 0..=10 { var sq = i * i; sum = sum + sq; }\nreturn sum;"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("for i in 0..3 { var x = i; }\nreturn x;"),
+            Err(TypeError::UndefinedVariable {
+                file: None,
+                line_number: 2,
+                column_number: 8,
+                name: String::from("x"),
+            })
+        );
+        assert_eq!(
+            check_str("for i in 0.5..3 {}"),
+            Err(TypeError::Mismatch {
+                file: None,
+                line_number: 1,
+                column_number: 10,
+                expected: ast::Type::I64,
+                got: ast::Type::F64,
+            })
+        );
+        assert_eq!(
+            check_str(
+                "let s = \"abc\";\nvar sum = 0;\nfor i in 0 as u8..s[0] { sum = sum + i as i64; }\nfor c in 0 as u8..=255 as u8 {}\nreturn sum;"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("for i in 0..3 { i = 5; }"),
+            Err(TypeError::AssignToImmutable {
+                file: None,
+                line_number: 1,
+                column_number: 17,
+                name: String::from("i"),
+                declared: Box::new((None, 1, 5)),
+            })
+        );
+    }
+
+    #[test]
+    fn defer() {
+        assert_eq!(
+            check_str(
+                "fn f(n: i64) -> i64 {\n    defer print_i64(n);\n    for i in 0..n { defer print_i64(i); }\n    return n;\n}"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("fn f() -> i64 {\n    defer return 1;\n    return 2;\n}"),
+            Err(TypeError::ReturnInDefer {
+                file: None,
+                line_number: 2,
+                column_number: 18,
+                defer: Box::new((None, 2, 5)),
+            })
+        );
+        assert_eq!(
+            check_str("defer let x = 1;\nreturn x;"),
+            Err(TypeError::UndefinedVariable {
+                file: None,
+                line_number: 2,
+                column_number: 8,
+                name: String::from("x"),
+            })
+        );
+    }
+
+    #[test]
+    fn loop_control() {
+        assert_eq!(
+            check_str(
+                "for i in 0..3 {\n    defer print_i64(i);\n    continue;\n}\nfor i in 0..3 { break; }\ndefer for i in 0..3 { break; }"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("break;"),
+            Err(TypeError::OutsideLoop {
+                file: None,
+                line_number: 1,
+                column_number: 1,
+                keyword: String::from("break"),
+            })
+        );
+        assert_eq!(
+            check_str("for i in 0..3 {\n    defer continue;\n}"),
+            Err(TypeError::OutsideLoop {
+                file: None,
+                line_number: 2,
+                column_number: 11,
+                keyword: String::from("continue"),
+            })
+        );
+        assert_eq!(
+            check_str("for i in 0..3 {\n    let f = |x: i64| { break; };\n}"),
+            Err(TypeError::OutsideLoop {
+                file: None,
+                line_number: 2,
+                column_number: 24,
+                keyword: String::from("break"),
+            })
+        );
+    }
+
+    #[test]
+    fn globals() {
+        assert_eq!(
+            check_str("const N: i64 = 4;\nvar p = &N;\n*p = 3;"),
+            Err(TypeError::PointerToConst {
+                file: None,
+                line_number: 2,
+                column_number: 9,
+                name: String::from("N"),
+                declared: Box::new((None, 1, 7)),
+            })
+        );
+        assert_eq!(
+            check_str(
+                "const N: i64 = 4;\nstatic COUNT: i64 = 0;\nfn bump() { COUNT = COUNT + N; }\nbump();\nreturn COUNT;"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("const P: [i64; 2] = [1, 2];\nP[0] = 3;"),
+            Err(TypeError::AssignToConst {
+                file: None,
+                line_number: 2,
+                column_number: 2,
+                name: String::from("P"),
+                declared: Box::new((None, 1, 7)),
+            })
+        );
+    }
+
+    #[test]
+    fn immutable_bindings() {
+        assert_eq!(
+            check_str(
+                "let mut n = 1;\nn = n + 1;\nlet (a, mut b) = (n, 2);\nb = a;\nlet p = alloc(8) as *i64;\np[0] = b;\nreturn p[0];"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("let t = (1, 2);\nt.0 = 3;"),
+            Err(TypeError::AssignToImmutable {
+                file: None,
+                line_number: 2,
+                column_number: 3,
+                name: String::from("t"),
+                declared: Box::new((None, 1, 5)),
+            })
+        );
+        assert_eq!(
+            check_str("let y = 3;\nvar z = &y;\n*z = 9;"),
+            Err(TypeError::PointerToImmutable {
+                file: None,
+                line_number: 2,
+                column_number: 9,
+                name: String::from("y"),
+                declared: Box::new((None, 1, 5)),
+            })
+        );
+        assert_eq!(
+            check_str("let a = [1, 2, 3];\nlet s = a[0..3];\ns[0] = 9;"),
+            Err(TypeError::PointerToImmutable {
+                file: None,
+                line_number: 2,
+                column_number: 10,
+                name: String::from("a"),
+                declared: Box::new((None, 1, 5)),
+            })
+        );
+        assert!(check_str("var a = [1, 2, 3];\nlet s = a[0..3];\ns[0] = 9;").is_ok());
+    }
+
+    #[test]
+    fn closures() {
+        assert_eq!(
+            check_str(
+                "fn twice(f: fn(i64) -> i64, x: i64) -> i64 { return f(f(x)); }\nfn inc(x: i64) -> i64 { return x + 1; }\nvar n = 2;\nlet add = |x: i64| -> i64 { return x + n; };\nreturn twice(add, 1) + twice(inc, n);"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("var n = 1;\nlet f = |x: i64| { n = x; };"),
+            Err(TypeError::AssignToCaptured {
+                file: None,
+                line_number: 2,
+                column_number: 20,
+                name: String::from("n"),
+                closure: Box::new((None, 2, 9)),
+            })
+        );
+        assert_eq!(
+            check_str("let f = wrapping_add;"),
+            Err(TypeError::NotAValue {
+                file: None,
+                line_number: 1,
+                column_number: 9,
+                name: String::from("wrapping_add"),
+            })
+        );
+        assert_eq!(
+            check_str("let n = 1;\nreturn n(2);"),
+            Err(TypeError::NotCallable {
+                file: None,
+                line_number: 2,
+                column_number: 8,
+                name: String::from("n"),
+                ty: ast::Type::I64,
+            })
+        );
+    }
+
+    #[test]
+    fn slices() {
+        assert_eq!(
+            check_str(
+                "fn sum(xs: []i64) -> i64 {\nvar total = 0;\nfor i in 0..xs.len { total = total + xs[i]; }\nreturn total;\n}\nvar a = [1, 2, 3, 4];\nlet s: str = \"hello\";\nprint_str(s[1..3]);\nlet b: u8 = s[0];\nlet p = s as *u8;\nlet bytes: []u8 = p[0..s.len];\nreturn sum(a[1..]) + sum(a[..=1]) + (&a).len;"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("var a = [1, 2];\na.len = 3;"),
+            Err(TypeError::InvalidAssignTarget {
+                file: None,
+                line_number: 2,
+                column_number: 3,
+            })
+        );
+        assert_eq!(
+            check_str("let p = alloc(8) as *i64;\nlet s = p[1..];"),
+            Err(TypeError::UnboundedSlice {
+                file: None,
+                line_number: 2,
+                column_number: 10,
+            })
+        );
+        assert_eq!(
+            check_str("let x = 1;\nlet s = x[0..1];"),
+            Err(TypeError::NotSliceable {
+                file: None,
+                line_number: 2,
+                column_number: 10,
+                got: ast::Type::I64,
+            })
+        );
+    }
+
+    #[test]
+    fn undefined_variable() {
+        assert_eq!(
+            check_str("return y;"),
+            Err(TypeError::UndefinedVariable {
+                file: None,
+                line_number: 1,
+                column_number: 8,
+                name: String::from("y"),
+            })
+        );
+    }
+
+    #[test]
+    fn tuples() {
+        assert_eq!(
+            check_str(
+                "fn div(a: i64, b: i64) -> (i64, i64) { return (a / b, a - a / b * b); }\nvar (q, r) = div(7, 2);\nvar t: (i64, (f64,)) = (q + r, (1.5,));\nvar p = &t;\nreturn p.1.0 as i64 + t.0;"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("var t = (1, 2.0);\nreturn t.2;"),
+            Err(TypeError::IndexOutOfBounds {
+                file: None,
+                line_number: 2,
+                column_number: 10,
+                index: 2,
+                len: 2,
+            })
+        );
+        assert_eq!(
+            check_str("var (a, b, c) = (1, 2);"),
+            Err(TypeError::CannotDestructure {
+                file: None,
+                line_number: 1,
+                column_number: 17,
+                got: ast::Type::Tuple(vec![ast::Type::I64, ast::Type::I64]),
+                names: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn chars_and_bytes() {
+        assert_eq!(
+            check_str(
+                "fn is_digit(c: char) -> i64 { return match c { '0' => 1, '1' => 1, _ => 0 }; }\nvar b = 'A' as u8 + 1 as u8;\nvar n = match b { 65 => 1, _ => 0 };\nreturn is_digit(b as char) + ('\\n' as i64) + n;"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_str("var c = 'a' + 'b';"),
+            Err(TypeError::InvalidOperands {
+                file: None,
+                line_number: 1,
+                column_number: 13,
+                op: lexer::Token::Plus { here: 12 },
+                ty: ast::Type::Char,
+            })
+        );
+        assert_eq!(
+            check_str("var c = 97 as char;"),
+            Err(TypeError::InvalidCast {
+                file: None,
+                line_number: 1,
+                column_number: 12,
+                from: ast::Type::I64,
+                to: ast::Type::Char,
+            })
+        );
+    }
+}